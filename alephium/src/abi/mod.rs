@@ -0,0 +1,496 @@
+// contract call abi
+
+// calling a contract from a transaction means shipping a script whose one
+// method pushes the arguments and calls out:
+//
+//   approvals     caller, (token,) amount, ApproveAlph / ApproveToken
+//   arguments     one push instruction each
+//   call          #args, #returns, contract id, CallExternal(method index)
+//   cleanup       a Pop for every returned value
+//
+// a script is an AVector of methods, each
+//
+//   isPublic (bool) | modifier (byte) | #args | #locals | #returns | AVector[instr]
+//
+// where the modifier has bit 0 set to spend approved assets and bit 1 to
+// spend the contract's own. values serialize as a type prefix byte (0 bool,
+// 2 U256, 3 ByteVec, 4 Address) and the value; the node's json api spells
+// the same types out by name instead.
+//
+// only the instructions a call script needs are known here, decoding
+// anything else fails.
+
+use crate::address::Address;
+use crate::codec::{decode_i32, encode_i32, Codec, CodecError, U256};
+use crate::contract::{ContractId, TokenId};
+
+// method indices of the pool contracts crate::ralph generates, which is
+// the order they are declared in
+pub const GET_RESERVES_METHOD: u8 = 0;
+pub const GET_TOTAL_SUPPLY_METHOD: u8 = 1;
+pub const SWAP_METHOD: u8 = 2;
+pub const ADD_LIQUIDITY_METHOD: u8 = 3;
+pub const REMOVE_LIQUIDITY_METHOD: u8 = 4;
+
+const USE_PREAPPROVED_ASSETS: u8 = 0b01;
+const USE_CONTRACT_ASSETS: u8 = 0b10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val {
+    Bool(bool),
+    U256(U256),
+    ByteVec(Vec<u8>),
+    Address(Address)
+}
+
+impl Val {
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Val::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_u256(&self) -> Option<U256> {
+        match self {
+            Val::U256(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_u256()?.to_u64()
+    }
+
+    // amounts in this crate are u128
+    pub fn as_u128(&self) -> Option<u128> {
+        self.as_u256()?.to_u128()
+    }
+
+    pub fn as_byte_vec(&self) -> Option<&[u8]> {
+        match self {
+            Val::ByteVec(bytes) => Some(bytes),
+            _ => None
+        }
+    }
+
+    pub fn as_address(&self) -> Option<&Address> {
+        match self {
+            Val::Address(address) => Some(address),
+            _ => None
+        }
+    }
+
+    // the instruction that pushes this value
+    fn push(&self) -> Instr {
+        match self {
+            Val::Bool(true) => Instr::ConstTrue,
+            Val::Bool(false) => Instr::ConstFalse,
+            Val::U256(n) => Instr::U256Const(*n),
+            Val::ByteVec(bytes) => Instr::BytesConst(bytes.clone()),
+            Val::Address(address) => Instr::AddressConst(address.clone())
+        }
+    }
+}
+
+impl From<u128> for Val {
+    fn from(n: u128) -> Self {
+        Val::U256(U256::from(n))
+    }
+}
+
+impl Codec for Val {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Val::Bool(b) => {
+                out.push(0x00);
+                b.encode(out);
+            }
+            Val::U256(n) => {
+                out.push(0x02);
+                n.encode(out);
+            }
+            Val::ByteVec(bytes) => {
+                out.push(0x03);
+                bytes.encode(out);
+            }
+            Val::Address(address) => {
+                out.push(0x04);
+                address.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            0x00 => Ok(Val::Bool(bool::decode(input)?)),
+            0x02 => Ok(Val::U256(U256::decode(input)?)),
+            0x03 => Ok(Val::ByteVec(Vec::decode(input)?)),
+            0x04 => Ok(Val::Address(Address::decode(input)?)),
+            // 0x01 is I256, which nothing here returns
+            prefix => Err(CodecError::UnknownPrefix { kind: "val", prefix })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    CallExternal(u8),
+    ConstTrue,
+    ConstFalse,
+    // 0 to 5 have their own opcodes
+    U256Const(U256),
+    BytesConst(Vec<u8>),
+    AddressConst(Address),
+    Pop,
+    ApproveAlph,
+    ApproveToken
+}
+
+const CALL_EXTERNAL: u8 = 0x01;
+const CONST_TRUE: u8 = 0x03;
+const CONST_FALSE: u8 = 0x04;
+const U256_CONST_0: u8 = 0x0c;
+const U256_CONST_5: u8 = 0x11;
+const U256_CONST: u8 = 0x13;
+const BYTES_CONST: u8 = 0x14;
+const ADDRESS_CONST: u8 = 0x15;
+const POP: u8 = 0x18;
+const APPROVE_ALPH: u8 = 0xa2;
+const APPROVE_TOKEN: u8 = 0xa3;
+
+impl Codec for Instr {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Instr::CallExternal(index) => out.extend_from_slice(&[CALL_EXTERNAL, *index]),
+            Instr::ConstTrue => out.push(CONST_TRUE),
+            Instr::ConstFalse => out.push(CONST_FALSE),
+            Instr::U256Const(n) => match n.to_u64() {
+                Some(small @ 0..=5) => out.push(U256_CONST_0 + small as u8),
+                _ => {
+                    out.push(U256_CONST);
+                    n.encode(out);
+                }
+            },
+            Instr::BytesConst(bytes) => {
+                out.push(BYTES_CONST);
+                bytes.encode(out);
+            }
+            Instr::AddressConst(address) => {
+                out.push(ADDRESS_CONST);
+                address.encode(out);
+            }
+            Instr::Pop => out.push(POP),
+            Instr::ApproveAlph => out.push(APPROVE_ALPH),
+            Instr::ApproveToken => out.push(APPROVE_TOKEN)
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            CALL_EXTERNAL => Ok(Instr::CallExternal(u8::decode(input)?)),
+            CONST_TRUE => Ok(Instr::ConstTrue),
+            CONST_FALSE => Ok(Instr::ConstFalse),
+            small @ U256_CONST_0..=U256_CONST_5 => Ok(Instr::U256Const(U256::from((small - U256_CONST_0) as u128))),
+            U256_CONST => Ok(Instr::U256Const(U256::decode(input)?)),
+            BYTES_CONST => Ok(Instr::BytesConst(Vec::decode(input)?)),
+            ADDRESS_CONST => Ok(Instr::AddressConst(Address::decode(input)?)),
+            POP => Ok(Instr::Pop),
+            APPROVE_ALPH => Ok(Instr::ApproveAlph),
+            APPROVE_TOKEN => Ok(Instr::ApproveToken),
+            prefix => Err(CodecError::UnknownPrefix { kind: "instruction", prefix })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub is_public: bool,
+    pub use_preapproved_assets: bool,
+    pub use_contract_assets: bool,
+    pub args_length: i32,
+    pub locals_length: i32,
+    pub return_length: i32,
+    pub instrs: Vec<Instr>
+}
+
+impl Codec for Method {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_public.encode(out);
+        let mut modifier = 0;
+        if self.use_preapproved_assets {
+            modifier |= USE_PREAPPROVED_ASSETS;
+        }
+        if self.use_contract_assets {
+            modifier |= USE_CONTRACT_ASSETS;
+        }
+        out.push(modifier);
+        encode_i32(self.args_length, out);
+        encode_i32(self.locals_length, out);
+        encode_i32(self.return_length, out);
+        self.instrs.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let is_public = bool::decode(input)?;
+        let modifier = u8::decode(input)?;
+        if modifier & !(USE_PREAPPROVED_ASSETS | USE_CONTRACT_ASSETS) != 0 {
+            return Err(CodecError::UnknownPrefix { kind: "method modifier", prefix: modifier });
+        }
+        Ok(Method {
+            is_public,
+            use_preapproved_assets: modifier & USE_PREAPPROVED_ASSETS != 0,
+            use_contract_assets: modifier & USE_CONTRACT_ASSETS != 0,
+            args_length: decode_i32(input)?,
+            locals_length: decode_i32(input)?,
+            return_length: decode_i32(input)?,
+            instrs: Vec::decode(input)?
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub methods: Vec<Method>
+}
+
+impl Codec for Script {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.methods.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Script { methods: Vec::decode(input)? })
+    }
+}
+
+// assets the caller lets the called method spend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub token: TokenId,
+    pub amount: u128
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractCall {
+    pub contract_id: ContractId,
+    pub method_index: u8,
+    pub args: Vec<Val>,
+    pub return_length: usize,
+    // approved from `caller`
+    pub caller: Address,
+    pub approvals: Vec<Approval>
+}
+
+impl ContractCall {
+
+    pub fn new(contract_id: ContractId, method_index: u8, caller: Address) -> Self {
+        ContractCall { contract_id, method_index, args: vec![], return_length: 0, caller, approvals: vec![] }
+    }
+
+    pub fn with_args(mut self, args: Vec<Val>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_return_length(mut self, return_length: usize) -> Self {
+        self.return_length = return_length;
+        self
+    }
+
+    pub fn approve(mut self, token: TokenId, amount: u128) -> Self {
+        self.approvals.push(Approval { token, amount });
+        self
+    }
+
+    // the arguments alone, serialized the way the vm serializes any
+    // AVector[Val]. the node's call-contract endpoint takes them as json
+    // instead, see NodeClient::call_contract
+    pub fn payload(&self) -> Vec<u8> {
+        crate::codec::serialize(&self.args)
+    }
+
+    pub fn to_script(&self) -> Script {
+        let mut instrs = Vec::new();
+        for approval in &self.approvals {
+            instrs.push(Instr::AddressConst(self.caller.clone()));
+            if approval.token.is_alph() {
+                instrs.push(Instr::U256Const(U256::from(approval.amount)));
+                instrs.push(Instr::ApproveAlph);
+            } else {
+                instrs.push(Instr::BytesConst(approval.token.as_bytes().to_vec()));
+                instrs.push(Instr::U256Const(U256::from(approval.amount)));
+                instrs.push(Instr::ApproveToken);
+            }
+        }
+
+        instrs.extend(self.args.iter().map(Val::push));
+        instrs.push(Instr::U256Const(U256::from(self.args.len() as u128)));
+        instrs.push(Instr::U256Const(U256::from(self.return_length as u128)));
+        instrs.push(Instr::BytesConst(self.contract_id.as_bytes().to_vec()));
+        instrs.push(Instr::CallExternal(self.method_index));
+        // the script itself returns nothing
+        instrs.extend(std::iter::repeat_n(Instr::Pop, self.return_length));
+
+        Script {
+            methods: vec![Method {
+                is_public: true,
+                use_preapproved_assets: !self.approvals.is_empty(),
+                use_contract_assets: false,
+                args_length: 0,
+                locals_length: 0,
+                return_length: 0,
+                instrs
+            }]
+        }
+    }
+
+    pub fn to_bytecode(&self) -> Vec<u8> {
+        crate::codec::serialize(&self.to_script())
+    }
+
+    // swap `amount_in` of the pool's first token (or its second), for no
+    // less than `amount_out_min`. `tokens` is the pool's pair in its own
+    // order, as Pool::tokens gives it, so the approval always matches the
+    // side being sold
+    pub fn swap(pool: ContractId, tokens: (TokenId, TokenId), caller: Address, amount_in: u128, token_in_is_one: bool, amount_out_min: u128) -> Self {
+        let token_in = if token_in_is_one { tokens.0 } else { tokens.1 };
+        let args = vec![Val::Address(caller.clone()), Val::from(amount_in), Val::Bool(token_in_is_one), Val::from(amount_out_min)];
+        ContractCall::new(pool, SWAP_METHOD, caller)
+            .with_args(args)
+            .with_return_length(1)
+            .approve(token_in, amount_in)
+    }
+
+    pub fn add_liquidity(pool: ContractId, caller: Address, (token_one, amount_one): (TokenId, u128), (token_two, amount_two): (TokenId, u128)) -> Self {
+        let args = vec![Val::Address(caller.clone()), Val::from(amount_one), Val::from(amount_two)];
+        ContractCall::new(pool, ADD_LIQUIDITY_METHOD, caller)
+            .with_args(args)
+            .with_return_length(1)
+            .approve(token_one, amount_one)
+            .approve(token_two, amount_two)
+    }
+
+    // the pool's lp token shares its contract id
+    pub fn remove_liquidity(pool: ContractId, caller: Address, liquidity: u128) -> Self {
+        let args = vec![Val::Address(caller.clone()), Val::from(liquidity)];
+        ContractCall::new(pool, REMOVE_LIQUIDITY_METHOD, caller)
+            .with_args(args)
+            .with_return_length(2)
+            .approve(TokenId::from(pool), liquidity)
+    }
+}
+
+// values serialized as the vm serializes any AVector[Val]. the node's
+// call-contract endpoint answers in json, see NodeClient::call_contract
+pub fn decode_returns(bytes: &[u8]) -> Result<Vec<Val>, CodecError> {
+    crate::codec::deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{deserialize, serialize};
+
+    const POOL: ContractId = ContractId::new([0x11; 32]);
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+
+    fn caller() -> Address {
+        Address::P2PKH([0x22; 32])
+    }
+
+    fn fixture(parts: &[&str]) -> Vec<u8> {
+        hex::decode(parts.concat()).unwrap()
+    }
+
+    #[test]
+    fn test_swap_bytecode() {
+        let call = ContractCall::swap(POOL, (TokenId::ALPH, USDT), caller(), 1_000, false, 900);
+        let caller_hex = format!("1500{}", "22".repeat(32));
+        let expected = fixture(&[
+            // one public method using approved assets, no args, locals or returns, 13 instrs
+            "01", "01", "01", "00", "00", "00", "0d",
+            // approve 1000 USDT from the caller
+            &caller_hex, &format!("144020{}", "17".repeat(32)), "1343e8", "a3",
+            // swap(caller, 1000, false, 900)
+            &caller_hex, "1343e8", "04", "134384",
+            // 4 args, 1 return, pool id, call method 2, drop the result
+            "10", "0d", &format!("144020{}", "11".repeat(32)), "0102", "18",
+        ]);
+        assert_eq!(call.to_bytecode(), expected);
+        assert_eq!(deserialize::<Script>(&expected).unwrap(), call.to_script());
+
+        // selling the other side approves the other token
+        let call = ContractCall::swap(POOL, (TokenId::ALPH, USDT), caller(), 1_000, true, 0);
+        assert_eq!(call.approvals, vec![Approval { token: TokenId::ALPH, amount: 1_000 }]);
+    }
+
+    #[test]
+    fn test_alph_approval() {
+        let call = ContractCall::add_liquidity(POOL, caller(), (TokenId::ALPH, 5), (USDT, 64));
+        let instrs = &call.to_script().methods[0].instrs;
+        assert_eq!(instrs[..3], [Instr::AddressConst(caller()), Instr::U256Const(U256::from(5)), Instr::ApproveAlph]);
+
+        let bytes = serialize(&instrs[1]);
+        assert_eq!(bytes, [0x11]);
+        assert_eq!(serialize(&Instr::U256Const(U256::from(64))), [0x13, 0x40, 0x40]);
+    }
+
+    #[test]
+    fn test_remove_liquidity() {
+        let call = ContractCall::remove_liquidity(POOL, caller(), 10);
+        assert_eq!(call.approvals, vec![Approval { token: TokenId::from(POOL), amount: 10 }]);
+        let instrs = &call.to_script().methods[0].instrs;
+        assert_eq!(instrs[instrs.len() - 3..], [Instr::CallExternal(REMOVE_LIQUIDITY_METHOD), Instr::Pop, Instr::Pop]);
+    }
+
+    #[test]
+    fn test_payload() {
+        let call = ContractCall::new(POOL, GET_RESERVES_METHOD, caller()).with_args(vec![
+            Val::Bool(true),
+            Val::from(1_000_000),
+            Val::ByteVec(vec![0xab, 0xcd]),
+            Val::Address(caller()),
+        ]);
+        let expected = fixture(&["04", "0001", "0280", "0f4240", "0302abcd", &format!("0400{}", "22".repeat(32))]);
+        assert_eq!(call.payload(), expected);
+        // no approvals, so the script doesn't ask for assets
+        assert!(!call.to_script().methods[0].use_preapproved_assets);
+    }
+
+    #[test]
+    fn test_decode_returns() {
+        // getReserves() -> (U256, U256)
+        let returned = fixture(&["02", "02c40de0b6b3a7640000", "02bfffffff"]);
+        let values = decode_returns(&returned).unwrap();
+        assert_eq!(values.iter().map(Val::as_u64).collect::<Vec<_>>(), vec![Some(1_000_000_000_000_000_000), Some(0x3fff_ffff)]);
+        assert_eq!(values[0].as_bool(), None);
+
+        assert_eq!(decode_returns(&fixture(&["01", "0101"])), Err(CodecError::UnknownPrefix { kind: "val", prefix: 1 }));
+        assert_eq!(decode_returns(&fixture(&["01", "0001", "00"])), Err(CodecError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn test_unknown_instructions() {
+        assert_eq!(deserialize::<Instr>(&[0x02]), Err(CodecError::UnknownPrefix { kind: "instruction", prefix: 2 }));
+        assert_eq!(
+            deserialize::<Method>(&[0x01, 0x04, 0, 0, 0, 0]),
+            Err(CodecError::UnknownPrefix { kind: "method modifier", prefix: 4 })
+        );
+    }
+
+    #[test]
+    fn test_method_indices_match_generated_contracts() {
+        // a method's index is its place among every fn the contract declares
+        for contract in [include_str!("../ralph/golden/constant_product.ral"), include_str!("../ralph/golden/stable_swap.ral")] {
+            let declared: Vec<&str> = contract.split("fn ").skip(1).map(|rest| &rest[..rest.find('(').unwrap()]).collect();
+            let index = |name: &str| declared.iter().position(|fn_name| *fn_name == name).unwrap() as u8;
+            assert_eq!(index("getReserves"), GET_RESERVES_METHOD);
+            assert_eq!(index("getTotalSupply"), GET_TOTAL_SUPPLY_METHOD);
+            assert_eq!(index("swap"), SWAP_METHOD);
+            assert_eq!(index("addLiquidity"), ADD_LIQUIDITY_METHOD);
+            assert_eq!(index("removeLiquidity"), REMOVE_LIQUIDITY_METHOD);
+        }
+    }
+}
@@ -1,18 +1,273 @@
 // alephium account logic
 
+#[allow(clippy::module_inception)]
 pub mod account {
 
+    use std::collections::BTreeMap;
+
+    use crate::address::Address;
+    use crate::contract::{ContractId, TokenId};
+    use crate::group::{GroupConfig, GroupError};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum AccountError {
+        InsufficientBalance { token: TokenId, available: u128, requested: u128 },
+        Overflow(TokenId),
+        // a token the account has no metadata for
+        UnknownToken(TokenId)
+    }
+
+    // what a token is; how much of it anyone holds lives in `Account`
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Token {
-        address: String,    // how do I derive this?
-        name: String,
-        symbol: String,
-        decimals: u64,
-        supply: u64
+        pub(crate) id: TokenId,
+        pub(crate) address: Address,    // the token contract's P2C address
+        pub(crate) name: String,
+        pub(crate) symbol: String,
+        pub(crate) decimals: u64,
+        pub(crate) supply: u128     // total issued, not anyone's balance
     }
 
+    impl Token {
+
+        pub fn new(id: TokenId, name: String, symbol: String, decimals: u64, supply: u128) -> Self {
+            Token { id, address: Address::contract(id.contract_id()), name, symbol, decimals, supply }
+        }
+
+        // a token's id is the id of the contract that issued it
+        pub fn from_contract_id(contract_id: ContractId, name: String, symbol: String, decimals: u64, supply: u128) -> Self {
+            Token::new(TokenId::from(contract_id), name, symbol, decimals, supply)
+        }
+
+        pub fn id(&self) -> TokenId {
+            self.id
+        }
+
+        pub fn address(&self) -> &Address {
+            &self.address
+        }
+
+        pub fn symbol(&self) -> &str {
+            &self.symbol
+        }
+
+        pub fn decimals(&self) -> u64 {
+            self.decimals
+        }
+
+        pub fn supply(&self) -> u128 {
+            self.supply
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Account {
-        alph_amount: u64,
-        tokens: Vec<Token>
+        pub(crate) address: Option<Address>,
+        pub(crate) alph_amount: u128,
+        pub(crate) tokens: Vec<Token>,             // metadata for every token the account knows
+        pub(crate) balances: BTreeMap<TokenId, u128>  // non-zero token balances, never ALPH
     }
 
-}
\ No newline at end of file
+    impl Account {
+
+        pub fn new(alph_amount: u128, tokens: Vec<Token>) -> Self {
+            Account { address: None, alph_amount, tokens, balances: BTreeMap::new() }
+        }
+
+        pub fn with_address(mut self, address: Address) -> Self {
+            self.address = Some(address);
+            self
+        }
+
+        pub fn address(&self) -> Option<&Address> {
+            self.address.as_ref()
+        }
+
+        // None until the account has an address, or when the address has no
+        // group (an empty multisig)
+        pub fn group(&self, config: &GroupConfig) -> Option<u32> {
+            self.address.as_ref().and_then(|address| config.group_of(address).ok())
+        }
+
+        // give the account a fresh P2PKH address in `group`, drawing keys from
+        // `next_public_key`, and hand back the public key that owns it
+        pub fn generate_address_in_group<F>(&mut self, config: &GroupConfig, group: u32, next_public_key: F) -> Result<Vec<u8>, GroupError>
+        where
+            F: FnMut() -> Vec<u8>
+        {
+            let (public_key, address) = config.p2pkh_in_group(group, next_public_key)?;
+            self.address = Some(address);
+            Ok(public_key)
+        }
+
+        pub fn token(&self, id: &TokenId) -> Option<&Token> {
+            self.tokens.iter().find(|token| token.id == *id)
+        }
+
+        // learn about a token, replacing whatever metadata was there
+        pub fn add_token(&mut self, token: Token) {
+            match self.tokens.iter_mut().find(|t| t.id == token.id) {
+                Some(existing) => *existing = token,
+                None => self.tokens.push(token)
+            }
+        }
+
+        fn knows(&self, id: &TokenId) -> bool {
+            id.is_alph() || self.token(id).is_some()
+        }
+
+        pub fn alph_balance(&self) -> u128 {
+            self.alph_amount
+        }
+
+        // ALPH or any token, zero if the account holds none
+        pub fn balance(&self, id: &TokenId) -> u128 {
+            if id.is_alph() {
+                self.alph_amount
+            } else {
+                self.balances.get(id).copied().unwrap_or(0)
+            }
+        }
+
+        pub fn balances(&self) -> impl Iterator<Item = (&TokenId, u128)> {
+            self.balances.iter().map(|(id, amount)| (id, *amount))
+        }
+
+        fn set_balance(&mut self, id: &TokenId, amount: u128) {
+            if id.is_alph() {
+                self.alph_amount = amount;
+            } else if amount == 0 {
+                self.balances.remove(id);
+            } else {
+                self.balances.insert(*id, amount);
+            }
+        }
+
+        fn credited(&self, id: &TokenId, amount: u128) -> Result<u128, AccountError> {
+            if !self.knows(id) {
+                return Err(AccountError::UnknownToken(*id));
+            }
+            self.balance(id).checked_add(amount).ok_or(AccountError::Overflow(*id))
+        }
+
+        fn debited(&self, id: &TokenId, amount: u128) -> Result<u128, AccountError> {
+            let available = self.balance(id);
+            available.checked_sub(amount).ok_or(AccountError::InsufficientBalance { token: *id, available, requested: amount })
+        }
+
+        pub fn deposit(&mut self, id: &TokenId, amount: u128) -> Result<(), AccountError> {
+            let balance = self.credited(id, amount)?;
+            self.set_balance(id, balance);
+            Ok(())
+        }
+
+        pub fn withdraw(&mut self, id: &TokenId, amount: u128) -> Result<(), AccountError> {
+            let balance = self.debited(id, amount)?;
+            self.set_balance(id, balance);
+            Ok(())
+        }
+
+        // move `amount` to `to`, who learns the token's metadata if it has to.
+        // either both balances change or neither does
+        pub fn transfer(&mut self, to: &mut Account, id: &TokenId, amount: u128) -> Result<(), AccountError> {
+            let from_balance = self.debited(id, amount)?;
+            if !to.knows(id) {
+                // we got this far, so we hold it and know what it is
+                to.add_token(self.token(id).ok_or(AccountError::UnknownToken(*id))?.clone());
+            }
+            let to_balance = to.credited(id, amount)?;
+
+            self.set_balance(id, from_balance);
+            to.set_balance(id, to_balance);
+            Ok(())
+        }
+    }
+
+}
+
+pub use self::account::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::TokenId;
+
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const USDC: TokenId = TokenId::new([0x1c; 32]);
+
+    fn create_test_token(id: TokenId, symbol: &str) -> Token {
+        Token::new(id, symbol.to_string(), symbol.to_string(), 6, 1_000_000_000)
+    }
+
+    fn create_test_account() -> Account {
+        Account::new(1_000, vec![create_test_token(USDT, "USDT")])
+    }
+
+    #[test]
+    fn test_deposit_and_withdraw() {
+        let mut account = create_test_account();
+        assert_eq!(account.balance(&USDT), 0);
+
+        account.deposit(&USDT, 500).unwrap();
+        account.withdraw(&USDT, 200).unwrap();
+        assert_eq!(account.balance(&USDT), 300);
+
+        account.deposit(&TokenId::ALPH, 50).unwrap();
+        account.withdraw(&TokenId::ALPH, 1_050).unwrap();
+        assert_eq!(account.alph_balance(), 0);
+
+        // metadata is untouched by balance changes
+        assert_eq!(account.token(&USDT).unwrap().supply(), 1_000_000_000);
+    }
+
+    #[test]
+    fn test_balance_errors() {
+        let mut account = create_test_account();
+        account.deposit(&USDT, 10).unwrap();
+
+        assert_eq!(
+            account.withdraw(&USDT, 11),
+            Err(AccountError::InsufficientBalance { token: USDT, available: 10, requested: 11 })
+        );
+        assert_eq!(account.deposit(&USDT, u128::MAX), Err(AccountError::Overflow(USDT)));
+        assert_eq!(account.deposit(&USDC, 1), Err(AccountError::UnknownToken(USDC)));
+        assert_eq!(account.balance(&USDT), 10);
+    }
+
+    #[test]
+    fn test_empty_balances_are_dropped() {
+        let mut account = create_test_account();
+        account.deposit(&USDT, 10).unwrap();
+        account.withdraw(&USDT, 10).unwrap();
+        assert_eq!(account.balances().count(), 0);
+        assert_eq!(account, create_test_account());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut alice = create_test_account();
+        let mut bob = Account::new(0, vec![]);
+        alice.deposit(&USDT, 100).unwrap();
+
+        alice.transfer(&mut bob, &USDT, 40).unwrap();
+        assert_eq!(alice.balance(&USDT), 60);
+        assert_eq!(bob.balance(&USDT), 40);
+        assert_eq!(bob.token(&USDT), alice.token(&USDT));
+
+        alice.transfer(&mut bob, &TokenId::ALPH, 1_000).unwrap();
+        assert_eq!((alice.alph_balance(), bob.alph_balance()), (0, 1_000));
+    }
+
+    #[test]
+    fn test_failed_transfer_changes_nothing() {
+        let mut alice = create_test_account();
+        let mut bob = create_test_account();
+        alice.deposit(&USDT, 100).unwrap();
+        bob.deposit(&USDT, u128::MAX - 10).unwrap();
+        let (alice_before, bob_before) = (alice.clone(), bob.clone());
+
+        assert_eq!(alice.transfer(&mut bob, &USDT, 50), Err(AccountError::Overflow(USDT)));
+        assert!(matches!(alice.transfer(&mut bob, &USDT, 500), Err(AccountError::InsufficientBalance { .. })));
+        assert_eq!((alice, bob), (alice_before, bob_before));
+    }
+}
@@ -0,0 +1,331 @@
+// alephium addresses
+
+// an address is the base58 encoding of a serialized lockup script:
+//
+//   P2PKH   0x00 | blake2b(public key)
+//   P2MPKH  0x01 | n | blake2b(key 1) .. blake2b(key n) | m
+//   P2SH    0x02 | blake2b(script)
+//   P2C     0x03 | contract id
+//
+// n and m are compact signed integers, same as everywhere else on chain.
+
+use std::fmt;
+use std::str::FromStr;
+
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::contract::ContractId;
+
+pub const HASH_LENGTH: usize = 32;
+
+const P2PKH_PREFIX: u8 = 0x00;
+const P2MPKH_PREFIX: u8 = 0x01;
+const P2SH_PREFIX: u8 = 0x02;
+const P2C_PREFIX: u8 = 0x03;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressError {
+    InvalidBase58,
+    Empty,
+    UnknownType(u8),
+    InvalidLength,
+    // a compact integer that is negative or too wide for a key count
+    InvalidCompact,
+    // m of n with m == 0, m > n or no keys at all
+    InvalidMultisig
+}
+
+pub type Hash = [u8; HASH_LENGTH];
+
+pub fn blake2b(data: &[u8]) -> Hash {
+    Blake2b::<U32>::digest(data).into()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Address {
+    P2PKH(Hash),
+    P2MPKH { public_key_hashes: Vec<Hash>, m: usize },
+    P2SH(Hash),
+    P2C(ContractId)
+}
+
+impl Address {
+
+    // pay to a single (compressed secp256k1) public key
+    pub fn p2pkh(public_key: &[u8]) -> Self {
+        Address::P2PKH(blake2b(public_key))
+    }
+
+    // any m of the given public keys
+    pub fn p2mpkh(public_keys: &[&[u8]], m: usize) -> Result<Self, AddressError> {
+        if m == 0 || m > public_keys.len() {
+            return Err(AddressError::InvalidMultisig);
+        }
+        let public_key_hashes = public_keys.iter().map(|key| blake2b(key)).collect();
+        Ok(Address::P2MPKH { public_key_hashes, m })
+    }
+
+    // pay to whoever can satisfy the serialized script
+    pub fn p2sh(script: &[u8]) -> Self {
+        Address::P2SH(blake2b(script))
+    }
+
+    pub fn contract(contract_id: ContractId) -> Self {
+        Address::P2C(contract_id)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Address::P2PKH(hash) => [&[P2PKH_PREFIX], &hash[..]].concat(),
+            Address::P2MPKH { public_key_hashes, m } => {
+                let mut bytes = vec![P2MPKH_PREFIX];
+                encode_compact(public_key_hashes.len(), &mut bytes);
+                for hash in public_key_hashes {
+                    bytes.extend_from_slice(hash);
+                }
+                encode_compact(*m, &mut bytes);
+                bytes
+            }
+            Address::P2SH(hash) => [&[P2SH_PREFIX], &hash[..]].concat(),
+            Address::P2C(id) => [&[P2C_PREFIX], &id.as_bytes()[..]].concat()
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AddressError> {
+        let (prefix, body) = bytes.split_first().ok_or(AddressError::Empty)?;
+
+        match *prefix {
+            P2PKH_PREFIX => Ok(Address::P2PKH(hash(body)?)),
+            P2SH_PREFIX => Ok(Address::P2SH(hash(body)?)),
+            P2C_PREFIX => Ok(Address::P2C(ContractId::new(hash(body)?))),
+            P2MPKH_PREFIX => {
+                let (n, mut rest) = decode_compact(body)?;
+                if rest.len() < n * HASH_LENGTH {
+                    return Err(AddressError::InvalidLength);
+                }
+                let public_key_hashes = (0..n)
+                    .map(|_| {
+                        let (hash_bytes, tail) = rest.split_at(HASH_LENGTH);
+                        rest = tail;
+                        hash(hash_bytes)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let (m, rest) = decode_compact(rest)?;
+                if !rest.is_empty() {
+                    return Err(AddressError::InvalidLength);
+                }
+                if m == 0 || m > n {
+                    return Err(AddressError::InvalidMultisig);
+                }
+                Ok(Address::P2MPKH { public_key_hashes, m })
+            }
+            other => Err(AddressError::UnknownType(other))
+        }
+    }
+}
+
+fn hash(bytes: &[u8]) -> Result<Hash, AddressError> {
+    bytes.try_into().map_err(|_| AddressError::InvalidLength)
+}
+
+// compact signed integers, non-negative values only: one byte below 0x20,
+// two bytes (0b01 prefix) below 0x2000, four bytes (0b10 prefix) below 0x2000_0000
+fn encode_compact(n: usize, bytes: &mut Vec<u8>) {
+    if n < 0x20 {
+        bytes.push(n as u8);
+    } else if n < 0x2000 {
+        bytes.extend_from_slice(&[0x40 | (n >> 8) as u8, n as u8]);
+    } else {
+        let n = n as u32;
+        bytes.extend_from_slice(&[0x80 | (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+    }
+}
+
+fn decode_compact(bytes: &[u8]) -> Result<(usize, &[u8]), AddressError> {
+    let first = *bytes.first().ok_or(AddressError::InvalidLength)?;
+    let length = match first >> 6 {
+        0b00 => 1,
+        0b01 => 2,
+        0b10 => 4,
+        _ => return Err(AddressError::InvalidCompact)
+    };
+    if bytes.len() < length {
+        return Err(AddressError::InvalidLength);
+    }
+
+    // the sign bit is the top bit of the value, and we only ever write >= 0
+    if first & 0x20 != 0 {
+        return Err(AddressError::InvalidCompact);
+    }
+    let n = bytes[1..length].iter().fold((first & 0x3f) as usize, |n, b| (n << 8) | *b as usize);
+    Ok((n, &bytes[length..]))
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bs58::encode(self.to_bytes()).into_string())
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(AddressError::Empty);
+        }
+        let bytes = bs58::decode(s).into_vec().map_err(|_| AddressError::InvalidBase58)?;
+        Address::from_bytes(&bytes)
+    }
+}
+
+// base58 in json and other human readable formats, lockup script bytes otherwise
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let parsed = if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?.parse()
+        } else {
+            Address::from_bytes(&Vec::<u8>::deserialize(deserializer)?)
+        };
+        parsed.map_err(|e| serde::de::Error::custom(format!("invalid address: {:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // compressed secp256k1 public keys
+    const KEY_ONE: [u8; 33] = [2; 33];
+    const KEY_TWO: [u8; 33] = [3; 33];
+
+    #[test]
+    fn test_blake2b_known_vector() {
+        assert_eq!(
+            blake2b(b""),
+            [
+                0x0e, 0x57, 0x51, 0xc0, 0x26, 0xe5, 0x43, 0xb2, 0xe8, 0xab, 0x2e, 0xb0, 0x60, 0x99, 0xda, 0xa1,
+                0xd1, 0xe5, 0xdf, 0x47, 0x77, 0x8f, 0x77, 0x87, 0xfa, 0xab, 0x45, 0xcd, 0xf1, 0x2f, 0xe3, 0xa8,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_p2pkh_round_trip() {
+        let address = Address::p2pkh(&KEY_ONE);
+        assert_eq!(address, Address::P2PKH(blake2b(&KEY_ONE)));
+
+        let encoded = address.to_string();
+        // a leading zero byte is a leading '1' in base58
+        assert!(encoded.starts_with('1'));
+        assert_eq!(encoded.parse::<Address>().unwrap(), address);
+    }
+
+    #[test]
+    fn test_p2mpkh_round_trip() {
+        let address = Address::p2mpkh(&[&KEY_ONE, &KEY_TWO], 2).unwrap();
+        let bytes = address.to_bytes();
+        assert_eq!(bytes.len(), 1 + 1 + 2 * HASH_LENGTH + 1);
+        assert_eq!(bytes[1], 2);
+        assert_eq!(*bytes.last().unwrap(), 2);
+        assert_eq!(address.to_string().parse::<Address>().unwrap(), address);
+    }
+
+    #[test]
+    fn test_p2mpkh_rejects_bad_threshold() {
+        assert_eq!(Address::p2mpkh(&[&KEY_ONE], 0), Err(AddressError::InvalidMultisig));
+        assert_eq!(Address::p2mpkh(&[&KEY_ONE], 2), Err(AddressError::InvalidMultisig));
+
+        let mut bytes = Address::p2mpkh(&[&KEY_ONE, &KEY_TWO], 1).unwrap().to_bytes();
+        *bytes.last_mut().unwrap() = 3;
+        assert_eq!(Address::from_bytes(&bytes), Err(AddressError::InvalidMultisig));
+    }
+
+    #[test]
+    fn test_compact_integers() {
+        for n in [0, 1, 0x1f, 0x20, 0x1fff, 0x2000, 0x1fff_ffff] {
+            let mut bytes = Vec::new();
+            encode_compact(n, &mut bytes);
+            let (decoded, rest) = decode_compact(&bytes).unwrap();
+            assert_eq!(decoded, n);
+            assert!(rest.is_empty());
+        }
+
+        let mut bytes = Vec::new();
+        encode_compact(0x20, &mut bytes);
+        assert_eq!(bytes, vec![0x40, 0x20]);
+
+        // negative, and the multi-byte form
+        assert_eq!(decode_compact(&[0x3f]), Err(AddressError::InvalidCompact));
+        assert_eq!(decode_compact(&[0x60, 0x00]), Err(AddressError::InvalidCompact));
+        assert_eq!(decode_compact(&[0xc0, 0, 0, 0, 0]), Err(AddressError::InvalidCompact));
+        assert_eq!(decode_compact(&[0x40]), Err(AddressError::InvalidLength));
+
+        let mut bytes = Address::p2mpkh(&[&KEY_ONE, &KEY_TWO], 1).unwrap().to_bytes();
+        bytes[1] = 0x3e;
+        assert_eq!(Address::from_bytes(&bytes), Err(AddressError::InvalidCompact));
+    }
+
+    #[test]
+    fn test_known_addresses() {
+        // the alephium-web3 test wallet; addresses don't encode the network,
+        // so this is the same address on mainnet
+        let public_key = hex::decode("0381818e63bd9e35a5489b52a430accefc608fd60aa2c7c0d1b393b5239aedf6b0").unwrap();
+        let address = Address::p2pkh(&public_key);
+        assert_eq!(address.to_string(), "1DrDyTr9RpRsQnDnXo2YRiPzPW4ooHX5LLoqXrqfMrpQH");
+        assert_eq!("1DrDyTr9RpRsQnDnXo2YRiPzPW4ooHX5LLoqXrqfMrpQH".parse::<Address>().unwrap(), address);
+
+        // the ALPH token's contract, id zero
+        let alph = Address::contract(ContractId::new([0; 32]));
+        assert_eq!(alph.to_string(), "tgx7VNFoP9DJiFMFgXXtafQZkUvyEdDHT9ryamHJYrjq");
+    }
+
+    #[test]
+    fn test_p2sh_and_contract_addresses() {
+        let script = Address::p2sh(b"some script");
+        assert_eq!(script.to_string().parse::<Address>().unwrap(), script);
+
+        let contract = Address::contract(ContractId::new([7; 32]));
+        assert_eq!(contract.to_bytes()[0], 0x03);
+        assert_eq!(contract.to_string().parse::<Address>().unwrap(), contract);
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert_eq!("".parse::<Address>(), Err(AddressError::Empty));
+        // 0, O, I and l aren't in the base58 alphabet
+        assert_eq!("0OIl".parse::<Address>(), Err(AddressError::InvalidBase58));
+
+        let mut bytes = Address::p2pkh(&KEY_ONE).to_bytes();
+        bytes.pop();
+        assert_eq!(Address::from_bytes(&bytes), Err(AddressError::InvalidLength));
+
+        bytes[0] = 0x09;
+        assert_eq!(Address::from_bytes(&bytes), Err(AddressError::UnknownType(0x09)));
+    }
+
+    #[test]
+    fn test_serde() {
+        let address = Address::p2mpkh(&[&KEY_ONE, &KEY_TWO], 1).unwrap();
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, format!("\"{}\"", address));
+        assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), address);
+        assert!(serde_json::from_str::<Address>("\"0OIl\"").is_err());
+
+        let bytes = postcard::to_allocvec(&address).unwrap();
+        assert_eq!(postcard::from_bytes::<Address>(&bytes).unwrap(), address);
+    }
+}
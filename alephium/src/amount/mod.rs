@@ -0,0 +1,337 @@
+// token amounts
+
+// on chain every amount is an integer count of the token's smallest unit.
+// people write "1.5 ALPH", which with 18 decimals is 1_500_000_000_000_000_000
+// of those. an `Amount` keeps the integer together with the token and its
+// decimals so the two views can't drift apart, and so amounts of different
+// tokens can't be added by accident.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::account::Token;
+use crate::contract::TokenId;
+
+pub const ALPH_SYMBOL: &str = "ALPH";
+pub const ALPH_DECIMALS: u64 = 18;
+
+// 10^38 is the largest power of ten a u128 holds
+pub const MAX_DECIMALS: u64 = 38;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmountError {
+    InvalidNumber(String),
+    InvalidDecimals(u64),
+    // more fractional digits than the token has
+    TooPrecise { decimals: u64 },
+    // "1.5 USDT" parsed as an ALPH amount
+    WrongSymbol(String),
+    TokenMismatch { left: TokenId, right: TokenId },
+    Overflow,
+    Underflow,
+    DivisionByZero
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Amount {
+    token: TokenId,
+    decimals: u64,
+    raw: u128
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FormatOptions {
+    // fractional digits to show, rounding half up. None shows every
+    // significant digit and no trailing zeros
+    pub precision: Option<u64>,
+    // between groups of three integer digits
+    pub separator: Option<char>,
+    // appended after a space
+    pub symbol: Option<String>
+}
+
+fn scale(decimals: u64) -> Result<u128, AmountError> {
+    if decimals > MAX_DECIMALS {
+        return Err(AmountError::InvalidDecimals(decimals));
+    }
+    Ok(10u128.pow(decimals as u32))
+}
+
+fn group_digits(digits: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+impl Amount {
+
+    pub fn new(token: TokenId, decimals: u64, raw: u128) -> Result<Self, AmountError> {
+        scale(decimals)?;
+        Ok(Amount { token, decimals, raw })
+    }
+
+    pub fn zero(token: TokenId, decimals: u64) -> Result<Self, AmountError> {
+        Amount::new(token, decimals, 0)
+    }
+
+    // `raw` attoALPH
+    pub fn alph(raw: u128) -> Self {
+        Amount { token: TokenId::ALPH, decimals: ALPH_DECIMALS, raw }
+    }
+
+    pub fn token(&self) -> TokenId {
+        self.token
+    }
+
+    pub fn decimals(&self) -> u64 {
+        self.decimals
+    }
+
+    // in the token's smallest unit
+    pub fn raw(&self) -> u128 {
+        self.raw
+    }
+
+    // "1.5", "1.5 USDT" or "1,000.25" for `token`. exact: no floats involved
+    pub fn parse(s: &str, token: &Token) -> Result<Self, AmountError> {
+        Amount::parse_as(s, token.id(), token.symbol(), token.decimals())
+    }
+
+    pub fn parse_alph(s: &str) -> Result<Self, AmountError> {
+        Amount::parse_as(s, TokenId::ALPH, ALPH_SYMBOL, ALPH_DECIMALS)
+    }
+
+    fn parse_as(s: &str, token: TokenId, symbol: &str, decimals: u64) -> Result<Self, AmountError> {
+        let invalid = || AmountError::InvalidNumber(s.to_string());
+        let scale = scale(decimals)?;
+
+        let mut parts = s.split_whitespace();
+        let number = parts.next().ok_or_else(invalid)?;
+        match (parts.next(), parts.next()) {
+            (None, _) => {}
+            (Some(given), None) if given.eq_ignore_ascii_case(symbol) => {}
+            (Some(given), None) => return Err(AmountError::WrongSymbol(given.to_string())),
+            _ => return Err(invalid())
+        }
+
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let whole: String = whole.chars().filter(|c| *c != ',' && *c != '_').collect();
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() as u64 > decimals {
+            return Err(AmountError::TooPrecise { decimals });
+        }
+
+        let whole: u128 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| AmountError::Overflow)? };
+        let fraction: u128 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<u128>().map_err(|_| invalid())? * 10u128.pow((decimals - fraction.len() as u64) as u32)
+        };
+
+        let raw = whole.checked_mul(scale)
+            .and_then(|raw| raw.checked_add(fraction))
+            .ok_or(AmountError::Overflow)?;
+        Ok(Amount { token, decimals, raw })
+    }
+
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let precision = options.precision.unwrap_or(self.decimals).min(self.decimals);
+
+        // round half up to `precision` digits. counting in steps of the last
+        // digit shown, so the carry can't overflow even next to u128::MAX
+        let step = 10u128.pow((self.decimals - precision) as u32);
+        let (quotient, remainder) = (self.raw / step, self.raw % step);
+        let rounded = if remainder >= step - remainder { quotient + 1 } else { quotient };
+
+        let shown = 10u128.pow(precision as u32);
+        let whole = (rounded / shown).to_string();
+        let mut fraction = if precision == 0 { String::new() } else { format!("{:0width$}", rounded % shown, width = precision as usize) };
+        if options.precision.is_none() {
+            fraction.truncate(fraction.trim_end_matches('0').len());
+        }
+
+        let mut formatted = match options.separator {
+            Some(separator) => group_digits(&whole, separator),
+            None => whole
+        };
+        if !fraction.is_empty() {
+            formatted.push('.');
+            formatted.push_str(&fraction);
+        }
+        if let Some(symbol) = &options.symbol {
+            formatted.push(' ');
+            formatted.push_str(symbol);
+        }
+        formatted
+    }
+
+    fn same_token(&self, other: &Amount) -> Result<(), AmountError> {
+        if self.token != other.token || self.decimals != other.decimals {
+            return Err(AmountError::TokenMismatch { left: self.token, right: other.token });
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Amount) -> Result<Amount, AmountError> {
+        self.same_token(other)?;
+        let raw = self.raw.checked_add(other.raw).ok_or(AmountError::Overflow)?;
+        Ok(Amount { raw, ..*self })
+    }
+
+    pub fn checked_sub(&self, other: &Amount) -> Result<Amount, AmountError> {
+        self.same_token(other)?;
+        let raw = self.raw.checked_sub(other.raw).ok_or(AmountError::Underflow)?;
+        Ok(Amount { raw, ..*self })
+    }
+
+    pub fn checked_mul(&self, factor: u128) -> Result<Amount, AmountError> {
+        let raw = self.raw.checked_mul(factor).ok_or(AmountError::Overflow)?;
+        Ok(Amount { raw, ..*self })
+    }
+
+    // rounds down, like every division on chain
+    pub fn checked_div(&self, divisor: u128) -> Result<Amount, AmountError> {
+        let raw = self.raw.checked_div(divisor).ok_or(AmountError::DivisionByZero)?;
+        Ok(Amount { raw, ..*self })
+    }
+}
+
+// amounts of different tokens have no order
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.same_token(other).ok().map(|_| self.raw.cmp(&other.raw))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(&FormatOptions::default()))
+    }
+}
+
+impl Token {
+
+    // `raw` of the token's smallest unit
+    pub fn amount(&self, raw: u128) -> Result<Amount, AmountError> {
+        Amount::new(self.id(), self.decimals(), raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+
+    fn create_test_token() -> Token {
+        Token::new(USDT, "Tether USD".to_string(), "USDT".to_string(), 6, 1_000_000_000)
+    }
+
+    #[test]
+    fn test_parse_exact() {
+        assert_eq!(Amount::parse_alph("1.5 ALPH").unwrap().raw(), 1_500_000_000_000_000_000);
+        assert_eq!(Amount::parse_alph("0.000000000000000001").unwrap().raw(), 1);
+        assert_eq!(Amount::parse_alph(".25").unwrap().raw(), 250_000_000_000_000_000);
+
+        let usdt = create_test_token();
+        assert_eq!(Amount::parse("0.000001", &usdt).unwrap().raw(), 1);
+        assert_eq!(Amount::parse("1,234.5 usdt", &usdt).unwrap().raw(), 1_234_500_000);
+        // trailing zeros past the token's precision are still exact
+        assert_eq!(Amount::parse("2.5000000000", &usdt).unwrap().raw(), 2_500_000);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let usdt = create_test_token();
+        assert_eq!(Amount::parse("0.0000001", &usdt), Err(AmountError::TooPrecise { decimals: 6 }));
+        assert_eq!(Amount::parse("1.5 ALPH", &usdt), Err(AmountError::WrongSymbol("ALPH".to_string())));
+        // past u128::MAX attoALPH, ~3.4e20 ALPH
+        assert_eq!(Amount::parse_alph("340282366920938463464 ALPH"), Err(AmountError::Overflow));
+
+        for bad in ["", ".", "1.2.3", "-1", "1e6", "abc", "1 USDT extra"] {
+            assert_eq!(Amount::parse(bad, &usdt), Err(AmountError::InvalidNumber(bad.to_string())), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_large_amounts() {
+        // well past what a u64 of attoALPH holds
+        let amount = Amount::parse_alph("1,000,000.5 ALPH").unwrap();
+        assert_eq!(amount.raw(), 1_000_000_500_000_000_000_000_000);
+        assert_eq!(amount.to_string(), "1000000.5");
+        assert_eq!(Amount::parse_alph("19 ALPH").unwrap().raw(), 19_000_000_000_000_000_000);
+
+        // rounding up at the very top doesn't overflow
+        let max = Amount::alph(u128::MAX);
+        assert_eq!(max.to_string(), "340282366920938463463.374607431768211455");
+        assert_eq!(max.format(&FormatOptions { precision: Some(0), ..Default::default() }), "340282366920938463463");
+        assert_eq!(max.format(&FormatOptions { precision: Some(1), ..Default::default() }), "340282366920938463463.4");
+    }
+
+    #[test]
+    fn test_format() {
+        let usdt = create_test_token();
+        let amount = Amount::parse("1234567.891", &usdt).unwrap();
+        assert_eq!(amount.to_string(), "1234567.891");
+        assert_eq!(amount.format(&FormatOptions { precision: Some(2), ..Default::default() }), "1234567.89");
+        assert_eq!(amount.format(&FormatOptions { precision: Some(4), ..Default::default() }), "1234567.8910");
+        assert_eq!(
+            amount.format(&FormatOptions { precision: Some(0), separator: Some(','), symbol: Some("USDT".to_string()) }),
+            "1,234,568 USDT"
+        );
+        assert_eq!(Amount::parse("0.999999", &usdt).unwrap().format(&FormatOptions { precision: Some(2), ..Default::default() }), "1.00");
+        assert_eq!(Amount::parse("0.994", &usdt).unwrap().format(&FormatOptions { precision: Some(2), ..Default::default() }), "0.99");
+        assert_eq!(usdt.amount(0).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        let usdt = create_test_token();
+        let options = FormatOptions { precision: None, separator: Some(','), symbol: Some("USDT".to_string()) };
+        for raw in [0, 1, 999_999, 1_000_000, 123_456_789_012, u64::MAX as u128, u128::MAX] {
+            let amount = usdt.amount(raw).unwrap();
+            assert_eq!(Amount::parse(&amount.format(&options), &usdt).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let usdt = create_test_token();
+        let one = usdt.amount(1_000_000).unwrap();
+        let two = one.checked_add(&one).unwrap();
+        assert_eq!(two.raw(), 2_000_000);
+        assert_eq!(two.checked_sub(&one).unwrap(), one);
+        assert_eq!(one.checked_sub(&two), Err(AmountError::Underflow));
+        assert_eq!(usdt.amount(u128::MAX).unwrap().checked_add(&one), Err(AmountError::Overflow));
+        assert_eq!(one.checked_mul(3).unwrap().raw(), 3_000_000);
+        assert_eq!(one.checked_div(3).unwrap().raw(), 333_333);
+        assert_eq!(one.checked_div(0), Err(AmountError::DivisionByZero));
+        assert!(one < two);
+    }
+
+    #[test]
+    fn test_tokens_dont_mix() {
+        let usdt = create_test_token().amount(1).unwrap();
+        let alph = Amount::alph(1);
+        assert_eq!(usdt.checked_add(&alph), Err(AmountError::TokenMismatch { left: USDT, right: TokenId::ALPH }));
+        assert_eq!(usdt.partial_cmp(&alph), None);
+    }
+
+    #[test]
+    fn test_invalid_decimals() {
+        assert_eq!(Amount::new(USDT, 39, 1), Err(AmountError::InvalidDecimals(39)));
+        assert!(Amount::new(USDT, 38, u128::MAX).is_ok());
+    }
+}
@@ -0,0 +1,309 @@
+// lp position analytics
+
+// everything is valued in units of token two at the pool's current spot
+// price, so "value" always means "how much token two is this worth right now"
+
+use crate::swap::stable_swap;
+use crate::swap::Pool;
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum AnalyticsError {
+    EmptyPosition,
+    EmptyPool,
+    // the position was opened after the time it is being valued at
+    TimeTravel,
+    // more lp tokens than the pool has issued
+    ExceedsSupply { lp_tokens: u128, total_supply: u128 }
+}
+
+// an lp position as it was when it was opened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub lp_tokens: u128,
+    pub entry_reserve_one: u128,
+    pub entry_reserve_two: u128,
+    pub entry_total_supply: u128,
+    // fee counters at entry, so only fees earned since then count
+    pub entry_fee_one: u128,
+    pub entry_fee_two: u128,
+    // unix seconds
+    pub entry_timestamp: u64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionReport {
+    // what the lp tokens redeem for now, and its value
+    pub amount_one: f64,
+    pub amount_two: f64,
+    pub value: f64,
+    // value of the tokens deposited had they just been held
+    pub hodl_value: f64,
+    // value of the deposit at entry prices
+    pub entry_value: f64,
+    // fraction lost to price movement alone (fees excluded), <= 0
+    pub impermanent_loss: f64,
+    // the position's share of fees earned since entry. fees stay in the
+    // reserves, so this is part of amount_one / amount_two, not on top
+    pub fee_income_one: f64,
+    pub fee_income_two: f64,
+    // value - entry_value, fees included once through the reserves
+    pub pnl: f64,
+    // fee income's value / entry_value, annualized
+    pub apr: f64
+}
+
+impl Position {
+
+    // record a position of `lp_tokens` opened against the pool as it is now
+    pub fn open(pool: &Pool, lp_tokens: u128, timestamp: u64) -> Result<Self, AnalyticsError> {
+        let total_supply = pool.total_supply();
+        if lp_tokens > total_supply {
+            return Err(AnalyticsError::ExceedsSupply { lp_tokens, total_supply });
+        }
+        let (entry_reserve_one, entry_reserve_two) = pool.reserves();
+        let (entry_fee_one, entry_fee_two) = pool.fees();
+        Ok(Position {
+            lp_tokens,
+            entry_reserve_one,
+            entry_reserve_two,
+            entry_total_supply: total_supply,
+            entry_fee_one,
+            entry_fee_two,
+            entry_timestamp: timestamp
+        })
+    }
+
+    // tokens the position was entitled to when it was opened
+    fn entry_amounts(&self) -> (f64, f64) {
+        let share = self.lp_tokens as f64 / self.entry_total_supply as f64;
+        (self.entry_reserve_one as f64 * share, self.entry_reserve_two as f64 * share)
+    }
+
+    pub fn report(&self, pool: &Pool, now: u64) -> Result<PositionReport, AnalyticsError> {
+        if self.lp_tokens == 0 || self.entry_total_supply == 0 {
+            return Err(AnalyticsError::EmptyPosition);
+        }
+        if now < self.entry_timestamp {
+            return Err(AnalyticsError::TimeTravel);
+        }
+
+        let (reserve_one, reserve_two) = pool.reserves();
+        let total_supply = pool.total_supply();
+        if total_supply == 0 || reserve_one == 0 || reserve_two == 0 {
+            return Err(AnalyticsError::EmptyPool);
+        }
+
+        let price = pool.spot_price();
+        let share = self.lp_tokens as f64 / total_supply as f64;
+        let amount_one = reserve_one as f64 * share;
+        let amount_two = reserve_two as f64 * share;
+        let value = amount_one * price + amount_two;
+
+        let (entry_one, entry_two) = self.entry_amounts();
+        let entry_price = match pool {
+            Pool::Swap(_) => self.entry_reserve_two as f64 / self.entry_reserve_one as f64,
+            Pool::StableSwap(_) => stable_swap::price_at(self.entry_reserve_one as f64, self.entry_reserve_two as f64)
+        };
+        let hodl_value = entry_one * price + entry_two;
+        let entry_value = entry_one * entry_price + entry_two;
+
+        let impermanent_loss = match pool {
+            Pool::Swap(_) => constant_product_il(price / entry_price),
+            Pool::StableSwap(_) => stable_il(self.entry_reserve_one as f64, self.entry_reserve_two as f64, price)
+        };
+
+        let (fee_one, fee_two) = pool.fees();
+        let fee_income_one = fee_one.saturating_sub(self.entry_fee_one) as f64 * share;
+        let fee_income_two = fee_two.saturating_sub(self.entry_fee_two) as f64 * share;
+        let fee_value = fee_income_one * price + fee_income_two;
+
+        let elapsed = now - self.entry_timestamp;
+        let apr = if elapsed == 0 || entry_value == 0.0 {
+            0.0
+        } else {
+            fee_value / entry_value * (SECONDS_PER_YEAR as f64 / elapsed as f64)
+        };
+
+        Ok(PositionReport {
+            amount_one,
+            amount_two,
+            value,
+            hodl_value,
+            entry_value,
+            impermanent_loss,
+            fee_income_one,
+            fee_income_two,
+            pnl: value - entry_value,
+            apr
+        })
+    }
+}
+
+// x * y = k: with r = new price / entry price, IL = 2√r / (1 + r) - 1
+pub fn constant_product_il(price_ratio: f64) -> f64 {
+    2.0 * price_ratio.sqrt() / (1.0 + price_ratio) - 1.0
+}
+
+// x³y + y³x = k has no tidy closed form, so find the point on the entry
+// curve that quotes `price` and compare it with holding (x0, y0)
+pub fn stable_il(entry_reserve_one: f64, entry_reserve_two: f64, price: f64) -> f64 {
+    let (x, y) = stable_reserves_at_price(entry_reserve_one, entry_reserve_two, price);
+    (x * price + y) / (entry_reserve_one * price + entry_reserve_two) - 1.0
+}
+
+// bisect on x: the curve's price of token one falls as x grows
+pub fn stable_reserves_at_price(reserve_one: f64, reserve_two: f64, price: f64) -> (f64, f64) {
+    let k = stable_swap::invariant(reserve_one, reserve_two);
+    let y_at = |x: f64| stable_swap::solve_y(x, k, (k / x).cbrt(), 100);
+
+    // search in log space, prices this far off mean the pool is drained anyway
+    let (mut lo, mut hi) = ((reserve_one * 1e-9).ln(), (reserve_one * 1e9).ln());
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        let x = mid.exp();
+        if stable_swap::price_at(x, y_at(x)) > price {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let x = ((lo + hi) / 2.0).exp();
+    (x, y_at(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::TokenId;
+    use crate::swap::{StableSwap, Swap};
+
+    const ALPH: TokenId = TokenId::ALPH;
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const USDC: TokenId = TokenId::new([0x1c; 32]);
+
+    fn create_test_pool() -> Pool {
+        Pool::Swap(Swap {
+            token_one: ALPH,
+            token_two: USDT,
+            reserve_one: 1_000_000,
+            reserve_two: 2_000_000,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 1_414_213,
+        })
+    }
+
+    fn create_test_stable_pool() -> Pool {
+        Pool::StableSwap(StableSwap {
+            token_one: USDT,
+            token_two: USDC,
+            reserve_one: 1_000_000,
+            reserve_two: 1_000_000,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 1_000_000,
+        })
+    }
+
+    #[test]
+    fn test_constant_product_il_known_values() {
+        assert_eq!(constant_product_il(1.0), 0.0);
+        // the textbook numbers: 2x -> -5.72%, 4x -> -20%
+        assert!((constant_product_il(2.0) + 0.057191).abs() < 1e-6);
+        assert!((constant_product_il(4.0) + 0.2).abs() < 1e-12);
+        assert!((constant_product_il(0.25) - constant_product_il(4.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stable_il_near_peg() {
+        assert!(stable_il(1000.0, 1000.0, 1.0).abs() < 1e-9);
+
+        let stable = stable_il(1000.0, 1000.0, 1.02);
+        let constant = constant_product_il(1.02);
+        assert!(stable < 0.0);
+        // the flat curve rebalances harder, so it loses more per unit of price move
+        assert!(stable < constant);
+    }
+
+    #[test]
+    fn test_stable_reserves_at_price_stay_on_curve() {
+        let (x, y) = stable_reserves_at_price(1000.0, 1000.0, 1.1);
+        let k = stable_swap::invariant(1000.0, 1000.0);
+        assert!((stable_swap::invariant(x, y) - k).abs() / k < 1e-9);
+        assert!((stable_swap::price_at(x, y) - 1.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_report_untouched_pool() {
+        let pool = create_test_pool();
+        let position = Position::open(&pool, 141_421, 0).unwrap();
+        let report = position.report(&pool, 0).unwrap();
+
+        assert!((report.value - report.entry_value).abs() < 1e-6);
+        assert_eq!(report.impermanent_loss, 0.0);
+        assert_eq!((report.fee_income_one, report.fee_income_two), (0.0, 0.0));
+        assert_eq!(report.apr, 0.0);
+    }
+
+    #[test]
+    fn test_report_after_trading() {
+        let mut pool = create_test_pool();
+        let position = Position::open(&pool, 141_421, 0).unwrap();
+
+        for _ in 0..10 {
+            pool.swap(50_000, true).unwrap();
+        }
+        let report = position.report(&pool, SECONDS_PER_YEAR / 12).unwrap();
+
+        // all the selling pushed ALPH down, so the lp is worse off than holding
+        assert!(report.impermanent_loss < 0.0);
+        assert!(report.value < report.hodl_value);
+        assert!(report.fee_income_one > 0.0);
+        assert_eq!(report.fee_income_two, 0.0);
+        // fees soften the loss compared with the pure curve
+        assert!(report.value / report.hodl_value - 1.0 > report.impermanent_loss);
+        let fee_value = report.fee_income_one * pool.spot_price() + report.fee_income_two;
+        assert!((report.apr - fee_value / report.entry_value * 12.0).abs() < 1e-9);
+
+        // fees are counted once: the value is exactly the reserves' share
+        let (reserve_one, reserve_two) = pool.reserves();
+        let share = 141_421.0 / pool.total_supply() as f64;
+        let held = reserve_one as f64 * share * pool.spot_price() + reserve_two as f64 * share;
+        assert!((report.value - held).abs() < 1e-6);
+        assert!((report.pnl - (held - report.entry_value)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_report_stable_pool() {
+        let mut pool = create_test_stable_pool();
+        let position = Position::open(&pool, 100_000, 100).unwrap();
+
+        pool.swap(200_000, true).unwrap();
+        let report = position.report(&pool, 200).unwrap();
+
+        assert!(report.impermanent_loss < 0.0);
+        assert!(report.impermanent_loss > -0.05);
+        assert!(report.fee_income_one > 0.0);
+        assert!(report.apr > 0.0);
+    }
+
+    #[test]
+    fn test_report_errors() {
+        let pool = create_test_pool();
+        let position = Position::open(&pool, 0, 10).unwrap();
+        assert!(matches!(position.report(&pool, 10), Err(AnalyticsError::EmptyPosition)));
+
+        let position = Position::open(&pool, 100, 10).unwrap();
+        assert!(matches!(position.report(&pool, 5), Err(AnalyticsError::TimeTravel)));
+
+        assert!(matches!(
+            Position::open(&pool, 1_414_214, 10),
+            Err(AnalyticsError::ExceedsSupply { lp_tokens: 1_414_214, total_supply: 1_414_213 })
+        ));
+    }
+}
@@ -0,0 +1,414 @@
+// arbitrage sizing
+
+// how much would an arbitrageur trade to pull a pool back to the market?
+// constant product pools get the closed form, anything with the stable curve
+// gets a search over simulated swaps. profits are in units of token two and
+// `price` is always the price of token one in token two. between two pools,
+// "token one" is the first pool's; a second pool listing the same tokens the
+// other way round is traded flipped.
+
+use crate::swap::{Pool, PoolError, StableSwap, Swap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArbitrageError {
+    // the pools don't trade the same two tokens
+    DifferentPairs,
+    // a fee over 10000 basis points, which no pool can swap with
+    InvalidFee(u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub amount_in: u128,
+    pub token_in_is_one: bool,
+    pub expected_profit: f64
+}
+
+// buy token one with token two in the cheap pool, sell it in the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairTrade {
+    pub amount_in: u128,
+    pub cheap_is_first: bool,
+    // the second pool lists the first pool's token two as its token one
+    pub second_reversed: bool,
+    pub expected_profit: f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub profit: f64,
+    // spot price of the pool that was traded against (the second pool for pairs)
+    pub price_after: f64
+}
+
+// none for a fee over 10000 basis points
+fn fee_multiplier(pool: &Pool) -> Option<f64> {
+    10000u64.checked_sub(pool.fee()).map(|rest| rest as f64 / 10000.0)
+}
+
+// what the trade actually yields, valued at `price`, if run on a copy
+fn profit_against_price(pool: &Pool, amount_in: u128, token_in_is_one: bool, price: f64) -> Option<f64> {
+    let amount_out = pool.clone().swap(amount_in, token_in_is_one).ok()?;
+    Some(if token_in_is_one {
+        amount_out as f64 - amount_in as f64 * price
+    } else {
+        amount_out as f64 * price - amount_in as f64
+    })
+}
+
+// the same pool with its tokens listed the other way round
+fn flipped(pool: &Pool) -> Pool {
+    match pool {
+        Pool::Swap(swap) => Pool::Swap(Swap {
+            token_one: swap.token_two,
+            token_two: swap.token_one,
+            reserve_one: swap.reserve_two,
+            reserve_two: swap.reserve_one,
+            fee_one: swap.fee_two,
+            fee_two: swap.fee_one,
+            ..swap.clone()
+        }),
+        Pool::StableSwap(swap) => Pool::StableSwap(StableSwap {
+            token_one: swap.token_two,
+            token_two: swap.token_one,
+            reserve_one: swap.reserve_two,
+            reserve_two: swap.reserve_one,
+            fee_one: swap.fee_two,
+            fee_two: swap.fee_one,
+            ..swap.clone()
+        })
+    }
+}
+
+// whether `second` has to be flipped to line up with `first`
+fn orientation(first: &Pool, second: &Pool) -> Result<bool, ArbitrageError> {
+    let (one, two) = first.tokens();
+    match second.tokens() {
+        tokens if tokens == (one, two) => Ok(false),
+        tokens if tokens == (two, one) => Ok(true),
+        _ => Err(ArbitrageError::DifferentPairs)
+    }
+}
+
+fn profit_between_pools(cheap: &Pool, rich: &Pool, amount_in: u128) -> Option<f64> {
+    let bought = cheap.clone().swap(amount_in, false).ok()?;
+    let sold = rich.clone().swap(bought, true).ok()?;
+    Some(sold as f64 - amount_in as f64)
+}
+
+// ternary search for the best integer input; profit is concave in the input
+// on both curves (up to rounding), so the peak is all we need
+fn search<F: Fn(u128) -> Option<f64>>(upper: u128, profit: F) -> Option<(u128, f64)> {
+    let value = |amount: u128| profit(amount).unwrap_or(f64::NEG_INFINITY);
+
+    let (mut lo, mut hi) = (1u128, upper.max(1));
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        if value(m1) < value(m2) {
+            lo = m1;
+        } else {
+            hi = m2;
+        }
+    }
+
+    (lo..=hi)
+        .map(|amount| (amount, value(amount)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|(_, p)| *p > 0.0)
+}
+
+pub fn optimal_trade_against_price(pool: &Pool, price: f64) -> Option<Trade> {
+    let (x, y) = pool.reserves();
+    if x == 0 || y == 0 || price <= 0.0 {
+        return None;
+    }
+    let gamma = fee_multiplier(pool)?;
+
+    // token one is dear in the pool: sell it in, buy it back at `price`
+    let token_in_is_one = pool.spot_price() > price;
+
+    let amount_in = match pool {
+        Pool::Swap(_) => {
+            // profit(d) = P·x·γd / (y + γd) - d is maximal at
+            //   d = (√(γ·P·x·y) - y) / γ   buying token one
+            //   d = (√(γ·x·y / P) - x) / γ  selling token one
+            let (x, y) = (x as f64, y as f64);
+            let d = if token_in_is_one {
+                ((gamma * x * y / price).sqrt() - x) / gamma
+            } else {
+                ((gamma * price * x * y).sqrt() - y) / gamma
+            };
+            if d < 1.0 {
+                return None;
+            }
+            d as u128
+        }
+        Pool::StableSwap(_) => {
+            let reserve_in = if token_in_is_one { x } else { y };
+            search(reserve_in.saturating_mul(4), |amount| profit_against_price(pool, amount, token_in_is_one, price))?.0
+        }
+    };
+
+    let expected_profit = profit_against_price(pool, amount_in, token_in_is_one, price)?;
+    (expected_profit > 0.0).then_some(Trade { amount_in, token_in_is_one, expected_profit })
+}
+
+pub fn optimal_trade_between_pools(first: &Pool, second: &Pool) -> Result<Option<PairTrade>, ArbitrageError> {
+    let second_reversed = orientation(first, second)?;
+    for pool in [first, second] {
+        if fee_multiplier(pool).is_none() {
+            return Err(ArbitrageError::InvalidFee(pool.fee()));
+        }
+    }
+    if first.reserves().0 == 0 || first.reserves().1 == 0 || second.reserves().0 == 0 || second.reserves().1 == 0 {
+        return Ok(None);
+    }
+    let second = &if second_reversed { flipped(second) } else { second.clone() };
+
+    let cheap_is_first = first.spot_price() < second.spot_price();
+    let (cheap, rich) = if cheap_is_first { (first, second) } else { (second, first) };
+    Ok(size_pair_trade(cheap, rich).map(|(amount_in, expected_profit)| {
+        PairTrade { amount_in, cheap_is_first, second_reversed, expected_profit }
+    }))
+}
+
+// both pools listing the pair the same way round
+fn size_pair_trade(cheap: &Pool, rich: &Pool) -> Option<(u128, f64)> {
+    let amount_in = match (cheap, rich) {
+        (Pool::Swap(_), Pool::Swap(_)) => {
+            // chaining two x·y = k swaps gives out(d) = N·d / (D + E·d) with
+            //   N = γa·γb·xa·yb, D = xb·ya, E = γa·(xb + γb·xa)
+            // and out(d) - d peaks at d = (√(N·D) - D) / E
+            let (xa, ya) = cheap.reserves();
+            let (xb, yb) = rich.reserves();
+            let (xa, ya, xb, yb) = (xa as f64, ya as f64, xb as f64, yb as f64);
+            let (ga, gb) = (fee_multiplier(cheap)?, fee_multiplier(rich)?);
+
+            let n = ga * gb * xa * yb;
+            let d = xb * ya;
+            let e = ga * (xb + gb * xa);
+            let amount = ((n * d).sqrt() - d) / e;
+            if amount < 1.0 {
+                return None;
+            }
+            amount as u128
+        }
+        _ => search(cheap.reserves().1.saturating_mul(4), |amount| profit_between_pools(cheap, rich, amount))?.0
+    };
+
+    let expected_profit = profit_between_pools(cheap, rich, amount_in)?;
+    (expected_profit > 0.0).then_some((amount_in, expected_profit))
+}
+
+// run the trade against the pool; the other leg happens off-pool at `price`
+pub fn execute_against_price(pool: &mut Pool, trade: &Trade, price: f64) -> Result<Outcome, PoolError> {
+    let amount_out = pool.swap(trade.amount_in, trade.token_in_is_one)?;
+    let profit = if trade.token_in_is_one {
+        amount_out as f64 - trade.amount_in as f64 * price
+    } else {
+        amount_out as f64 * price - trade.amount_in as f64
+    };
+
+    Ok(Outcome { amount_in: trade.amount_in, amount_out, profit, price_after: pool.spot_price() })
+}
+
+// both legs or neither: the pools are only updated if both swaps go through
+pub fn execute_between_pools(first: &mut Pool, second: &mut Pool, trade: &PairTrade) -> Result<Outcome, PoolError> {
+    // buying token one means paying in token two, unless the pool is flipped
+    let (cheap, rich, cheap_reversed, rich_reversed) = if trade.cheap_is_first {
+        (first, second, false, trade.second_reversed)
+    } else {
+        (second, first, trade.second_reversed, false)
+    };
+
+    let mut new_cheap = cheap.clone();
+    let mut new_rich = rich.clone();
+    let bought = new_cheap.swap(trade.amount_in, cheap_reversed)?;
+    let amount_out = new_rich.swap(bought, !rich_reversed)?;
+
+    *cheap = new_cheap;
+    *rich = new_rich;
+
+    let price_after = if rich_reversed { 1.0 / rich.spot_price() } else { rich.spot_price() };
+    Ok(Outcome { amount_in: trade.amount_in, amount_out, profit: amount_out as f64 - trade.amount_in as f64, price_after })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::TokenId;
+
+    const ALPH: TokenId = TokenId::ALPH;
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const USDC: TokenId = TokenId::new([0x1c; 32]);
+
+    fn create_test_pool(reserve_one: u128, reserve_two: u128) -> Pool {
+        Pool::Swap(Swap {
+            token_one: ALPH,
+            token_two: USDT,
+            reserve_one,
+            reserve_two,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 0,
+        })
+    }
+
+    fn create_test_stable_pool(reserve_one: u128, reserve_two: u128) -> Pool {
+        Pool::StableSwap(StableSwap {
+            token_one: USDT,
+            token_two: USDC,
+            reserve_one,
+            reserve_two,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 0,
+        })
+    }
+
+    // brute force over a window around the answer; integer outputs make the
+    // profit jitter by a unit or two of token two near the peak
+    fn assert_locally_optimal<F: Fn(u128) -> Option<f64>>(amount: u128, profit: F) {
+        let best = profit(amount).unwrap();
+        for delta in [1, 10, 100, 1000] {
+            assert!(profit(amount + delta).unwrap_or(f64::NEG_INFINITY) <= best + 3.0);
+            assert!(profit(amount.saturating_sub(delta).max(1)).unwrap_or(f64::NEG_INFINITY) <= best + 3.0);
+        }
+    }
+
+    #[test]
+    fn test_no_trade_at_fair_price() {
+        let pool = create_test_pool(1_000_000, 2_000_000);
+        // inside the fee band nothing is worth doing
+        assert_eq!(optimal_trade_against_price(&pool, 2.0), None);
+        assert_eq!(optimal_trade_against_price(&pool, 2.005), None);
+        assert_eq!(optimal_trade_between_pools(&pool, &pool.clone()), Ok(None));
+    }
+
+    #[test]
+    fn test_constant_product_against_price() {
+        let pool = create_test_pool(1_000_000, 2_000_000);
+
+        let trade = optimal_trade_against_price(&pool, 2.2).unwrap();
+        assert!(!trade.token_in_is_one);
+        assert_locally_optimal(trade.amount_in, |a| profit_against_price(&pool, a, false, 2.2));
+
+        let trade = optimal_trade_against_price(&pool, 1.8).unwrap();
+        assert!(trade.token_in_is_one);
+        assert_locally_optimal(trade.amount_in, |a| profit_against_price(&pool, a, true, 1.8));
+    }
+
+    #[test]
+    fn test_execute_moves_price_to_market() {
+        let mut pool = create_test_pool(1_000_000, 2_000_000);
+        let trade = optimal_trade_against_price(&pool, 2.5).unwrap();
+        let outcome = execute_against_price(&mut pool, &trade, 2.5).unwrap();
+
+        assert!((outcome.profit - trade.expected_profit).abs() < 1e-9);
+        // after the arb the pool sits at the edge of the fee band
+        assert!((outcome.price_after * 0.997 - 2.5).abs() / 2.5 < 0.01);
+        assert_eq!(optimal_trade_against_price(&pool, 2.5), None);
+    }
+
+    #[test]
+    fn test_stable_against_price() {
+        let mut pool = create_test_stable_pool(1_000_000, 1_000_000);
+        let trade = optimal_trade_against_price(&pool, 1.02).unwrap();
+        assert!(!trade.token_in_is_one);
+        assert_locally_optimal(trade.amount_in, |a| profit_against_price(&pool, a, false, 1.02));
+
+        let outcome = execute_against_price(&mut pool, &trade, 1.02).unwrap();
+        assert!(outcome.profit > 0.0);
+        assert!(outcome.price_after > 1.0);
+    }
+
+    #[test]
+    fn test_constant_product_pair() {
+        let mut first = create_test_pool(1_000_000, 2_000_000);
+        let mut second = create_test_pool(1_000_000, 2_400_000);
+
+        let trade = optimal_trade_between_pools(&first, &second).unwrap().unwrap();
+        assert!(trade.cheap_is_first);
+        assert!(!trade.second_reversed);
+        assert_locally_optimal(trade.amount_in, |a| profit_between_pools(&first, &second, a));
+
+        let outcome = execute_between_pools(&mut first, &mut second, &trade).unwrap();
+        assert_eq!(outcome.profit, trade.expected_profit);
+        assert!(first.spot_price() > 2.0);
+        assert!(second.spot_price() < 2.4);
+        assert_eq!(optimal_trade_between_pools(&first, &second), Ok(None));
+    }
+
+    #[test]
+    fn test_reversed_pair() {
+        let first = create_test_pool(1_000_000, 2_000_000);
+        let second = create_test_pool(1_000_000, 2_400_000);
+        // the same USDT/ALPH pool, listed the other way round
+        let reversed = flipped(&second);
+        assert_eq!(reversed.tokens(), (USDT, ALPH));
+
+        let trade = optimal_trade_between_pools(&first, &second).unwrap().unwrap();
+        let flipped_trade = optimal_trade_between_pools(&first, &reversed).unwrap().unwrap();
+        assert!(flipped_trade.second_reversed);
+        assert_eq!((flipped_trade.amount_in, flipped_trade.cheap_is_first), (trade.amount_in, trade.cheap_is_first));
+        assert_eq!(flipped_trade.expected_profit, trade.expected_profit);
+
+        let (mut first_a, mut second_a) = (first.clone(), second);
+        let (mut first_b, mut second_b) = (first, reversed);
+        let outcome = execute_between_pools(&mut first_a, &mut second_a, &trade).unwrap();
+        let flipped_outcome = execute_between_pools(&mut first_b, &mut second_b, &flipped_trade).unwrap();
+        assert_eq!(outcome.amount_out, flipped_outcome.amount_out);
+        assert!((outcome.price_after - flipped_outcome.price_after).abs() < 1e-12);
+        assert_eq!(second_b, flipped(&second_a));
+    }
+
+    #[test]
+    fn test_mixed_pair_uses_search() {
+        let first = create_test_stable_pool(1_000_000, 1_000_000);
+        let second = Pool::Swap(Swap { token_one: USDT, token_two: USDC, reserve_one: 1_000_000, reserve_two: 950_000, fee: 30, fee_one: 0, fee_two: 0, total_supply: 0 });
+
+        let trade = optimal_trade_between_pools(&first, &second).unwrap().unwrap();
+        assert!(!trade.cheap_is_first);
+        assert_locally_optimal(trade.amount_in, |a| profit_between_pools(&second, &first, a));
+    }
+
+    #[test]
+    fn test_different_pairs() {
+        let first = create_test_stable_pool(1_000_000, 1_000_000);
+        let second = create_test_pool(1_000_000, 950_000);
+        assert_eq!(
+            optimal_trade_between_pools(&first, &second),
+            Err(ArbitrageError::DifferentPairs)
+        );
+    }
+
+    #[test]
+    fn test_invalid_fee() {
+        let mut broken = create_test_pool(1_000_000, 2_000_000);
+        if let Pool::Swap(swap) = &mut broken {
+            swap.fee = 20_000;
+        }
+        assert_eq!(optimal_trade_against_price(&broken, 3.0), None);
+        assert_eq!(
+            optimal_trade_between_pools(&create_test_pool(1_000_000, 2_400_000), &broken),
+            Err(ArbitrageError::InvalidFee(20_000))
+        );
+    }
+
+    #[test]
+    fn test_failed_pair_leaves_pools_untouched() {
+        let mut first = create_test_pool(1_000_000, 2_000_000);
+        let mut second = create_test_pool(1_000_000, 2_400_000);
+        let (before_first, before_second) = (first.clone(), second.clone());
+
+        let trade = PairTrade { amount_in: 0, cheap_is_first: true, second_reversed: false, expected_profit: 0.0 };
+        assert!(matches!(execute_between_pools(&mut first, &mut second, &trade), Err(PoolError::AmountIsZero)));
+        assert_eq!(first, before_first);
+        assert_eq!(second, before_second);
+    }
+}
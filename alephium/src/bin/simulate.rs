@@ -0,0 +1,46 @@
+// replay a trade log against a pool snapshot and print per-step state as csv
+//
+// usage: simulate <pool.json> <trades.csv|trades.json> [out.csv]
+
+use std::fs::{self, File};
+use std::io;
+use std::process;
+
+use alephium::simulator::{self, Simulator};
+use alephium::snapshot::Snapshot;
+use alephium::swap::Pool;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("usage: {} <pool.json> <trades.csv|trades.json> [out.csv]", args[0]);
+        process::exit(2);
+    }
+
+    let pool_json = fs::read_to_string(&args[1]).unwrap_or_else(|e| fail(&args[1], e));
+    let pool = Snapshot::from_json(&pool_json)
+        .and_then(Pool::try_from)
+        .unwrap_or_else(|e| fail(&args[1], e));
+
+    let trades = File::open(&args[2]).unwrap_or_else(|e| fail(&args[2], e));
+    let actions = if args[2].ends_with(".json") {
+        simulator::read_trades_json(trades, &pool)
+    } else {
+        simulator::read_trades_csv(trades, &pool)
+    }.unwrap_or_else(|e| fail(&args[2], e));
+
+    let records = Simulator::new(pool).replay(&actions);
+
+    let written = match args.get(3) {
+        Some(path) => File::create(path)
+            .map_err(|e| simulator::SimulatorError::Csv(e.into()))
+            .and_then(|file| simulator::write_csv(&records, file)),
+        None => simulator::write_csv(&records, io::stdout().lock())
+    };
+    written.unwrap_or_else(|e| fail("output", e));
+}
+
+fn fail<E: std::fmt::Debug>(what: &str, e: E) -> ! {
+    eprintln!("{}: {:?}", what, e);
+    process::exit(1);
+}
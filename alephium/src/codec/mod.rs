@@ -0,0 +1,718 @@
+// alephium binary codec
+
+// the node's wire format, which is also what transaction ids hash:
+//
+//   byte, bool      one byte
+//   hash, key       raw bytes, fixed length
+//   int             compact signed integer
+//   U256            compact unsigned integer
+//   timestamp       8 bytes big endian
+//   AVector[T]      int length, then each element
+//   ByteString      AVector[byte]
+//   Option[T]       0x00, or 0x01 and the value
+//
+// compact integers keep their mode in the top two bits of the first byte:
+//
+//   0b00  1 byte,  6 bit value
+//   0b01  2 bytes, 14 bit value
+//   0b10  4 bytes, 30 bit value
+//   0b11  (low 6 bits + 4) more bytes, big endian
+//
+// signed values are two's complement within their bits. an int that doesn't
+// fit in 30 bits always takes the 4 byte long form, a U256 takes as few
+// bytes as it can (but at least 4).
+
+use std::fmt;
+
+use crate::address::{Address, AddressError, Hash, HASH_LENGTH};
+use crate::contract::TokenId;
+use crate::keys::{KeyError, PublicKey, PUBLIC_KEY_LENGTH};
+use crate::transaction::{TxInput, UnlockScript, UnsignedTransaction};
+use crate::utxo::{AssetOutput, OutputRef};
+
+const SINGLE_BYTE: u8 = 0x00;
+const TWO_BYTE: u8 = 0x40;
+const FOUR_BYTE: u8 = 0x80;
+const MULTI_BYTE: u8 = 0xc0;
+const MODE_MASK: u8 = 0xc0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodecError {
+    UnexpectedEnd,
+    TrailingBytes(usize),
+    // a compact integer too big for the type it decodes into
+    Overflow,
+    InvalidLength(i32),
+    InvalidBool(u8),
+    InvalidGas(i32),
+    UnknownPrefix { kind: &'static str, prefix: u8 },
+    // contracts can't lock asset outputs
+    ContractLockup,
+    // transaction scripts aren't decoded yet
+    UnsupportedScript,
+    Address(AddressError),
+    Key(KeyError)
+}
+
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    // read one value off the front of `input`
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError>;
+}
+
+pub fn serialize<T: Codec>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.encode(&mut out);
+    out
+}
+
+// the whole of `bytes` has to be one value
+pub fn deserialize<T: Codec>(bytes: &[u8]) -> Result<T, CodecError> {
+    let mut input = bytes;
+    let value = T::decode(&mut input)?;
+    if !input.is_empty() {
+        return Err(CodecError::TrailingBytes(input.len()));
+    }
+    Ok(value)
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], CodecError> {
+    if input.len() < n {
+        return Err(CodecError::UnexpectedEnd);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], CodecError> {
+    Ok(take(input, N)?.try_into().expect("took exactly N bytes"))
+}
+
+// the first byte and the bytes after it, big endian with the mode bits
+// still in place for the short forms
+fn take_compact<'a>(input: &mut &'a [u8]) -> Result<(u8, &'a [u8]), CodecError> {
+    let first = take(input, 1)?[0];
+    let rest = match first & MODE_MASK {
+        SINGLE_BYTE => 0,
+        TWO_BYTE => 1,
+        FOUR_BYTE => 3,
+        _ => (first & !MODE_MASK) as usize + 4
+    };
+    Ok((first, take(input, rest)?))
+}
+
+pub fn encode_i32(n: i32, out: &mut Vec<u8>) {
+    let bytes = n.to_be_bytes();
+    if (-0x20..0x20).contains(&n) {
+        out.push(bytes[3] & !MODE_MASK);
+    } else if (-0x2000..0x2000).contains(&n) {
+        out.extend_from_slice(&[(bytes[2] & !MODE_MASK) | TWO_BYTE, bytes[3]]);
+    } else if (-0x2000_0000..0x2000_0000).contains(&n) {
+        out.extend_from_slice(&[(bytes[0] & !MODE_MASK) | FOUR_BYTE, bytes[1], bytes[2], bytes[3]]);
+    } else {
+        out.push(MULTI_BYTE);
+        out.extend_from_slice(&bytes);
+    }
+}
+
+pub fn decode_i32(input: &mut &[u8]) -> Result<i32, CodecError> {
+    let (first, rest) = take_compact(input)?;
+    if first & MODE_MASK == MULTI_BYTE {
+        let bytes: [u8; 4] = rest.try_into().map_err(|_| CodecError::Overflow)?;
+        return Ok(i32::from_be_bytes(bytes));
+    }
+
+    let bits = 6 + 8 * rest.len() as u32;
+    let value = rest.iter().fold((first & !MODE_MASK) as i32, |n, b| (n << 8) | *b as i32);
+    // sign extend from the top value bit
+    let shift = 32 - bits;
+    Ok((value << shift) >> shift)
+}
+
+// an AVector or ByteString length
+fn encode_length(n: usize, out: &mut Vec<u8>) {
+    encode_i32(i32::try_from(n).expect("vectors hold fewer than 2^31 elements"), out);
+}
+
+fn decode_length(input: &mut &[u8]) -> Result<usize, CodecError> {
+    let n = decode_i32(input)?;
+    usize::try_from(n).map_err(|_| CodecError::InvalidLength(n))
+}
+
+// 256 bit unsigned integers, big endian
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct U256([u8; 32]);
+
+impl U256 {
+
+    pub const ZERO: U256 = U256([0; 32]);
+    pub const MAX: U256 = U256([0xff; 32]);
+
+    pub const fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        U256(bytes)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        let (high, low) = self.0.split_at(24);
+        high.iter().all(|b| *b == 0).then(|| u64::from_be_bytes(low.try_into().unwrap()))
+    }
+
+    // amounts in this crate are u128
+    pub fn to_u128(&self) -> Option<u128> {
+        let (high, low) = self.0.split_at(16);
+        high.iter().all(|b| *b == 0).then(|| u128::from_be_bytes(low.try_into().unwrap()))
+    }
+
+    // decimal digits, as the node writes them in json. None for anything
+    // else, or past U256::MAX
+    pub fn from_dec_str(s: &str) -> Option<Self> {
+        if s.is_empty() {
+            return None;
+        }
+        let mut bytes = [0u8; 32];
+        for c in s.chars() {
+            let mut carry = c.to_digit(10)?;
+            for byte in bytes.iter_mut().rev() {
+                let value = *byte as u32 * 10 + carry;
+                *byte = value as u8;
+                carry = value >> 8;
+            }
+            if carry != 0 {
+                return None;
+            }
+        }
+        Some(U256(bytes))
+    }
+
+    // big endian without leading zeros
+    fn significant_bytes(&self) -> &[u8] {
+        let start = self.0.iter().position(|b| *b != 0).unwrap_or(32);
+        &self.0[start..]
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(n: u128) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[16..].copy_from_slice(&n.to_be_bytes());
+        U256(bytes)
+    }
+}
+
+impl fmt::Display for U256 {
+    // long division by 10 on the big endian bytes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = Vec::new();
+        let mut n = self.0;
+        while n.iter().any(|b| *b != 0) {
+            let mut remainder = 0u32;
+            for byte in n.iter_mut() {
+                let value = (remainder << 8) | *byte as u32;
+                *byte = (value / 10) as u8;
+                remainder = value % 10;
+            }
+            digits.push(b'0' + remainder as u8);
+        }
+        if digits.is_empty() {
+            digits.push(b'0');
+        }
+        digits.reverse();
+        f.write_str(std::str::from_utf8(&digits).expect("digits are ascii"))
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256({})", self)
+    }
+}
+
+impl Codec for U256 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let n = self.to_u64().unwrap_or(u64::MAX);
+        if n < 0x40 {
+            out.push(n as u8);
+        } else if n < 0x4000 {
+            out.extend_from_slice(&[TWO_BYTE | (n >> 8) as u8, n as u8]);
+        } else if n < 0x4000_0000 {
+            out.extend_from_slice(&[FOUR_BYTE | (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+        } else {
+            let bytes = &self.0[32 - self.significant_bytes().len().max(4)..];
+            out.push(MULTI_BYTE | (bytes.len() - 4) as u8);
+            out.extend_from_slice(bytes);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let (first, rest) = take_compact(input)?;
+        let mut bytes = [0u8; 32];
+        if first & MODE_MASK == MULTI_BYTE {
+            if rest.len() > 32 {
+                return Err(CodecError::Overflow);
+            }
+            bytes[32 - rest.len()..].copy_from_slice(rest);
+        } else {
+            bytes[31 - rest.len()] = first & !MODE_MASK;
+            bytes[32 - rest.len()..].copy_from_slice(rest);
+        }
+        Ok(U256(bytes))
+    }
+}
+
+impl Codec for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(take(input, 1)?[0])
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(CodecError::InvalidBool(other))
+        }
+    }
+}
+
+impl Codec for i32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_i32(*self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        decode_i32(input)
+    }
+}
+
+// hashes, keys and signatures: no length prefix
+impl<const N: usize> Codec for [u8; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        take_array(input)
+    }
+}
+
+// AVector, and so ByteString for Vec<u8>
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let length = decode_length(input)?;
+        // don't trust the length with an allocation, running out of input
+        // stops a bogus one soon enough
+        let mut items = Vec::new();
+        for _ in 0..length {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match bool::decode(input)? {
+            false => Ok(None),
+            true => Ok(Some(T::decode(input)?))
+        }
+    }
+}
+
+impl Codec for TokenId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(TokenId::new(take_array(input)?))
+    }
+}
+
+impl Codec for PublicKey {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        PublicKey::from_bytes(take(input, PUBLIC_KEY_LENGTH)?).map_err(CodecError::Key)
+    }
+}
+
+// a lockup script, the same bytes an address is the base58 of
+impl Codec for Address {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let start = *input;
+        match u8::decode(input)? {
+            0x00 | 0x02 | 0x03 => {
+                take(input, HASH_LENGTH)?;
+            }
+            0x01 => {
+                Vec::<Hash>::decode(input)?;
+                decode_i32(input)?;
+            }
+            prefix => return Err(CodecError::UnknownPrefix { kind: "lockup script", prefix })
+        }
+        // hand the bytes just read to the address parser for its checks
+        let script = &start[..start.len() - input.len()];
+        Address::from_bytes(script).map_err(CodecError::Address)
+    }
+}
+
+impl Codec for OutputRef {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.hint.to_be_bytes());
+        out.extend_from_slice(&self.key);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let hint = u32::from_be_bytes(take_array(input)?);
+        Ok(OutputRef { hint, key: take_array(input)? })
+    }
+}
+
+impl Codec for UnlockScript {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            UnlockScript::P2PKH(public_key) => {
+                out.push(0x00);
+                public_key.encode(out);
+            }
+            UnlockScript::P2MPKH(keys) => {
+                out.push(0x01);
+                encode_length(keys.len(), out);
+                for (public_key, index) in keys {
+                    public_key.encode(out);
+                    encode_length(*index, out);
+                }
+            }
+            UnlockScript::SameAsPrevious => out.push(0x03)
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            0x00 => Ok(UnlockScript::P2PKH(PublicKey::decode(input)?)),
+            0x01 => {
+                let length = decode_length(input)?;
+                let mut keys = Vec::new();
+                for _ in 0..length {
+                    keys.push((PublicKey::decode(input)?, decode_length(input)?));
+                }
+                Ok(UnlockScript::P2MPKH(keys))
+            }
+            // P2SH carries a whole script
+            0x02 => Err(CodecError::UnsupportedScript),
+            0x03 => Ok(UnlockScript::SameAsPrevious),
+            prefix => Err(CodecError::UnknownPrefix { kind: "unlock script", prefix })
+        }
+    }
+}
+
+impl Codec for TxInput {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.output_ref.encode(out);
+        self.unlock_script.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(TxInput { output_ref: OutputRef::decode(input)?, unlock_script: UnlockScript::decode(input)? })
+    }
+}
+
+fn decode_amount(input: &mut &[u8]) -> Result<u128, CodecError> {
+    U256::decode(input)?.to_u128().ok_or(CodecError::Overflow)
+}
+
+impl Codec for AssetOutput {
+    fn encode(&self, out: &mut Vec<u8>) {
+        U256::from(self.alph_amount).encode(out);
+        self.lockup_script.encode(out);
+        out.extend_from_slice(&self.lock_time.to_be_bytes());
+        encode_length(self.tokens.len(), out);
+        for (id, amount) in &self.tokens {
+            id.encode(out);
+            U256::from(*amount).encode(out);
+        }
+        self.additional_data.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let alph_amount = decode_amount(input)?;
+        let lockup_script = Address::decode(input)?;
+        if matches!(lockup_script, Address::P2C(_)) {
+            return Err(CodecError::ContractLockup);
+        }
+        let lock_time = u64::from_be_bytes(take_array(input)?);
+
+        let length = decode_length(input)?;
+        let mut tokens = Vec::new();
+        for _ in 0..length {
+            tokens.push((TokenId::decode(input)?, decode_amount(input)?));
+        }
+
+        Ok(AssetOutput { alph_amount, lockup_script, lock_time, tokens, additional_data: Vec::decode(input)? })
+    }
+}
+
+impl Codec for UnsignedTransaction {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.version);
+        out.push(self.network_id);
+        // no script: plain transfers only
+        out.push(0);
+        // the builder keeps gas far below i32::MAX
+        encode_i32(i32::try_from(self.gas_amount).unwrap_or(i32::MAX), out);
+        U256::from(self.gas_price).encode(out);
+        self.inputs.encode(out);
+        self.fixed_outputs.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let version = u8::decode(input)?;
+        let network_id = u8::decode(input)?;
+        if bool::decode(input)? {
+            return Err(CodecError::UnsupportedScript);
+        }
+        let gas_amount = decode_i32(input)?;
+        let gas_amount = u64::try_from(gas_amount).map_err(|_| CodecError::InvalidGas(gas_amount))?;
+
+        Ok(UnsignedTransaction {
+            version,
+            network_id,
+            gas_amount,
+            gas_price: decode_amount(input)?,
+            inputs: Vec::decode(input)?,
+            fixed_outputs: Vec::decode(input)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyPair;
+
+    fn encoded<T: Codec>(value: T) -> String {
+        hex::encode(serialize(&value))
+    }
+
+    fn round_trip<T: Codec + PartialEq + fmt::Debug>(value: T) {
+        assert_eq!(deserialize::<T>(&serialize(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_compact_signed_vectors() {
+        let vectors: [(i32, &str); 14] = [
+            (0, "00"),
+            (1, "01"),
+            (0x1f, "1f"),
+            (-1, "3f"),
+            (-0x20, "20"),
+            (0x20, "4020"),
+            (0x1fff, "5fff"),
+            (-0x21, "7fdf"),
+            (0x2000, "80002000"),
+            (20_000, "80004e20"),
+            (-0x2001, "bfffdfff"),
+            (0x2000_0000, "c020000000"),
+            (i32::MAX, "c07fffffff"),
+            (i32::MIN, "c080000000"),
+        ];
+        for (n, hex) in vectors {
+            assert_eq!(encoded(n), hex, "{}", n);
+            assert_eq!(deserialize::<i32>(&hex::decode(hex).unwrap()), Ok(n));
+        }
+    }
+
+    #[test]
+    fn test_compact_unsigned_vectors() {
+        let alph = 1_000_000_000_000_000_000u128;
+        let vectors: [(U256, &str); 11] = [
+            (U256::ZERO, "00"),
+            (U256::from(0x3f), "3f"),
+            (U256::from(0x40), "4040"),
+            (U256::from(0x3fff), "7fff"),
+            (U256::from(0x4000), "80004000"),
+            (U256::from(0x3fff_ffff), "bfffffff"),
+            (U256::from(0x4000_0000), "c040000000"),
+            (U256::from(u32::MAX as u128), "c0ffffffff"),
+            (U256::from(1 << 32), "c10100000000"),
+            // 100 nanoALPH and one ALPH
+            (U256::from(100_000_000_000), "c1174876e800"),
+            (U256::from(alph), "c40de0b6b3a7640000"),
+        ];
+        for (n, hex) in vectors {
+            assert_eq!(encoded(n), hex, "{}", n);
+            assert_eq!(deserialize::<U256>(&hex::decode(hex).unwrap()), Ok(n));
+        }
+        assert_eq!(encoded(U256::MAX), format!("dc{}", "ff".repeat(32)));
+        round_trip(U256::MAX);
+    }
+
+    #[test]
+    fn test_u256() {
+        assert_eq!(U256::from(12_345).to_string(), "12345");
+        assert_eq!(U256::ZERO.to_string(), "0");
+        assert_eq!(
+            U256::MAX.to_string(),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+        assert_eq!(U256::from(u64::MAX as u128).to_u64(), Some(u64::MAX));
+        assert_eq!(U256::from(u64::MAX as u128 + 1).to_u64(), None);
+        assert_eq!(U256::from(u128::MAX).to_u128(), Some(u128::MAX));
+        assert_eq!(U256::MAX.to_u128(), None);
+
+        let max = U256::MAX.to_string();
+        assert_eq!(U256::from_dec_str(&max), Some(U256::MAX));
+        assert_eq!(U256::from_dec_str("0"), Some(U256::ZERO));
+        assert_eq!(U256::from_dec_str("1000000000000000000"), Some(U256::from(1_000_000_000_000_000_000)));
+        // one past the max
+        assert_eq!(U256::from_dec_str(&max.replace("935", "936")), None);
+        assert_eq!(U256::from_dec_str(""), None);
+        assert_eq!(U256::from_dec_str("-1"), None);
+        assert_eq!(U256::from_dec_str("1e18"), None);
+    }
+
+    #[test]
+    fn test_containers() {
+        assert_eq!(encoded(vec![1u8, 2, 3]), "03010203");
+        assert_eq!(encoded(Vec::<u8>::new()), "00");
+        assert_eq!(encoded(Some(true)), "0101");
+        assert_eq!(encoded(None::<bool>), "00");
+        round_trip(vec![vec![1i32, -1_000_000], vec![]]);
+        round_trip(Some([7u8; 32]));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(deserialize::<i32>(&[]), Err(CodecError::UnexpectedEnd));
+        assert_eq!(deserialize::<i32>(&[0x80, 0x00]), Err(CodecError::UnexpectedEnd));
+        assert_eq!(deserialize::<i32>(&[0x01, 0x02]), Err(CodecError::TrailingBytes(1)));
+        // an int never takes more than 4 bytes
+        assert_eq!(deserialize::<i32>(&[0xc1, 0, 0, 0, 0, 1]), Err(CodecError::Overflow));
+        assert_eq!(deserialize::<U256>(&[&[0xdd][..], &[1; 33]].concat()), Err(CodecError::Overflow));
+        assert_eq!(deserialize::<bool>(&[2]), Err(CodecError::InvalidBool(2)));
+        assert_eq!(deserialize::<Vec<u8>>(&[0x3f]), Err(CodecError::InvalidLength(-1)));
+        assert_eq!(deserialize::<Vec<u8>>(&[0x05, 1, 2]), Err(CodecError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_lockup_scripts() {
+        let p2pkh = Address::P2PKH([0xaa; 32]);
+        assert_eq!(encoded(p2pkh.clone()), format!("00{}", "aa".repeat(32)));
+        round_trip(p2pkh);
+
+        let p2mpkh = Address::p2mpkh(&[&[2; 33], &[3; 33], &[4; 33]], 2).unwrap();
+        let bytes = serialize(&p2mpkh);
+        assert_eq!((bytes[1], bytes[bytes.len() - 1]), (3, 2));
+        round_trip(p2mpkh);
+
+        // a lockup script in the middle of a stream stops where it should
+        let mut stream = serialize(&Address::P2SH([1; 32]));
+        stream.push(0x42);
+        let mut input = &stream[..];
+        assert_eq!(Address::decode(&mut input), Ok(Address::P2SH([1; 32])));
+        assert_eq!(input, &[0x42]);
+
+        assert_eq!(deserialize::<Address>(&[0x04]), Err(CodecError::UnknownPrefix { kind: "lockup script", prefix: 4 }));
+    }
+
+    #[test]
+    fn test_unlock_scripts() {
+        let key = KeyPair::from_secret_bytes(&[1; 32]).unwrap().public_key();
+        assert_eq!(encoded(UnlockScript::P2PKH(key)), format!("00{}", key));
+        assert_eq!(encoded(UnlockScript::SameAsPrevious), "03");
+        round_trip(UnlockScript::P2MPKH(vec![(key, 0), (key, 2)]));
+        assert_eq!(deserialize::<UnlockScript>(&[0x02]), Err(CodecError::UnsupportedScript));
+    }
+
+    #[test]
+    fn test_asset_output() {
+        let output = AssetOutput::new(1_000_000_000_000_000_000, Address::P2PKH([0xbb; 32]))
+            .with_lock_time(1_700_000_000_000)
+            .with_tokens(vec![(TokenId::new([0xcc; 32]), 100)]);
+        let output = AssetOutput { additional_data: vec![0xde, 0xad], ..output };
+        let expected = [
+            "c40de0b6b3a7640000",
+            &format!("00{}", "bb".repeat(32)),
+            "0000018bcfe56800",
+            &format!("01{}", "cc".repeat(32)),
+            "4064",
+            "02dead",
+        ]
+        .concat();
+        assert_eq!(encoded(output.clone()), expected);
+        round_trip(output);
+
+        let contract = AssetOutput::new(1, Address::P2C(crate::contract::ContractId::new([1; 32])));
+        assert_eq!(deserialize::<AssetOutput>(&serialize(&contract)), Err(CodecError::ContractLockup));
+    }
+
+    #[test]
+    fn test_unsigned_transaction() {
+        let key = KeyPair::from_secret_bytes(&[1; 32]).unwrap().public_key();
+        let tx = UnsignedTransaction {
+            version: 0,
+            network_id: 0,
+            gas_amount: 20_000,
+            gas_price: 100_000_000_000,
+            inputs: vec![
+                TxInput { output_ref: OutputRef { hint: 0x01020304, key: [0x11; 32] }, unlock_script: UnlockScript::P2PKH(key) },
+                TxInput { output_ref: OutputRef { hint: 0x05060708, key: [0x22; 32] }, unlock_script: UnlockScript::SameAsPrevious },
+            ],
+            fixed_outputs: vec![AssetOutput::new(1_000_000_000_000_000_000, Address::P2PKH([0xbb; 32]))]
+        };
+        let expected = [
+            // version, network, no script, gas amount, gas price
+            "00", "00", "00", "80004e20", "c1174876e800",
+            "02",
+            &format!("01020304{}00{}", "11".repeat(32), key),
+            &format!("05060708{}03", "22".repeat(32)),
+            "01",
+            &format!("c40de0b6b3a7640000 00{} 0000000000000000 00 00", "bb".repeat(32)).replace(' ', ""),
+        ]
+        .concat();
+        assert_eq!(encoded(tx.clone()), expected);
+        round_trip(tx.clone());
+
+        let mut scripted = serialize(&tx);
+        scripted[2] = 1;
+        assert_eq!(deserialize::<UnsignedTransaction>(&scripted), Err(CodecError::UnsupportedScript));
+    }
+}
@@ -0,0 +1,207 @@
+// contract and token ids
+
+// a contract's id is fixed by the transaction output that created it:
+//
+//   blake2b(tx id | output index as 4 big endian bytes), last byte = group
+//
+// a token issued by a contract shares the contract's id, and ALPH itself is
+// the all-zero id.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::address::{blake2b, Hash, HASH_LENGTH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdError {
+    InvalidHex,
+    InvalidLength(usize)
+}
+
+fn parse_hex(s: &str) -> Result<Hash, IdError> {
+    let bytes = hex::decode(s).map_err(|_| IdError::InvalidHex)?;
+    let length = bytes.len();
+    bytes.try_into().map_err(|_| IdError::InvalidLength(length))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContractId(Hash);
+
+impl ContractId {
+
+    pub const fn new(bytes: Hash) -> Self {
+        ContractId(bytes)
+    }
+
+    // id of the contract created by output `output_index` of transaction `tx_id`
+    pub fn from_creation(tx_id: &Hash, output_index: u32, group: u8) -> Self {
+        let mut preimage = [0u8; HASH_LENGTH + 4];
+        preimage[..HASH_LENGTH].copy_from_slice(tx_id);
+        preimage[HASH_LENGTH..].copy_from_slice(&output_index.to_be_bytes());
+
+        let mut id = blake2b(&preimage);
+        id[HASH_LENGTH - 1] = group;
+        ContractId(id)
+    }
+
+    pub fn as_bytes(&self) -> &Hash {
+        &self.0
+    }
+
+    // the group the contract lives in
+    pub fn group(&self) -> u8 {
+        self.0[HASH_LENGTH - 1]
+    }
+}
+
+impl fmt::Display for ContractId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl FromStr for ContractId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(ContractId)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TokenId(Hash);
+
+impl TokenId {
+
+    pub const ALPH: TokenId = TokenId([0; HASH_LENGTH]);
+
+    pub const fn new(bytes: Hash) -> Self {
+        TokenId(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &Hash {
+        &self.0
+    }
+
+    pub fn is_alph(&self) -> bool {
+        *self == TokenId::ALPH
+    }
+
+    // the contract that issued the token
+    pub fn contract_id(&self) -> ContractId {
+        ContractId(self.0)
+    }
+}
+
+impl From<ContractId> for TokenId {
+    fn from(id: ContractId) -> Self {
+        TokenId(id.0)
+    }
+}
+
+impl fmt::Display for TokenId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl FromStr for TokenId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(TokenId)
+    }
+}
+
+// hex in json and other human readable formats, raw bytes otherwise
+macro_rules! serde_as_hex {
+    ($id:ident) => {
+        impl Serialize for $id {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&self.to_string())
+                } else {
+                    self.0.serialize(serializer)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $id {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    let s = String::deserialize(deserializer)?;
+                    s.parse().map_err(|e| serde::de::Error::custom(format!("invalid id `{}`: {:?}", s, e)))
+                } else {
+                    Hash::deserialize(deserializer).map($id)
+                }
+            }
+        }
+    };
+}
+
+serde_as_hex!(ContractId);
+serde_as_hex!(TokenId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        let id = TokenId::new([0xab; 32]);
+        let hex = id.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex.parse::<TokenId>().unwrap(), id);
+        assert_eq!(hex.to_uppercase().parse::<TokenId>().unwrap(), id);
+    }
+
+    #[test]
+    fn test_invalid_hex() {
+        assert_eq!("zz".parse::<TokenId>(), Err(IdError::InvalidHex));
+        assert_eq!("abcd".parse::<ContractId>(), Err(IdError::InvalidLength(2)));
+    }
+
+    #[test]
+    fn test_serde() {
+        let id = TokenId::new([0xab; 32]);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", id));
+        assert_eq!(serde_json::from_str::<TokenId>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<TokenId>("\"abcd\"").is_err());
+
+        let bytes = postcard::to_allocvec(&id).unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(postcard::from_bytes::<TokenId>(&bytes).unwrap(), id);
+    }
+
+    #[test]
+    fn test_alph_id() {
+        assert!(TokenId::ALPH.is_alph());
+        assert_eq!(TokenId::ALPH.to_string(), "0".repeat(64));
+    }
+
+    #[test]
+    fn test_contract_id_from_creation() {
+        let tx_id = [0x11; 32];
+        let id = ContractId::from_creation(&tx_id, 0, 2);
+
+        let mut preimage = tx_id.to_vec();
+        preimage.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(id.as_bytes()[..31], blake2b(&preimage)[..31]);
+        assert_eq!(id.group(), 2);
+
+        // a different output or group is a different contract
+        assert_ne!(ContractId::from_creation(&tx_id, 1, 2), id);
+        assert_ne!(ContractId::from_creation(&tx_id, 0, 3), id);
+    }
+
+    #[test]
+    fn test_token_id_is_contract_id() {
+        let contract_id = ContractId::from_creation(&[0x22; 32], 1, 0);
+        let token_id = TokenId::from(contract_id);
+        assert_eq!(token_id.contract_id(), contract_id);
+        assert_eq!(token_id.to_string(), contract_id.to_string());
+    }
+}
@@ -0,0 +1,238 @@
+// dex pair state
+
+// turns the state of a deployed pair contract into a `Swap`, so quotes and
+// simulations can run on real pools. the state comes from the node, live
+// (NodeClient::contract_state) or as a saved dump (ContractState::from_json),
+// and a layout says where the pair keeps each value:
+//
+//   tokens          ByteVec fields holding the token ids
+//   reserves        U256 fields
+//   total supply    U256 field, of the LP token
+//   fee             a U256 field in basis points, or fixed by the contract
+//
+// pairs don't tally the fees they've collected the way our pools do, so the
+// decoded swap starts with fee_one and fee_two at zero. amounts are u128 in
+// this crate; a pair holding more than that can't be imported.
+
+use crate::abi::Val;
+use crate::address::HASH_LENGTH;
+use crate::contract::TokenId;
+use crate::node::{ContractState, NodeError};
+use crate::swap::Swap;
+
+const MAX_FEE: u64 = 10_000;
+
+#[derive(Debug)]
+pub enum PairError {
+    // the dump itself didn't parse
+    State(NodeError),
+    MissingField(Field),
+    WrongType { field: Field, expected: &'static str },
+    // a token id field that isn't 32 bytes
+    InvalidTokenId(Field),
+    // past u128, or a fee field past u64
+    Overflow(Field),
+    SameToken(TokenId),
+    // basis points, so at most 10000
+    InvalidFee(u64)
+}
+
+// where a value lives in the contract's fields, by declaration order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Immutable(usize),
+    Mutable(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeSource {
+    // basis points, for contracts where it's a constant
+    Fixed(u64),
+    Field(Field)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairLayout {
+    pub token_one: Field,
+    pub token_two: Field,
+    pub reserve_one: Field,
+    pub reserve_two: Field,
+    pub total_supply: Field,
+    pub fee: FeeSource
+}
+
+impl PairLayout {
+
+    // TokenPair of the alephium dex:
+    //
+    //   tokenPairFactory, token0Id, token1Id,
+    //   mut reserve0, mut reserve1, mut blockTimeStampLast,
+    //   mut price0CumulativeLast, mut price1CumulativeLast,
+    //   mut totalSupply, mut kLast, mut feeCollectorId
+    //
+    // swaps pay a fixed 0.3%, and token0 is the smaller id so ALPH always
+    // comes first
+    pub const ALEPHIUM_DEX: PairLayout = PairLayout {
+        token_one: Field::Immutable(1),
+        token_two: Field::Immutable(2),
+        reserve_one: Field::Mutable(0),
+        reserve_two: Field::Mutable(1),
+        total_supply: Field::Mutable(5),
+        fee: FeeSource::Fixed(30)
+    };
+
+    pub fn decode(&self, state: &ContractState) -> Result<Swap, PairError> {
+        let token_one = token_id(state, self.token_one)?;
+        let token_two = token_id(state, self.token_two)?;
+        if token_one == token_two {
+            return Err(PairError::SameToken(token_one));
+        }
+        let fee = match self.fee {
+            FeeSource::Fixed(fee) => fee,
+            FeeSource::Field(field) => amount(state, field)?.try_into().map_err(|_| PairError::Overflow(field))?
+        };
+        if fee > MAX_FEE {
+            return Err(PairError::InvalidFee(fee));
+        }
+
+        Ok(Swap {
+            token_one,
+            token_two,
+            reserve_one: amount(state, self.reserve_one)?,
+            reserve_two: amount(state, self.reserve_two)?,
+            fee,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: amount(state, self.total_supply)?
+        })
+    }
+
+    // a dump of GET /contracts/{address}/state
+    pub fn decode_json(&self, json: &str) -> Result<Swap, PairError> {
+        self.decode(&ContractState::from_json(json).map_err(PairError::State)?)
+    }
+}
+
+fn field(state: &ContractState, field: Field) -> Result<&Val, PairError> {
+    let value = match field {
+        Field::Immutable(index) => state.immutable_fields.get(index),
+        Field::Mutable(index) => state.mutable_fields.get(index)
+    };
+    value.ok_or(PairError::MissingField(field))
+}
+
+fn amount(state: &ContractState, at: Field) -> Result<u128, PairError> {
+    field(state, at)?
+        .as_u256()
+        .ok_or(PairError::WrongType { field: at, expected: "U256" })?
+        .to_u128()
+        .ok_or(PairError::Overflow(at))
+}
+
+fn token_id(state: &ContractState, at: Field) -> Result<TokenId, PairError> {
+    let bytes = field(state, at)?.as_byte_vec().ok_or(PairError::WrongType { field: at, expected: "ByteVec" })?;
+    let id: [u8; HASH_LENGTH] = bytes.try_into().map_err(|_| PairError::InvalidTokenId(at))?;
+    Ok(TokenId::new(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::U256;
+    use crate::node::mock::{MockNode, Route};
+    use crate::node::NodeClient;
+
+    const ALPH_USDT: &str = include_str!("fixtures/alph_usdt_pair.json");
+    const USDT: &str = "625096cf33295c0ec60aa39350cbaf54b3a3fc0ee8966059724b99d2ec00c1db";
+
+    fn create_test_state() -> ContractState {
+        ContractState::from_json(ALPH_USDT).unwrap()
+    }
+
+    #[test]
+    fn test_decode_alephium_dex_pair() {
+        // 5,000 ALPH against 1,000 USDT, 6 decimals
+        let swap = PairLayout::ALEPHIUM_DEX.decode_json(ALPH_USDT).unwrap();
+        assert_eq!(swap.token_one, TokenId::ALPH);
+        assert_eq!(swap.token_two, USDT.parse().unwrap());
+        assert_eq!((swap.reserve_one, swap.reserve_two), (5_000_000_000_000_000_000_000, 1_000_000_000));
+        assert_eq!(swap.total_supply, 2_236_067_977_499_789);
+        assert_eq!((swap.fee, swap.fee_one, swap.fee_two), (30, 0, 0));
+
+        // and it's a pool like any other: 1 ALPH buys just under 0.2 USDT
+        let mut swap = swap;
+        let out = swap.swap(1_000_000_000_000_000_000, true).unwrap();
+        assert_eq!(out, 199_360);
+        assert_eq!(swap.reserve_two, 1_000_000_000 - out);
+        assert_eq!(swap.fee_one, 3_000_000_000_000_000);
+    }
+
+    #[test]
+    fn test_liquidity_on_decoded_pair() {
+        let mut swap = PairLayout::ALEPHIUM_DEX.decode_json(ALPH_USDT).unwrap();
+        let before = swap.clone();
+
+        // 50 ALPH and 10 USDT is 1% of the pool
+        let liquidity = swap.add_liquidity(50_000_000_000_000_000_000, 10_000_000).unwrap();
+        assert_eq!(liquidity, 22_360_679_774_997);
+        assert_eq!((swap.reserve_one, swap.reserve_two), (5_050_000_000_000_000_000_000, 1_010_000_000));
+        assert_eq!(swap.total_supply, before.total_supply + liquidity);
+
+        // redeeming rounds down, so the pool keeps the dust
+        let (amount_one, amount_two) = swap.remove_liquidity(liquidity).unwrap();
+        assert_eq!((amount_one, amount_two), (49_999_999_999_998_029_603, 9_999_999));
+        assert_eq!(swap.total_supply, before.total_supply);
+        assert!(swap.reserve_one > before.reserve_one && swap.reserve_two > before.reserve_two);
+    }
+
+    #[test]
+    fn test_custom_layout() {
+        // a pair keeping its fee in a field
+        let layout = PairLayout { fee: FeeSource::Field(Field::Mutable(2)), ..PairLayout::ALEPHIUM_DEX };
+        let mut state = create_test_state();
+        state.mutable_fields[2] = Val::U256(U256::from(25));
+        assert_eq!(layout.decode(&state).unwrap().fee, 25);
+
+        state.mutable_fields[2] = Val::U256(U256::from(10_001));
+        assert!(matches!(layout.decode(&state), Err(PairError::InvalidFee(10_001))));
+        let layout = PairLayout { fee: FeeSource::Fixed(10_001), ..PairLayout::ALEPHIUM_DEX };
+        assert!(matches!(layout.decode(&create_test_state()), Err(PairError::InvalidFee(10_001))));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let layout = PairLayout::ALEPHIUM_DEX;
+
+        let mut state = create_test_state();
+        state.mutable_fields.truncate(5);
+        assert!(matches!(layout.decode(&state), Err(PairError::MissingField(Field::Mutable(5)))));
+
+        let mut state = create_test_state();
+        state.immutable_fields[2] = Val::U256(U256::from(1));
+        assert!(matches!(layout.decode(&state), Err(PairError::WrongType { field: Field::Immutable(2), expected: "ByteVec" })));
+
+        let mut state = create_test_state();
+        state.immutable_fields[2] = Val::ByteVec(vec![0x62; 31]);
+        assert!(matches!(layout.decode(&state), Err(PairError::InvalidTokenId(Field::Immutable(2)))));
+
+        let mut state = create_test_state();
+        state.immutable_fields[2] = state.immutable_fields[1].clone();
+        assert!(matches!(layout.decode(&state), Err(PairError::SameToken(id)) if id.is_alph()));
+
+        // a pool deeper than this crate's amounts
+        let mut state = create_test_state();
+        state.mutable_fields[0] = Val::U256(U256::from_dec_str("340282366920938463463374607431768211456").unwrap());
+        assert!(matches!(layout.decode(&state), Err(PairError::Overflow(Field::Mutable(0)))));
+
+        assert!(matches!(layout.decode_json("{}"), Err(PairError::State(NodeError::Json(_)))));
+    }
+
+    #[test]
+    fn test_live_pair() {
+        // the same dump, served by the mock node
+        let pair = create_test_state().address;
+        let node = MockNode::start(vec![Route::get(&format!("/contracts/{}/state", pair), ALPH_USDT)]);
+        let state = NodeClient::new(node.url()).contract_state(&pair).unwrap();
+        assert_eq!(PairLayout::ALEPHIUM_DEX.decode(&state).unwrap(), PairLayout::ALEPHIUM_DEX.decode_json(ALPH_USDT).unwrap());
+    }
+}
@@ -0,0 +1,264 @@
+// gas and fee estimation
+
+// every transaction pays gas amount * gas price in ALPH. the gas amount
+// follows the node's schedule for the transaction itself
+//
+//   base       1000 per transaction
+//   inputs     2000 each
+//   signature  2060, checked once: later inputs unlock as SameAsPrevious
+//   outputs    4500 each
+//
+// on top of which a contract call pays for running its script. the node
+// measures that by emulating the call; the numbers below are what the DEX
+// methods cost on a typical pool, rounded up. whatever the sum, a
+// transaction never pays for less than the minimal gas.
+
+use crate::amount::Amount;
+use crate::swap::stable_swap::NEWTON_ITERATIONS;
+use crate::swap::Pool;
+use crate::transaction::{MAXIMAL_GAS_PER_TX, MINIMAL_GAS, MIN_GAS_PRICE};
+
+pub const TX_BASE_GAS: u64 = 1_000;
+pub const TX_INPUT_BASE_GAS: u64 = 2_000;
+pub const TX_OUTPUT_BASE_GAS: u64 = 4_500;
+pub const P2PKH_UNLOCK_GAS: u64 = 2_060;
+
+const SWAP_GAS: u64 = 30_000;
+const ADD_LIQUIDITY_GAS: u64 = 45_000;
+const REMOVE_LIQUIDITY_GAS: u64 = 40_000;
+// stable pools solve for the new reserve with newton's method on chain
+const NEWTON_ITERATION_GAS: u64 = 1_200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeeError {
+    GasPriceTooLow(u128),
+    GasOutOfRange(u64),
+    Overflow
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Transfer,
+    TokenTransfer,
+    Swap,
+    AddLiquidity,
+    RemoveLiquidity
+}
+
+impl Operation {
+
+    // gas for running the contract call, on a constant product pool
+    pub fn script_gas(&self) -> u64 {
+        match self {
+            Operation::Transfer | Operation::TokenTransfer => 0,
+            Operation::Swap => SWAP_GAS,
+            Operation::AddLiquidity => ADD_LIQUIDITY_GAS,
+            Operation::RemoveLiquidity => REMOVE_LIQUIDITY_GAS
+        }
+    }
+
+    // (inputs, outputs) of the usual transaction: the ALPH input plus one
+    // carrying tokens where tokens are spent, a payment and the change
+    pub fn typical_shape(&self) -> (usize, usize) {
+        match self {
+            Operation::Transfer | Operation::RemoveLiquidity => (1, 2),
+            Operation::TokenTransfer | Operation::Swap | Operation::AddLiquidity => (2, 2)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fee {
+    gas_amount: u64,
+    gas_price: u128,
+    total: u128
+}
+
+impl Fee {
+
+    // for gas figures that come from elsewhere, like the node's own estimate
+    pub fn new(gas_amount: u64, gas_price: u128) -> Result<Self, FeeError> {
+        if gas_price < MIN_GAS_PRICE {
+            return Err(FeeError::GasPriceTooLow(gas_price));
+        }
+        if !(MINIMAL_GAS..=MAXIMAL_GAS_PER_TX).contains(&gas_amount) {
+            return Err(FeeError::GasOutOfRange(gas_amount));
+        }
+        let total = (gas_amount as u128).checked_mul(gas_price).ok_or(FeeError::Overflow)?;
+        Ok(Fee { gas_amount, gas_price, total })
+    }
+
+    pub fn gas_amount(&self) -> u64 {
+        self.gas_amount
+    }
+
+    pub fn gas_price(&self) -> u128 {
+        self.gas_price
+    }
+
+    // in attoALPH
+    pub fn total(&self) -> u128 {
+        self.total
+    }
+
+    pub fn to_alph(&self) -> Amount {
+        Amount::alph(self.total)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeEstimator {
+    gas_price: u128,
+    minimal_gas: u64
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        FeeEstimator { gas_price: MIN_GAS_PRICE, minimal_gas: MINIMAL_GAS }
+    }
+}
+
+impl FeeEstimator {
+
+    // the node refuses gas below its own minimums, so only raise them
+    pub fn new(gas_price: u128, minimal_gas: u64) -> Result<Self, FeeError> {
+        if gas_price < MIN_GAS_PRICE {
+            return Err(FeeError::GasPriceTooLow(gas_price));
+        }
+        if !(MINIMAL_GAS..=MAXIMAL_GAS_PER_TX).contains(&minimal_gas) {
+            return Err(FeeError::GasOutOfRange(minimal_gas));
+        }
+        Ok(FeeEstimator { gas_price, minimal_gas })
+    }
+
+    pub fn gas_price(&self) -> u128 {
+        self.gas_price
+    }
+
+    pub fn minimal_gas(&self) -> u64 {
+        self.minimal_gas
+    }
+
+    // gas for a transaction with `inputs` P2PKH inputs and `outputs` fixed outputs
+    pub fn gas(&self, operation: Operation, inputs: usize, outputs: usize) -> u64 {
+        self.with_script_gas(operation.script_gas(), inputs, outputs)
+    }
+
+    fn with_script_gas(&self, script_gas: u64, inputs: usize, outputs: usize) -> u64 {
+        let gas = TX_BASE_GAS
+            .saturating_add(TX_INPUT_BASE_GAS.saturating_mul(inputs as u64))
+            .saturating_add(if inputs > 0 { P2PKH_UNLOCK_GAS } else { 0 })
+            .saturating_add(TX_OUTPUT_BASE_GAS.saturating_mul(outputs as u64))
+            .saturating_add(script_gas);
+        gas.max(self.minimal_gas)
+    }
+
+    pub fn estimate(&self, operation: Operation, inputs: usize, outputs: usize) -> Result<Fee, FeeError> {
+        self.fee(self.gas(operation, inputs, outputs))
+    }
+
+    // for when the inputs haven't been picked yet
+    pub fn estimate_typical(&self, operation: Operation) -> Result<Fee, FeeError> {
+        let (inputs, outputs) = operation.typical_shape();
+        self.estimate(operation, inputs, outputs)
+    }
+
+    // gas is never below the minimal gas here, so only the upper bound can fail
+    fn fee(&self, gas_amount: u64) -> Result<Fee, FeeError> {
+        Fee::new(gas_amount, self.gas_price)
+    }
+}
+
+impl Pool {
+
+    // what calling `operation` on this pool costs, in a typically shaped
+    // transaction. stable pools pay for their newton iterations on swaps
+    pub fn estimate_fee(&self, estimator: &FeeEstimator, operation: Operation) -> Result<Fee, FeeError> {
+        let script_gas = match (self, operation) {
+            (Pool::StableSwap(_), Operation::Swap) => SWAP_GAS + NEWTON_ITERATION_GAS * NEWTON_ITERATIONS as u64,
+            _ => operation.script_gas()
+        };
+        let (inputs, outputs) = operation.typical_shape();
+        estimator.fee(estimator.with_script_gas(script_gas, inputs, outputs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::TokenId;
+    use crate::swap::{StableSwap, Swap};
+
+    fn create_test_pools() -> (Pool, Pool) {
+        let (token_one, token_two) = (TokenId::ALPH, TokenId::new([0x17; 32]));
+        let swap = Swap { token_one, token_two, reserve_one: 1_000, reserve_two: 2_000, fee: 30, fee_one: 0, fee_two: 0, total_supply: 0 };
+        let stable = StableSwap { token_one, token_two, reserve_one: 1_000, reserve_two: 1_000, fee: 4, fee_one: 0, fee_two: 0, total_supply: 0 };
+        (Pool::Swap(swap), Pool::StableSwap(stable))
+    }
+
+    #[test]
+    fn test_small_transfers_pay_minimal_gas() {
+        let estimator = FeeEstimator::default();
+        // 1000 + 4060 + 2 * 4500 = 14060
+        assert_eq!(estimator.gas(Operation::Transfer, 1, 2), MINIMAL_GAS);
+
+        let fee = estimator.estimate_typical(Operation::Transfer).unwrap();
+        assert_eq!(fee.total(), 2_000_000_000_000_000);
+        assert_eq!(fee.to_alph().to_string(), "0.002");
+    }
+
+    #[test]
+    fn test_gas_grows_with_inputs_and_outputs() {
+        let estimator = FeeEstimator::default();
+        assert_eq!(estimator.gas(Operation::Transfer, 5, 3), 1_000 + 5 * 2_000 + 2_060 + 3 * 4_500);
+        assert_eq!(estimator.gas(Operation::Transfer, 5, 4) - estimator.gas(Operation::Transfer, 5, 3), TX_OUTPUT_BASE_GAS);
+        assert_eq!(estimator.gas(Operation::Swap, 2, 2), 1_000 + 2 * 2_000 + 2_060 + 2 * 4_500 + SWAP_GAS);
+        // the signature is checked once however many inputs there are
+        assert_eq!(estimator.gas(Operation::Transfer, 6, 3) - estimator.gas(Operation::Transfer, 5, 3), TX_INPUT_BASE_GAS);
+
+        assert_eq!(estimator.estimate(Operation::Transfer, 5_000, 2), Err(FeeError::GasOutOfRange(1_000 + 5_000 * 2_000 + 2_060 + 9_000)));
+    }
+
+    #[test]
+    fn test_configuration() {
+        let estimator = FeeEstimator::new(2 * MIN_GAS_PRICE, 50_000).unwrap();
+        let fee = estimator.estimate_typical(Operation::Transfer).unwrap();
+        assert_eq!((fee.gas_amount(), fee.gas_price()), (50_000, 2 * MIN_GAS_PRICE));
+        assert_eq!(fee.to_alph().to_string(), "0.01");
+
+        assert_eq!(FeeEstimator::new(MIN_GAS_PRICE - 1, MINIMAL_GAS), Err(FeeError::GasPriceTooLow(MIN_GAS_PRICE - 1)));
+        assert_eq!(FeeEstimator::new(MIN_GAS_PRICE, 1_000), Err(FeeError::GasOutOfRange(1_000)));
+        assert_eq!(
+            FeeEstimator::new(u128::MAX, MINIMAL_GAS).unwrap().estimate_typical(Operation::Transfer),
+            Err(FeeError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_fee_from_gas() {
+        let fee = Fee::new(30_000, MIN_GAS_PRICE).unwrap();
+        assert_eq!(fee.total(), 3_000_000_000_000_000);
+        assert_eq!(Fee::new(30_000, MIN_GAS_PRICE - 1), Err(FeeError::GasPriceTooLow(MIN_GAS_PRICE - 1)));
+        assert_eq!(Fee::new(MINIMAL_GAS - 1, MIN_GAS_PRICE), Err(FeeError::GasOutOfRange(MINIMAL_GAS - 1)));
+        assert_eq!(Fee::new(MAXIMAL_GAS_PER_TX + 1, MIN_GAS_PRICE), Err(FeeError::GasOutOfRange(MAXIMAL_GAS_PER_TX + 1)));
+        assert_eq!(Fee::new(MAXIMAL_GAS_PER_TX, u128::MAX), Err(FeeError::Overflow));
+    }
+
+    #[test]
+    fn test_pool_operations() {
+        let estimator = FeeEstimator::default();
+        let (swap, stable) = create_test_pools();
+
+        let swap_fee = swap.estimate_fee(&estimator, Operation::Swap).unwrap();
+        assert_eq!(swap_fee, estimator.estimate_typical(Operation::Swap).unwrap());
+        assert!(stable.estimate_fee(&estimator, Operation::Swap).unwrap().total() > swap_fee.total());
+
+        // only swaps iterate
+        assert_eq!(
+            stable.estimate_fee(&estimator, Operation::AddLiquidity),
+            swap.estimate_fee(&estimator, Operation::AddLiquidity)
+        );
+        let remove = swap.estimate_fee(&estimator, Operation::RemoveLiquidity).unwrap();
+        assert!(remove.total() > estimator.estimate_typical(Operation::Transfer).unwrap().total());
+    }
+}
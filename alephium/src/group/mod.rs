@@ -0,0 +1,222 @@
+// address groups (shards)
+
+// asset addresses land in a group through a "script hint" of their first
+// hash:
+//
+//   hint  = djb(hash) | 1
+//   group = (hint's four bytes xor'd together) % groups
+//
+// contracts pick their group when they are created, and carry it in the
+// last byte of their id.
+
+use crate::address::{Address, Hash};
+
+pub const DEFAULT_GROUPS: u32 = 4;
+
+// generous enough that a bad key source fails instead of spinning forever;
+// a fair source needs ~groups tries on average
+const MAX_ATTEMPTS_PER_GROUP: u32 = 1_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupError {
+    InvalidGroupCount(u32),
+    InvalidGroup(u32),
+    // the key source never produced an address in the group
+    Exhausted,
+    // a P2MPKH address without keys has no hash to hint with
+    EmptyMultisig
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroupConfig {
+    groups: u32
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        GroupConfig { groups: DEFAULT_GROUPS }
+    }
+}
+
+pub fn script_hint(hash: &Hash) -> u32 {
+    let djb = hash.iter().fold(5381u32, |h, b| h.wrapping_mul(33).wrapping_add(*b as u32));
+    djb | 1
+}
+
+// the hint an output locked by `address` carries, so nodes can route it to a
+// group without decoding the script. contracts clear the low bit instead
+pub fn address_hint(address: &Address) -> Result<u32, GroupError> {
+    match address {
+        Address::P2PKH(hash) | Address::P2SH(hash) => Ok(script_hint(hash)),
+        Address::P2MPKH { public_key_hashes, .. } => {
+            public_key_hashes.first().map(script_hint).ok_or(GroupError::EmptyMultisig)
+        }
+        Address::P2C(contract_id) => Ok(script_hint(contract_id.as_bytes()) & !1)
+    }
+}
+
+fn xor_bytes(n: u32) -> u32 {
+    n.to_be_bytes().iter().fold(0, |acc, b| acc ^ b) as u32
+}
+
+impl GroupConfig {
+
+    // group indices are a single byte on chain
+    pub fn new(groups: u32) -> Result<Self, GroupError> {
+        if groups == 0 || groups > 256 {
+            return Err(GroupError::InvalidGroupCount(groups));
+        }
+        Ok(GroupConfig { groups })
+    }
+
+    pub fn groups(&self) -> u32 {
+        self.groups
+    }
+
+    pub fn group_of(&self, address: &Address) -> Result<u32, GroupError> {
+        match address {
+            Address::P2C(contract_id) => Ok(contract_id.group() as u32 % self.groups),
+            _ => Ok(xor_bytes(address_hint(address)?) % self.groups)
+        }
+    }
+
+    // keep drawing public keys until one's P2PKH address is in `group`
+    pub fn p2pkh_in_group<F>(&self, group: u32, mut next_public_key: F) -> Result<(Vec<u8>, Address), GroupError>
+    where
+        F: FnMut() -> Vec<u8>
+    {
+        if group >= self.groups {
+            return Err(GroupError::InvalidGroup(group));
+        }
+
+        for _ in 0..MAX_ATTEMPTS_PER_GROUP * self.groups {
+            let public_key = next_public_key();
+            let address = Address::p2pkh(&public_key);
+            if self.group_of(&address)? == group {
+                return Ok((public_key, address));
+            }
+        }
+        Err(GroupError::Exhausted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::contract::ContractId;
+
+    // a deterministic stand-in for a key generator
+    fn counting_keys() -> impl FnMut() -> Vec<u8> {
+        let mut n = 0u32;
+        move || {
+            n += 1;
+            let mut key = vec![0x02; 33];
+            key[1..5].copy_from_slice(&n.to_be_bytes());
+            key
+        }
+    }
+
+    #[test]
+    fn test_script_hint() {
+        assert_eq!(xor_bytes(0x1234_5678), 0x12 ^ 0x34 ^ 0x56 ^ 0x78);
+
+        // every hint is odd
+        assert_eq!(script_hint(&[0; 32]) & 1, 1);
+        assert_eq!(script_hint(&[0xff; 32]) & 1, 1);
+    }
+
+    #[test]
+    fn test_group_of_hashes() {
+        let config = GroupConfig::default();
+        let hash = [7u8; 32];
+        let expected = xor_bytes(script_hint(&hash)) % 4;
+
+        assert_eq!(config.group_of(&Address::P2PKH(hash)).unwrap(), expected);
+        assert_eq!(config.group_of(&Address::P2SH(hash)).unwrap(), expected);
+        assert_eq!(config.group_of(&Address::P2MPKH { public_key_hashes: vec![hash, [1; 32]], m: 1 }).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_contract_group_from_id() {
+        let config = GroupConfig::default();
+        let id = ContractId::from_creation(&[0x33; 32], 0, 3);
+        assert_eq!(config.group_of(&Address::contract(id)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_groups_are_spread() {
+        let config = GroupConfig::default();
+        let mut keys = counting_keys();
+        let mut counts = [0; 4];
+        for _ in 0..400 {
+            counts[config.group_of(&Address::p2pkh(&keys())).unwrap() as usize] += 1;
+        }
+        assert!(counts.iter().all(|c| *c > 50), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_p2pkh_in_group() {
+        let config = GroupConfig::new(4).unwrap();
+        for group in 0..4 {
+            let (public_key, address) = config.p2pkh_in_group(group, counting_keys()).unwrap();
+            assert_eq!(address, Address::p2pkh(&public_key));
+            assert_eq!(config.group_of(&address).unwrap(), group);
+        }
+    }
+
+    #[test]
+    fn test_account_group() {
+        let config = GroupConfig::default();
+        let mut account = Account::new(1_000, vec![]);
+        assert_eq!(account.group(&config), None);
+
+        let public_key = account.generate_address_in_group(&config, 2, counting_keys()).unwrap();
+        assert_eq!(account.address(), Some(&Address::p2pkh(&public_key)));
+        assert_eq!(account.group(&config), Some(2));
+
+        // the same address can sit in a different group on a network with more of them
+        let wider = GroupConfig::new(16).unwrap();
+        assert!(account.group(&wider).unwrap() < 16);
+
+        // a failed search leaves the old address alone
+        assert_eq!(account.generate_address_in_group(&config, 9, counting_keys()), Err(GroupError::InvalidGroup(9)));
+        assert_eq!(account.group(&config), Some(2));
+    }
+
+    #[test]
+    fn test_group_errors() {
+        assert_eq!(GroupConfig::new(0), Err(GroupError::InvalidGroupCount(0)));
+        assert_eq!(GroupConfig::new(257), Err(GroupError::InvalidGroupCount(257)));
+
+        let config = GroupConfig::default();
+        assert_eq!(config.p2pkh_in_group(4, counting_keys()), Err(GroupError::InvalidGroup(4)));
+
+        // the same key over and over can only ever land in one group
+        let key = vec![0x02; 33];
+        let stuck = config.group_of(&Address::p2pkh(&key)).unwrap();
+        let other = (stuck + 1) % 4;
+        assert_eq!(config.p2pkh_in_group(other, || key.clone()), Err(GroupError::Exhausted));
+
+        let empty = Address::P2MPKH { public_key_hashes: vec![], m: 0 };
+        assert_eq!(address_hint(&empty), Err(GroupError::EmptyMultisig));
+        assert_eq!(config.group_of(&empty), Err(GroupError::EmptyMultisig));
+    }
+
+    #[test]
+    fn test_known_groups() {
+        // the genesis addresses of a devnet, one per group, and the ALPH
+        // contract
+        let config = GroupConfig::default();
+        let known = [
+            ("1DrDyTr9RpRsQnDnXo2YRiPzPW4ooHX5LLoqXrqfMrpQH", 0),
+            ("14UAjZ3qcmEVKdTo84Kwf4RprTQi86w2TefnnGFjov9xF", 1),
+            ("15jjExDyS8q3Wqk9v29PCQ21jDqubDrD8WQdgn6VW2oi4", 2),
+            ("17cBiTcWhina5tS2Hw4zUncgq5bwBXM8ba7tq8Z1Tb4Ks", 3),
+            ("tgx7VNFoP9DJiFMFgXXtafQZkUvyEdDHT9ryamHJYrjq", 0)
+        ];
+        for (address, group) in known {
+            assert_eq!(config.group_of(&address.parse().unwrap()), Ok(group), "{}", address);
+        }
+    }
+}
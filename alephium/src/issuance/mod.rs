@@ -0,0 +1,265 @@
+// token issuance
+
+// accounts each keep their own copy of a token's metadata, so none of them
+// can be the authority on how much of it exists. an `Issuance` is: it
+// creates the token, credits the initial supply to the issuer, lets the
+// authorized minter mint more up to the cap, and takes burned tokens out of
+// circulation. every account it touches gets its copy of the supply
+// refreshed on the way.
+
+use crate::account::{Account, AccountError, Token};
+use crate::address::Address;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssuanceError {
+    // ALPH is created by mining, not issued
+    NativeToken,
+    // the supply is fixed: there is no minter
+    NotMintable,
+    Unauthorized,
+    CapExceeded { max_supply: u128, requested: u128 },
+    // burning more than was ever issued: the holder's balance didn't all
+    // come from this issuance
+    ExceedsSupply { supply: u128, requested: u128 },
+    AmountIsZero,
+    Account(AccountError)
+}
+
+impl From<AccountError> for IssuanceError {
+    fn from(e: AccountError) -> Self {
+        IssuanceError::Account(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issuance {
+    token: Token,
+    max_supply: Option<u128>,
+    minter: Option<Address>
+}
+
+impl Issuance {
+
+    // issue `token`, crediting its whole `supply` to `issuer`. with no
+    // `minter` the supply can only ever shrink
+    pub fn issue(token: Token, max_supply: Option<u128>, minter: Option<Address>, issuer: &mut Account) -> Result<Self, IssuanceError> {
+        if token.id().is_alph() {
+            return Err(IssuanceError::NativeToken);
+        }
+        if let Some(max_supply) = max_supply
+            && token.supply() > max_supply {
+            return Err(IssuanceError::CapExceeded { max_supply, requested: token.supply() });
+        }
+
+        let issuance = Issuance { token, max_supply, minter };
+        let mut updated = issuer.clone();
+        issuance.refresh(&mut updated);
+        updated.deposit(&issuance.token.id(), issuance.token.supply())?;
+
+        *issuer = updated;
+        Ok(issuance)
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    // everything minted and not yet burned
+    pub fn supply(&self) -> u128 {
+        self.token.supply()
+    }
+
+    pub fn max_supply(&self) -> Option<u128> {
+        self.max_supply
+    }
+
+    pub fn minter(&self) -> Option<&Address> {
+        self.minter.as_ref()
+    }
+
+    // mint `amount` into the minter's own account
+    pub fn mint(&mut self, minter: &mut Account, amount: u128) -> Result<(), IssuanceError> {
+        let authorized = self.minter.as_ref().ok_or(IssuanceError::NotMintable)?;
+        if minter.address() != Some(authorized) {
+            return Err(IssuanceError::Unauthorized);
+        }
+        if amount == 0 {
+            return Err(IssuanceError::AmountIsZero);
+        }
+
+        let max_supply = self.max_supply.unwrap_or(u128::MAX);
+        let supply = self.supply().checked_add(amount)
+            .filter(|supply| *supply <= max_supply)
+            .ok_or(IssuanceError::CapExceeded { max_supply, requested: self.supply().saturating_add(amount) })?;
+
+        let mut updated = minter.clone();
+        self.refresh(&mut updated);
+        updated.deposit(&self.token.id(), amount)?;
+
+        self.token.supply = supply;
+        self.refresh(&mut updated);
+        *minter = updated;
+        Ok(())
+    }
+
+    // destroy `amount` of the holder's tokens
+    pub fn burn(&mut self, holder: &mut Account, amount: u128) -> Result<(), IssuanceError> {
+        if amount == 0 {
+            return Err(IssuanceError::AmountIsZero);
+        }
+
+        let mut updated = holder.clone();
+        updated.withdraw(&self.token.id(), amount)?;
+        let supply = self.supply().checked_sub(amount)
+            .ok_or(IssuanceError::ExceedsSupply { supply: self.supply(), requested: amount })?;
+        self.token.supply = supply;
+        self.refresh(&mut updated);
+
+        *holder = updated;
+        Ok(())
+    }
+
+    // bring `account`'s copy of the metadata up to date
+    pub fn refresh(&self, account: &mut Account) {
+        account.add_token(self.token.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::TokenId;
+    use proptest::prelude::*;
+
+    const TOKEN: TokenId = TokenId::new([0x42; 32]);
+
+    fn create_test_token(supply: u128) -> Token {
+        Token::new(TOKEN, "Test Token".to_string(), "TEST".to_string(), 18, supply)
+    }
+
+    fn create_test_account(key: u8) -> Account {
+        Account::new(0, vec![]).with_address(Address::p2pkh(&[key; 33]))
+    }
+
+    fn circulating(accounts: &[Account]) -> u128 {
+        accounts.iter().map(|account| account.balance(&TOKEN)).sum()
+    }
+
+    #[test]
+    fn test_issue() {
+        let mut issuer = create_test_account(2);
+        let issuance = Issuance::issue(create_test_token(1_000), Some(5_000), None, &mut issuer).unwrap();
+
+        assert_eq!(issuer.balance(&TOKEN), 1_000);
+        assert_eq!(issuer.token(&TOKEN).unwrap().supply(), 1_000);
+        assert_eq!(issuance.supply(), 1_000);
+
+        assert_eq!(
+            Issuance::issue(create_test_token(6_000), Some(5_000), None, &mut issuer),
+            Err(IssuanceError::CapExceeded { max_supply: 5_000, requested: 6_000 })
+        );
+        let alph = Token::new(TokenId::ALPH, "Alephium".to_string(), "ALPH".to_string(), 18, 1);
+        assert_eq!(Issuance::issue(alph, None, None, &mut issuer), Err(IssuanceError::NativeToken));
+    }
+
+    #[test]
+    fn test_mint_under_cap() {
+        let mut minter = create_test_account(2);
+        let mut other = create_test_account(3);
+        let address = minter.address().cloned();
+        let mut issuance = Issuance::issue(create_test_token(1_000), Some(1_500), address, &mut minter).unwrap();
+
+        issuance.mint(&mut minter, 400).unwrap();
+        assert_eq!((issuance.supply(), minter.balance(&TOKEN)), (1_400, 1_400));
+        assert_eq!(minter.token(&TOKEN).unwrap().supply(), 1_400);
+
+        let before = (issuance.clone(), minter.clone());
+        assert_eq!(issuance.mint(&mut minter, 101), Err(IssuanceError::CapExceeded { max_supply: 1_500, requested: 1_501 }));
+        assert_eq!(issuance.mint(&mut other, 1), Err(IssuanceError::Unauthorized));
+        assert_eq!(issuance.mint(&mut minter, 0), Err(IssuanceError::AmountIsZero));
+        assert_eq!((issuance, minter), before);
+    }
+
+    #[test]
+    fn test_fixed_supply() {
+        let mut issuer = create_test_account(2);
+        let mut issuance = Issuance::issue(create_test_token(1_000), None, None, &mut issuer).unwrap();
+        assert_eq!(issuance.mint(&mut issuer, 1), Err(IssuanceError::NotMintable));
+    }
+
+    #[test]
+    fn test_burn() {
+        let mut issuer = create_test_account(2);
+        let mut holder = create_test_account(3);
+        let mut issuance = Issuance::issue(create_test_token(1_000), None, None, &mut issuer).unwrap();
+        issuer.transfer(&mut holder, &TOKEN, 300).unwrap();
+
+        issuance.burn(&mut holder, 100).unwrap();
+        assert_eq!(issuance.supply(), 900);
+        assert_eq!(holder.balance(&TOKEN), 200);
+        assert_eq!(holder.token(&TOKEN).unwrap().supply(), 900);
+
+        assert!(matches!(
+            issuance.burn(&mut holder, 201),
+            Err(IssuanceError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+        assert_eq!(issuance.supply(), 900);
+        assert_eq!(circulating(&[issuer, holder]), 900);
+    }
+
+    #[test]
+    fn test_burn_past_supply() {
+        // tokens credited behind the issuance's back can't take the supply
+        // below zero
+        let mut issuer = create_test_account(2);
+        let mut issuance = Issuance::issue(create_test_token(1_000), None, None, &mut issuer).unwrap();
+        issuer.deposit(&TOKEN, 500).unwrap();
+        let before = issuer.clone();
+
+        assert_eq!(issuance.burn(&mut issuer, 1_500), Err(IssuanceError::ExceedsSupply { supply: 1_000, requested: 1_500 }));
+        assert_eq!((issuance.supply(), issuer), (1_000, before));
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Mint(u128),
+        Burn { holder: usize, amount: u128 },
+        Transfer { from: usize, to: usize, amount: u128 }
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..1_000u128).prop_map(Op::Mint),
+            (0..3usize, 0..1_000u128).prop_map(|(holder, amount)| Op::Burn { holder, amount }),
+            (0..3usize, 0..3usize, 0..1_000u128).prop_map(|(from, to, amount)| Op::Transfer { from, to, amount }),
+        ]
+    }
+
+    proptest! {
+
+        #[test]
+        fn prop_supply_matches_balances(initial in 0..10_000u128, ops in prop::collection::vec(op(), 1..50)) {
+            let mut accounts = vec![create_test_account(2), create_test_account(3), create_test_account(4)];
+            let minter = accounts[0].address().cloned();
+            let mut issuance = Issuance::issue(create_test_token(initial), Some(20_000), minter, &mut accounts[0]).unwrap();
+
+            for op in ops {
+                // failures are fine, they just mustn't move anything
+                let _ = match op {
+                    Op::Mint(amount) => issuance.mint(&mut accounts[0], amount),
+                    Op::Burn { holder, amount } => issuance.burn(&mut accounts[holder], amount),
+                    Op::Transfer { from, to, amount } if from != to => {
+                        let (low, high) = accounts.split_at_mut(from.max(to));
+                        let (a, b) = (&mut low[from.min(to)], &mut high[0]);
+                        let (from, to) = if from < to { (a, b) } else { (b, a) };
+                        from.transfer(to, &TOKEN, amount).map_err(IssuanceError::Account)
+                    }
+                    Op::Transfer { .. } => Ok(())
+                };
+
+                prop_assert_eq!(circulating(&accounts), issuance.supply());
+                prop_assert!(issuance.supply() <= 20_000);
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// secp256k1 keys and signatures
+
+// an account is owned by whoever holds the secret key behind its P2PKH
+// address: blake2b of the 33 byte compressed public key. transactions are
+// authorized by signing their 32 byte id directly (it is already a hash), as
+//
+//   ECDSA    64 bytes r | s, nonce from RFC 6979, s normalized low
+//   Schnorr  64 bytes, BIP 340 with all-zero aux randomness
+//
+// both are deterministic: the same key and hash always sign the same way.
+
+use std::fmt;
+use std::str::FromStr;
+
+use k256::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+use k256::{ecdsa, schnorr};
+use rand::rngs::OsRng;
+
+use crate::account::{Account, Token};
+use crate::address::{Address, Hash};
+
+pub const SECRET_KEY_LENGTH: usize = 32;
+pub const PUBLIC_KEY_LENGTH: usize = 33;
+pub const SIGNATURE_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyError {
+    InvalidHex,
+    InvalidSecretKey,
+    InvalidPublicKey,
+    InvalidSignature,
+    // the key doesn't own the account it tried to act for
+    WrongKey
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey([u8; PUBLIC_KEY_LENGTH]);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Signature([u8; SIGNATURE_LENGTH]);
+
+// never prints the secret
+#[derive(Clone)]
+pub struct KeyPair {
+    secret: ecdsa::SigningKey,
+    public_key: PublicKey
+}
+
+impl KeyPair {
+
+    pub fn generate() -> Self {
+        KeyPair::from_signing_key(ecdsa::SigningKey::random(&mut OsRng))
+    }
+
+    pub fn from_secret_bytes(bytes: &[u8]) -> Result<Self, KeyError> {
+        // k256 left-pads short slices, which would hide a truncated key
+        if bytes.len() != SECRET_KEY_LENGTH {
+            return Err(KeyError::InvalidSecretKey);
+        }
+        let secret = ecdsa::SigningKey::from_slice(bytes).map_err(|_| KeyError::InvalidSecretKey)?;
+        Ok(KeyPair::from_signing_key(secret))
+    }
+
+    pub fn from_secret_hex(s: &str) -> Result<Self, KeyError> {
+        KeyPair::from_secret_bytes(&hex::decode(s).map_err(|_| KeyError::InvalidHex)?)
+    }
+
+    fn from_signing_key(secret: ecdsa::SigningKey) -> Self {
+        let point = secret.verifying_key().to_encoded_point(true);
+        let public_key = PublicKey(point.as_bytes().try_into().expect("compressed points are 33 bytes"));
+        KeyPair { secret, public_key }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; SECRET_KEY_LENGTH] {
+        self.secret.to_bytes().into()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    pub fn address(&self) -> Address {
+        self.public_key.address()
+    }
+
+    // a fresh account owned by this key
+    pub fn account(&self, alph_amount: u128, tokens: Vec<Token>) -> Account {
+        Account::new(alph_amount, tokens).with_address(self.address())
+    }
+
+    pub fn sign(&self, hash: &Hash) -> Signature {
+        let signature: ecdsa::Signature = self.secret.sign_prehash(hash).expect("32 byte prehashes always sign");
+        // k256 already normalizes, but the chain rejects high s so make sure
+        let signature = signature.normalize_s().unwrap_or(signature);
+        Signature(signature.to_bytes().into())
+    }
+
+    pub fn sign_schnorr(&self, hash: &Hash) -> Signature {
+        let secret = schnorr::SigningKey::from_bytes(&self.secret_bytes()).expect("a valid ecdsa key is a valid schnorr key");
+        let signature = secret.sign_prehash_with_aux_rand(hash, &[0; 32]).expect("schnorr signing only fails on a zero nonce");
+        Signature(signature.to_bytes())
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair").field("public_key", &self.public_key).finish_non_exhaustive()
+    }
+}
+
+impl PublicKey {
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeyError> {
+        // parse to reject points that aren't on the curve
+        let key = ecdsa::VerifyingKey::from_sec1_bytes(bytes).map_err(|_| KeyError::InvalidPublicKey)?;
+        let point = key.to_encoded_point(true);
+        Ok(PublicKey(point.as_bytes().try_into().map_err(|_| KeyError::InvalidPublicKey)?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; PUBLIC_KEY_LENGTH] {
+        &self.0
+    }
+
+    pub fn address(&self) -> Address {
+        Address::p2pkh(&self.0)
+    }
+
+    pub fn verify(&self, hash: &Hash, signature: &Signature) -> Result<(), KeyError> {
+        let key = ecdsa::VerifyingKey::from_sec1_bytes(&self.0).map_err(|_| KeyError::InvalidPublicKey)?;
+        let signature = ecdsa::Signature::from_slice(&signature.0).map_err(|_| KeyError::InvalidSignature)?;
+        // high s signatures are malleable copies of low s ones, refuse them
+        if signature.normalize_s().is_some() {
+            return Err(KeyError::InvalidSignature);
+        }
+        key.verify_prehash(hash, &signature).map_err(|_| KeyError::InvalidSignature)
+    }
+
+    // BIP 340 only looks at the x coordinate
+    pub fn verify_schnorr(&self, hash: &Hash, signature: &Signature) -> Result<(), KeyError> {
+        let key = schnorr::VerifyingKey::from_bytes(&self.0[1..]).map_err(|_| KeyError::InvalidPublicKey)?;
+        let signature = schnorr::Signature::try_from(&signature.0[..]).map_err(|_| KeyError::InvalidSignature)?;
+        key.verify_prehash(hash, &signature).map_err(|_| KeyError::InvalidSignature)
+    }
+}
+
+impl Signature {
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeyError> {
+        bytes.try_into().map(Signature).map_err(|_| KeyError::InvalidSignature)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; SIGNATURE_LENGTH] {
+        &self.0
+    }
+}
+
+impl Account {
+
+    // check that `public_key` owns this account and signed `hash`
+    pub fn verify_signature(&self, hash: &Hash, public_key: &PublicKey, signature: &Signature) -> Result<(), KeyError> {
+        if self.address() != Some(&public_key.address()) {
+            return Err(KeyError::WrongKey);
+        }
+        public_key.verify(hash, signature)
+    }
+}
+
+macro_rules! hex_display {
+    ($bytes:ident) => {
+        impl fmt::Display for $bytes {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&hex::encode(self.0))
+            }
+        }
+
+        impl fmt::Debug for $bytes {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($bytes), self)
+            }
+        }
+
+        impl FromStr for $bytes {
+            type Err = KeyError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $bytes::from_bytes(&hex::decode(s).map_err(|_| KeyError::InvalidHex)?)
+            }
+        }
+    };
+}
+
+hex_display!(PublicKey);
+hex_display!(Signature);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::blake2b;
+
+    fn create_test_key(last: u8) -> KeyPair {
+        let mut secret = [0u8; 32];
+        secret[31] = last;
+        KeyPair::from_secret_bytes(&secret).unwrap()
+    }
+
+    #[test]
+    fn test_public_key_of_one_is_the_generator() {
+        let key = create_test_key(1);
+        assert_eq!(
+            key.public_key().to_string(),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(key.address(), Address::P2PKH(blake2b(key.public_key().as_bytes())));
+    }
+
+    #[test]
+    fn test_import() {
+        let key = KeyPair::generate();
+        let imported = KeyPair::from_secret_hex(&hex::encode(key.secret_bytes())).unwrap();
+        assert_eq!(imported.public_key(), key.public_key());
+
+        assert_eq!(KeyPair::from_secret_bytes(&[0; 32]).unwrap_err(), KeyError::InvalidSecretKey);
+        assert_eq!(KeyPair::from_secret_bytes(&[1; 31]).unwrap_err(), KeyError::InvalidSecretKey);
+        assert_eq!(KeyPair::from_secret_hex("zz").unwrap_err(), KeyError::InvalidHex);
+        assert!(!format!("{:?}", key).contains(&hex::encode(key.secret_bytes())));
+    }
+
+    #[test]
+    fn test_public_key_parsing() {
+        let key = create_test_key(7).public_key();
+        assert_eq!(key.to_string().parse::<PublicKey>().unwrap(), key);
+        // x = 5 is not on the curve
+        let mut bad = [0u8; 33];
+        bad[0] = 2;
+        bad[32] = 5;
+        assert_eq!(PublicKey::from_bytes(&bad), Err(KeyError::InvalidPublicKey));
+    }
+
+    #[test]
+    fn test_ecdsa_is_deterministic() {
+        let key = create_test_key(3);
+        let hash = blake2b(b"transaction");
+        let signature = key.sign(&hash);
+
+        assert_eq!(key.sign(&hash), signature);
+        assert!(key.public_key().verify(&hash, &signature).is_ok());
+        assert_eq!(key.public_key().verify(&blake2b(b"other"), &signature), Err(KeyError::InvalidSignature));
+        assert_eq!(create_test_key(4).public_key().verify(&hash, &signature), Err(KeyError::InvalidSignature));
+        assert_eq!(signature.to_string().parse::<Signature>().unwrap(), signature);
+    }
+
+    #[test]
+    fn test_ecdsa_rejects_high_s() {
+        let key = create_test_key(3);
+        let hash = blake2b(b"transaction");
+        let low = ecdsa::Signature::from_slice(key.sign(&hash).as_bytes()).unwrap();
+        let (r, s) = low.split_scalars();
+        let high = ecdsa::Signature::from_scalars(r, -*s.as_ref()).unwrap();
+        let high = Signature(high.to_bytes().into());
+        assert_eq!(key.public_key().verify(&hash, &high), Err(KeyError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_schnorr_bip340_vector() {
+        // test vector 0 from BIP 340
+        let key = create_test_key(3);
+        let signature = key.sign_schnorr(&[0; 32]);
+        assert_eq!(
+            signature.to_string().to_uppercase(),
+            "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215\
+             25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0"
+        );
+        assert!(key.public_key().verify_schnorr(&[0; 32], &signature).is_ok());
+        assert_eq!(key.public_key().verify_schnorr(&[1; 32], &signature), Err(KeyError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_account_requires_owner_signature() {
+        let owner = create_test_key(5);
+        let account = owner.account(1_000, vec![]);
+        let hash = blake2b(b"withdraw");
+
+        assert!(account.verify_signature(&hash, &owner.public_key(), &owner.sign(&hash)).is_ok());
+
+        let thief = create_test_key(6);
+        assert_eq!(account.verify_signature(&hash, &thief.public_key(), &thief.sign(&hash)), Err(KeyError::WrongKey));
+        assert_eq!(
+            account.verify_signature(&hash, &owner.public_key(), &thief.sign(&hash)),
+            Err(KeyError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,373 @@
+// encrypted keystores
+
+// a keystore holds one secret key at rest, locked with a password:
+//
+//   {
+//     "version": 1,
+//     "address": "<base58 P2PKH of the key>",
+//     "kdf": { "name": "scrypt", "logN": 16, "r": 8, "p": 1, "salt": "<hex>" },
+//     "cipher": { "name": "aes-256-gcm", "nonce": "<hex>" },
+//     "ciphertext": "<hex>",
+//     "check": "<hex>"
+//   }
+//
+// scrypt stretches the password into 64 bytes. the first half is the
+// AES-GCM key, the second half is hashed into `check`, which is how a wrong
+// password is told apart from a damaged file: if `check` matches but the
+// ciphertext doesn't authenticate, someone changed the file. everything
+// outside the ciphertext is authenticated with it as associated data, so
+// swapping the address or the kdf settings is caught too.
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::address::{blake2b, Address, Hash};
+use crate::keys::{KeyError, KeyPair};
+
+pub const KEYSTORE_VERSION: u64 = 1;
+
+const KDF_NAME: &str = "scrypt";
+const CIPHER_NAME: &str = "aes-256-gcm";
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+// scrypt needs 128 * r * 2^logN bytes and runs p times over, so a hostile
+// file could ask for more memory or time than the machine has
+const MAX_LOG_N: u8 = 20;
+const MAX_R: u32 = 32;
+const MAX_P: u32 = 16;
+// 1 GiB, what logN 20 takes at the default r
+const MAX_MEMORY: u64 = 1 << 30;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Json(serde_json::Error),
+    MissingVersion,
+    UnsupportedVersion(u64),
+    UnsupportedKdf(String),
+    UnsupportedCipher(String),
+    InvalidKdfParams,
+    // a field that isn't hex, or is the wrong length
+    InvalidField(&'static str),
+    WrongPassword,
+    // the password is right but the file was modified
+    Tampered,
+    Key(KeyError)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    #[serde(rename = "logN")]
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32
+}
+
+impl Default for KdfParams {
+    // 64 MiB and a fraction of a second on a laptop
+    fn default() -> Self {
+        KdfParams { log_n: 16, r: 8, p: 1 }
+    }
+}
+
+impl KdfParams {
+
+    fn derive(&self, password: &str, salt: &[u8]) -> Result<([u8; 32], Hash), KeystoreError> {
+        if self.log_n > MAX_LOG_N || self.r > MAX_R || self.p > MAX_P
+            || 128 * self.r as u64 * (1u64 << self.log_n) > MAX_MEMORY {
+            return Err(KeystoreError::InvalidKdfParams);
+        }
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 64).map_err(|_| KeystoreError::InvalidKdfParams)?;
+        let mut output = [0u8; 64];
+        scrypt::scrypt(password.as_bytes(), salt, &params, &mut output).map_err(|_| KeystoreError::InvalidKdfParams)?;
+
+        let (key, check) = output.split_at(32);
+        Ok((key.try_into().unwrap(), blake2b(check)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keystore {
+    address: Address,
+    kdf: KdfParams,
+    salt: [u8; SALT_LENGTH],
+    nonce: [u8; NONCE_LENGTH],
+    ciphertext: Vec<u8>,
+    check: Hash
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u64,
+    address: Address,
+    kdf: KdfSection,
+    cipher: CipherSection,
+    ciphertext: String,
+    check: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfSection {
+    name: String,
+    #[serde(flatten)]
+    params: KdfParams,
+    salt: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct CipherSection {
+    name: String,
+    nonce: String
+}
+
+impl Keystore {
+
+    pub fn encrypt(key_pair: &KeyPair, password: &str, kdf: KdfParams) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let (key, check) = kdf.derive(password, &salt)?;
+        let mut keystore = Keystore { address: key_pair.address(), kdf, salt, nonce, ciphertext: Vec::new(), check };
+        let payload = Payload { msg: &key_pair.secret_bytes(), aad: &keystore.associated_data() };
+        keystore.ciphertext = cipher(&key).encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("encrypting a 32 byte secret can't fail");
+        Ok(keystore)
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    pub fn kdf(&self) -> KdfParams {
+        self.kdf
+    }
+
+    pub fn unlock(&self, password: &str) -> Result<KeyPair, KeystoreError> {
+        let (key, check) = self.kdf.derive(password, &self.salt)?;
+        if check != self.check {
+            return Err(KeystoreError::WrongPassword);
+        }
+
+        let payload = Payload { msg: &self.ciphertext, aad: &self.associated_data() };
+        let secret = cipher(&key).decrypt(Nonce::from_slice(&self.nonce), payload)
+            .map_err(|_| KeystoreError::Tampered)?;
+        let key_pair = KeyPair::from_secret_bytes(&secret).map_err(KeystoreError::Key)?;
+        // authenticated data covers the address, so this only trips on a
+        // file that was written wrong in the first place
+        if key_pair.address() != self.address {
+            return Err(KeystoreError::Tampered);
+        }
+        Ok(key_pair)
+    }
+
+    // the same key under a new password, with fresh salt and nonce
+    pub fn change_password(&self, old: &str, new: &str, kdf: KdfParams) -> Result<Self, KeystoreError> {
+        Keystore::encrypt(&self.unlock(old)?, new, kdf)
+    }
+
+    pub fn to_json(&self) -> Result<String, KeystoreError> {
+        serde_json::to_string_pretty(&self.to_file()).map_err(KeystoreError::Json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, KeystoreError> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(KeystoreError::Json)?;
+
+        // check the version first so a newer format says so instead of
+        // failing on whichever field changed
+        let version = value.get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or(KeystoreError::MissingVersion)?;
+        if version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(version));
+        }
+
+        let file: KeystoreFile = serde_json::from_value(value).map_err(KeystoreError::Json)?;
+        Keystore::from_file(file)
+    }
+
+    fn to_file(&self) -> KeystoreFile {
+        KeystoreFile {
+            version: KEYSTORE_VERSION,
+            address: self.address.clone(),
+            kdf: KdfSection { name: KDF_NAME.to_string(), params: self.kdf, salt: hex::encode(self.salt) },
+            cipher: CipherSection { name: CIPHER_NAME.to_string(), nonce: hex::encode(self.nonce) },
+            ciphertext: hex::encode(&self.ciphertext),
+            check: hex::encode(self.check)
+        }
+    }
+
+    fn from_file(file: KeystoreFile) -> Result<Self, KeystoreError> {
+        if file.kdf.name != KDF_NAME {
+            return Err(KeystoreError::UnsupportedKdf(file.kdf.name));
+        }
+        if file.cipher.name != CIPHER_NAME {
+            return Err(KeystoreError::UnsupportedCipher(file.cipher.name));
+        }
+
+        Ok(Keystore {
+            address: file.address,
+            kdf: file.kdf.params,
+            salt: decode_hex("salt", &file.kdf.salt)?,
+            nonce: decode_hex("nonce", &file.cipher.nonce)?,
+            ciphertext: hex::decode(&file.ciphertext).map_err(|_| KeystoreError::InvalidField("ciphertext"))?,
+            check: decode_hex("check", &file.check)?
+        })
+    }
+
+    // everything in the file except the ciphertext itself
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(128);
+        data.extend_from_slice(&KEYSTORE_VERSION.to_le_bytes());
+        data.extend_from_slice(self.address.to_string().as_bytes());
+        data.push(self.kdf.log_n);
+        data.extend_from_slice(&self.kdf.r.to_le_bytes());
+        data.extend_from_slice(&self.kdf.p.to_le_bytes());
+        data.extend_from_slice(&self.salt);
+        data.extend_from_slice(&self.check);
+        data
+    }
+}
+
+fn cipher(key: &[u8; 32]) -> Aes256Gcm {
+    Aes256Gcm::new_from_slice(key).expect("aes-256 keys are 32 bytes")
+}
+
+fn decode_hex<const N: usize>(field: &'static str, s: &str) -> Result<[u8; N], KeystoreError> {
+    hex::decode(s).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(KeystoreError::InvalidField(field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap enough for debug builds
+    const TEST_KDF: KdfParams = KdfParams { log_n: 4, r: 8, p: 1 };
+
+    fn create_test_keystore() -> (KeyPair, Keystore) {
+        let key_pair = KeyPair::from_secret_hex("0101010101010101010101010101010101010101010101010101010101010101").unwrap();
+        let keystore = Keystore::encrypt(&key_pair, "correct horse", TEST_KDF).unwrap();
+        (key_pair, keystore)
+    }
+
+    // edit one field of the saved json and load it back
+    fn tamper(keystore: &Keystore, edit: impl FnOnce(&mut serde_json::Value)) -> Keystore {
+        let mut value: serde_json::Value = serde_json::from_str(&keystore.to_json().unwrap()).unwrap();
+        edit(&mut value);
+        Keystore::from_json(&value.to_string()).unwrap()
+    }
+
+    fn flip_hex(value: &mut serde_json::Value) {
+        let mut bytes = hex::decode(value.as_str().unwrap()).unwrap();
+        bytes[0] ^= 1;
+        *value = serde_json::Value::String(hex::encode(bytes));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (key_pair, keystore) = create_test_keystore();
+        assert_eq!(keystore.address(), &key_pair.address());
+
+        let loaded = Keystore::from_json(&keystore.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, keystore);
+        let unlocked = loaded.unlock("correct horse").unwrap();
+        assert_eq!(unlocked.secret_bytes(), key_pair.secret_bytes());
+
+        let account = unlocked.account(1_000, vec![]);
+        let hash = blake2b(b"transfer");
+        assert!(account.verify_signature(&hash, &unlocked.public_key(), &unlocked.sign(&hash)).is_ok());
+    }
+
+    #[test]
+    fn test_file_hides_the_secret() {
+        let (key_pair, keystore) = create_test_keystore();
+        let json = keystore.to_json().unwrap();
+        assert!(!json.contains(&hex::encode(key_pair.secret_bytes())));
+        assert!(json.contains("\"logN\": 4"));
+
+        // fresh salt and nonce each time
+        let again = Keystore::encrypt(&key_pair, "correct horse", TEST_KDF).unwrap();
+        assert_ne!(again.ciphertext, keystore.ciphertext);
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let (_, keystore) = create_test_keystore();
+        assert!(matches!(keystore.unlock("battery staple"), Err(KeystoreError::WrongPassword)));
+        assert!(matches!(keystore.unlock(""), Err(KeystoreError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_tampered_file() {
+        let (_, keystore) = create_test_keystore();
+
+        let ciphertext = tamper(&keystore, |v| flip_hex(&mut v["ciphertext"]));
+        assert!(matches!(ciphertext.unlock("correct horse"), Err(KeystoreError::Tampered)));
+
+        let nonce = tamper(&keystore, |v| flip_hex(&mut v["cipher"]["nonce"]));
+        assert!(matches!(nonce.unlock("correct horse"), Err(KeystoreError::Tampered)));
+
+        let other = Address::p2pkh(&[2; 33]).to_string();
+        let address = tamper(&keystore, |v| v["address"] = serde_json::Value::String(other));
+        assert!(matches!(address.unlock("correct horse"), Err(KeystoreError::Tampered)));
+
+        // a different salt derives different keys, which looks like a wrong password
+        let salt = tamper(&keystore, |v| flip_hex(&mut v["kdf"]["salt"]));
+        assert!(matches!(salt.unlock("correct horse"), Err(KeystoreError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_change_password() {
+        let (key_pair, keystore) = create_test_keystore();
+        let changed = keystore.change_password("correct horse", "new password", TEST_KDF).unwrap();
+        assert_eq!(changed.unlock("new password").unwrap().public_key(), key_pair.public_key());
+        assert!(matches!(changed.unlock("correct horse"), Err(KeystoreError::WrongPassword)));
+        assert!(matches!(keystore.change_password("nope", "x", TEST_KDF), Err(KeystoreError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let (_, keystore) = create_test_keystore();
+        let json: serde_json::Value = serde_json::from_str(&keystore.to_json().unwrap()).unwrap();
+        let load = |edit: &dyn Fn(&mut serde_json::Value)| {
+            let mut value = json.clone();
+            edit(&mut value);
+            Keystore::from_json(&value.to_string())
+        };
+
+        assert!(matches!(load(&|v| v["version"] = 2.into()), Err(KeystoreError::UnsupportedVersion(2))));
+        assert!(matches!(
+            load(&|v| { v.as_object_mut().unwrap().remove("version"); }),
+            Err(KeystoreError::MissingVersion)
+        ));
+        assert!(matches!(load(&|v| v["kdf"]["name"] = "pbkdf2".into()), Err(KeystoreError::UnsupportedKdf(_))));
+        assert!(matches!(load(&|v| v["cipher"]["name"] = "aes-128-ctr".into()), Err(KeystoreError::UnsupportedCipher(_))));
+        assert!(matches!(load(&|v| v["cipher"]["nonce"] = "abcd".into()), Err(KeystoreError::InvalidField("nonce"))));
+        assert!(matches!(load(&|v| v["check"] = "zz".into()), Err(KeystoreError::InvalidField("check"))));
+        assert!(matches!(load(&|v| { v.as_object_mut().unwrap().remove("cipher"); }), Err(KeystoreError::Json(_))));
+
+        let greedy = load(&|v| v["kdf"]["logN"] = 40.into()).unwrap();
+        assert!(matches!(greedy.unlock("correct horse"), Err(KeystoreError::InvalidKdfParams)));
+    }
+
+    #[test]
+    fn test_kdf_bounds() {
+        // each of these is turned away before scrypt allocates anything
+        let greedy = [
+            KdfParams { r: 33, ..TEST_KDF },
+            KdfParams { p: 17, ..TEST_KDF },
+            // 128 * 16 * 2^20 is 2 GiB, though each bound alone is fine
+            KdfParams { log_n: 20, r: 16, p: 1 },
+            KdfParams { log_n: 4, r: u32::MAX, p: u32::MAX },
+        ];
+        for kdf in greedy {
+            assert!(matches!(kdf.derive("correct horse", &[0; SALT_LENGTH]), Err(KeystoreError::InvalidKdfParams)), "{:?}", kdf);
+        }
+        assert!(KdfParams { r: 32, p: 16, ..TEST_KDF }.derive("correct horse", &[0; SALT_LENGTH]).is_ok());
+    }
+}
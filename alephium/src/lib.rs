@@ -0,0 +1,23 @@
+pub mod abi;
+pub mod account;
+pub mod address;
+pub mod amount;
+pub mod analytics;
+pub mod arbitrage;
+pub mod codec;
+pub mod contract;
+pub mod dex;
+pub mod fee;
+pub mod group;
+pub mod issuance;
+pub mod keys;
+pub mod keystore;
+pub mod node;
+pub mod ralph;
+pub mod simulator;
+pub mod snapshot;
+pub mod swap;
+pub mod token_list;
+pub mod transaction;
+pub mod utxo;
+pub mod wallet;
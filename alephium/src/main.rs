@@ -1,11 +1,3 @@
-
-mod account;
-use crate::account::*;
-
-mod swap;
-use crate::swap::*;
-
-
 fn main() {
     println!("Hello, world!");
 }
@@ -0,0 +1,114 @@
+// mock full node for the client tests
+//
+// serves canned responses on a local port so the tests never leave the
+// machine. the bodies under fixtures/ are node responses, shape for shape,
+// trimmed to what the tests look at. each route answers with one of them (or
+// any other body) for an exact method and path, query included. anything else gets the node's 404.
+// requests are kept so the tests can check what was sent.
+
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use tiny_http::{Header, Response, Server};
+
+pub const BALANCE: &str = include_str!("fixtures/balance.json");
+pub const UTXOS: &str = include_str!("fixtures/utxos.json");
+pub const CONTRACT_STATE: &str = include_str!("fixtures/contract_state.json");
+pub const EVENTS: &str = include_str!("fixtures/events.json");
+pub const CALL_CONTRACT: &str = include_str!("fixtures/call_contract.json");
+pub const SUBMIT: &str = include_str!("fixtures/submit.json");
+pub const BUILD: &str = include_str!("fixtures/build.json");
+pub const NOT_FOUND: &str = include_str!("fixtures/not_found.json");
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    method: &'static str,
+    path: String,
+    status: u16,
+    body: String
+}
+
+impl Route {
+
+    pub fn get(path: &str, body: &str) -> Self {
+        Route { method: "GET", path: path.to_string(), status: 200, body: body.to_string() }
+    }
+
+    pub fn post(path: &str, body: &str) -> Self {
+        Route { method: "POST", path: path.to_string(), status: 200, body: body.to_string() }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub api_key: Option<String>,
+    pub body: String
+}
+
+pub struct MockNode {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+    url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>
+}
+
+impl MockNode {
+
+    // binds a free port on localhost
+    pub fn start(routes: Vec<Route>) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("bind mock node"));
+        let port = server.server_addr().to_ip().expect("tcp listener").port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handle = {
+            let (server, requests) = (Arc::clone(&server), Arc::clone(&requests));
+            thread::spawn(move || serve(&server, &routes, &requests))
+        };
+        MockNode { server, handle: Some(handle), url: format!("http://127.0.0.1:{}", port), requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockNode {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn serve(server: &Server, routes: &[Route], requests: &Mutex<Vec<Recorded>>) {
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let _ = request.as_reader().read_to_string(&mut body);
+        let method = request.method().as_str().to_string();
+        let path = request.url().to_string();
+        let api_key = request.headers().iter()
+            .find(|header| header.field.equiv("X-API-KEY"))
+            .map(|header| header.value.as_str().to_string());
+
+        let (status, response) = match routes.iter().find(|route| route.method == method && route.path == path) {
+            Some(route) => (route.status, route.body.clone()),
+            None => (404, format!(r#"{{"resource":"{}","detail":"{} not found"}}"#, path, path))
+        };
+        requests.lock().unwrap().push(Recorded { method, path, api_key, body });
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let _ = request.respond(Response::from_string(response).with_status_code(status).with_header(content_type));
+    }
+}
@@ -0,0 +1,817 @@
+// full node rest client
+
+// a blocking client for the parts of the node's rest api this crate needs:
+//
+//   GET  /addresses/{address}/balance     Balance
+//   GET  /addresses/{address}/utxos       UtxoSet
+//   GET  /contracts/{address}/state       ContractState
+//   GET  /events/contract/{address}       ContractEvents
+//   POST /contracts/call-contract         returned values
+//   POST /transactions/build              gas estimate, as a Fee
+//   POST /transactions/submit             transaction id
+//
+// the node writes amounts as decimal strings and hashes and bytes as hex;
+// everything is parsed into the crate's own types before it's handed out,
+// so an amount past u128 is an error here rather than a surprise later.
+// contract fields and call arguments and returns go over the wire as typed
+// json ({"type": "U256", "value": "5"}) and are abi values here.
+
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::abi::{ContractCall, Val};
+use crate::account::{Account, AccountError};
+use crate::address::{Address, Hash};
+use crate::codec::{self, U256};
+use crate::contract::TokenId;
+use crate::fee::{Fee, FeeError};
+use crate::keys::{PublicKey, Signature};
+use crate::swap::Swap;
+use crate::transaction::UnsignedTransaction;
+use crate::utxo::{AssetOutput, OutputRef, Utxo, UtxoSet};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const API_KEY_HEADER: &str = "X-API-KEY";
+
+// mutable fields of the pools crate::ralph generates, in declaration order
+const RESERVE_ONE_FIELD: usize = 0;
+const RESERVE_TWO_FIELD: usize = 1;
+const FEE_ONE_FIELD: usize = 2;
+const FEE_TWO_FIELD: usize = 3;
+const TOTAL_SUPPLY_FIELD: usize = 4;
+
+#[derive(Debug)]
+pub enum NodeError {
+    // never got an answer
+    Transport(Box<ureq::Transport>),
+    // the node answered with an error status
+    Status { code: u16, detail: String },
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    // well formed json with a value that doesn't fit, like an amount past u128
+    InvalidResponse(String),
+    // the mutable field at this index is missing or not the expected U256
+    UnexpectedField(usize),
+    // refreshing an account needs its address
+    NoAddress,
+    // the node ran the call and it failed, with the vm's reason
+    CallFailed(String),
+    // the contract doesn't hold enough of this token to back the reserve
+    // read for it, so it isn't a pool on the swap's pair
+    WrongPair(TokenId),
+    Account(AccountError),
+    Fee(FeeError)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Balance {
+    // locked amounts are included in the totals
+    pub alph_amount: u128,
+    pub locked_alph_amount: u128,
+    pub tokens: Vec<(TokenId, u128)>,
+    pub locked_tokens: Vec<(TokenId, u128)>,
+    pub utxo_count: u64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractState {
+    pub address: Address,
+    pub immutable_fields: Vec<Val>,
+    pub mutable_fields: Vec<Val>,
+    // contracts can hold more than a u128, like a DEX pair holding the LP
+    // tokens it hasn't issued yet
+    pub alph_amount: U256,
+    pub tokens: Vec<(TokenId, U256)>
+}
+
+impl ContractState {
+
+    // a state dump saved from GET /contracts/{address}/state
+    pub fn from_json(json: &str) -> Result<Self, NodeError> {
+        ContractState::from_wire(&serde_json::from_str(json).map_err(NodeError::Json)?)
+    }
+
+    fn from_wire(json: &ContractStateJson) -> Result<Self, NodeError> {
+        Ok(ContractState {
+            address: json.address.clone(),
+            immutable_fields: json.imm_fields.iter().map(val).collect::<Result<_, _>>()?,
+            mutable_fields: json.mut_fields.iter().map(val).collect::<Result<_, _>>()?,
+            alph_amount: u256(&json.asset.atto_alph_amount)?,
+            tokens: json.asset.tokens.iter().map(|token| Ok((token.id, u256(&token.amount)?))).collect::<Result<_, NodeError>>()?
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractEvent {
+    pub block_hash: Hash,
+    pub tx_id: Hash,
+    // the event's index in the contract's declaration order
+    pub event_index: i32,
+    pub fields: Vec<Val>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractEvents {
+    pub events: Vec<ContractEvent>,
+    // where the next page starts
+    pub next_start: u64
+}
+
+// the wire format
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenJson {
+    id: TokenId,
+    amount: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceJson {
+    balance: String,
+    locked_balance: String,
+    #[serde(default)]
+    token_balances: Vec<TokenJson>,
+    #[serde(default)]
+    locked_token_balances: Vec<TokenJson>,
+    utxo_num: u64
+}
+
+#[derive(Debug, Deserialize)]
+struct UtxosJson {
+    utxos: Vec<UtxoJson>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UtxoJson {
+    #[serde(rename = "ref")]
+    reference: OutputRefJson,
+    amount: String,
+    #[serde(default)]
+    tokens: Vec<TokenJson>,
+    lock_time: u64,
+    #[serde(default)]
+    additional_data: String
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputRefJson {
+    // the node's hints are signed
+    hint: i32,
+    key: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FieldJson {
+    #[serde(rename = "type")]
+    kind: String,
+    value: serde_json::Value
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractStateJson {
+    address: Address,
+    imm_fields: Vec<FieldJson>,
+    mut_fields: Vec<FieldJson>,
+    asset: ContractAssetJson
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractAssetJson {
+    atto_alph_amount: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tokens: Vec<TokenJson>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallContractRequest {
+    group: u32,
+    address: Address,
+    caller_address: Address,
+    method_index: u8,
+    args: Vec<FieldJson>,
+    // what the caller approves, so the call runs as it would in a script
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    input_assets: Vec<InputAssetJson>
+}
+
+#[derive(Debug, Serialize)]
+struct InputAssetJson {
+    address: Address,
+    asset: ContractAssetJson
+}
+
+// CallContractSucceeded with `returns`, or CallContractFailed with `error`
+#[derive(Debug, Deserialize)]
+struct CallContractJson {
+    #[serde(default)]
+    returns: Vec<FieldJson>,
+    error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventsJson {
+    events: Vec<EventJson>,
+    next_start: u64
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventJson {
+    block_hash: String,
+    tx_id: String,
+    event_index: i32,
+    fields: Vec<FieldJson>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitRequest {
+    unsigned_tx: String,
+    signature: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitJson {
+    tx_id: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildRequest {
+    from_public_key: String,
+    destinations: Vec<DestinationJson>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DestinationJson {
+    address: Address,
+    atto_alph_amount: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tokens: Vec<TokenJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock_time: Option<u64>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildJson {
+    gas_amount: u64,
+    gas_price: String
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorJson {
+    detail: String
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeClient {
+    base_url: String,
+    api_key: Option<String>,
+    agent: ureq::Agent
+}
+
+impl NodeClient {
+
+    // e.g. http://127.0.0.1:22973
+    pub fn new(base_url: &str) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(DEFAULT_TIMEOUT).build();
+        NodeClient { base_url: base_url.trim_end_matches('/').to_string(), api_key: None, agent }
+    }
+
+    // for nodes started with an api key
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn balance(&self, address: &Address) -> Result<Balance, NodeError> {
+        let json: BalanceJson = self.get(&format!("/addresses/{}/balance", address))?;
+        Ok(Balance {
+            alph_amount: amount(&json.balance)?,
+            locked_alph_amount: amount(&json.locked_balance)?,
+            tokens: tokens(&json.token_balances)?,
+            locked_tokens: tokens(&json.locked_token_balances)?,
+            utxo_count: json.utxo_num
+        })
+    }
+
+    pub fn utxos(&self, address: &Address) -> Result<UtxoSet, NodeError> {
+        let json: UtxosJson = self.get(&format!("/addresses/{}/utxos", address))?;
+        let utxos = json.utxos.iter()
+            .map(|utxo| {
+                let output = AssetOutput {
+                    alph_amount: amount(&utxo.amount)?,
+                    lockup_script: address.clone(),
+                    lock_time: utxo.lock_time,
+                    tokens: tokens(&utxo.tokens)?,
+                    additional_data: bytes(&utxo.additional_data)?
+                };
+                let reference = OutputRef { hint: utxo.reference.hint as u32, key: hash(&utxo.reference.key)? };
+                Ok(Utxo { reference, output })
+            })
+            .collect::<Result<_, NodeError>>()?;
+        Ok(UtxoSet::new(utxos))
+    }
+
+    pub fn contract_state(&self, contract: &Address) -> Result<ContractState, NodeError> {
+        let json: ContractStateJson = self.get(&format!("/contracts/{}/state", contract))?;
+        ContractState::from_wire(&json)
+    }
+
+    // at most `limit` events emitted by `contract`, starting from the
+    // `start`th; keep asking from `next_start` for the rest
+    pub fn contract_events(&self, contract: &Address, start: u64, limit: u32) -> Result<ContractEvents, NodeError> {
+        let json: EventsJson = self.get(&format!("/events/contract/{}?start={}&limit={}", contract, start, limit))?;
+        let events = json.events.iter()
+            .map(|event| {
+                Ok(ContractEvent {
+                    block_hash: hash(&event.block_hash)?,
+                    tx_id: hash(&event.tx_id)?,
+                    event_index: event.event_index,
+                    fields: event.fields.iter().map(val).collect::<Result<_, _>>()?
+                })
+            })
+            .collect::<Result<_, NodeError>>()?;
+        Ok(ContractEvents { events, next_start: json.next_start })
+    }
+
+    // runs `call` against the contract's current state without a
+    // transaction; `group` is the contract's. the approvals go along as the
+    // caller's assets
+    pub fn call_contract(&self, call: &ContractCall, group: u32) -> Result<Vec<Val>, NodeError> {
+        let mut input_assets = vec![];
+        if !call.approvals.is_empty() {
+            let mut alph_amount = 0u128;
+            let mut tokens = vec![];
+            for approval in &call.approvals {
+                if approval.token.is_alph() {
+                    alph_amount = alph_amount.checked_add(approval.amount).ok_or(NodeError::Account(AccountError::Overflow(TokenId::ALPH)))?;
+                } else {
+                    tokens.push(TokenJson { id: approval.token, amount: approval.amount.to_string() });
+                }
+            }
+            let asset = ContractAssetJson { atto_alph_amount: alph_amount.to_string(), tokens };
+            input_assets.push(InputAssetJson { address: call.caller.clone(), asset });
+        }
+
+        let request = CallContractRequest {
+            group,
+            address: Address::contract(call.contract_id),
+            caller_address: call.caller.clone(),
+            method_index: call.method_index,
+            args: call.args.iter().map(field_json).collect(),
+            input_assets
+        };
+        let json: CallContractJson = self.post("/contracts/call-contract", &request)?;
+        match json.error {
+            Some(error) => Err(NodeError::CallFailed(error)),
+            None => json.returns.iter().map(val).collect()
+        }
+    }
+
+    // what the node would charge to pay `destinations` from `from`, at its
+    // default gas price
+    pub fn estimate_gas(&self, from: &PublicKey, destinations: &[AssetOutput]) -> Result<Fee, NodeError> {
+        let request = BuildRequest {
+            from_public_key: hex::encode(from.as_bytes()),
+            destinations: destinations.iter()
+                .map(|output| DestinationJson {
+                    address: output.lockup_script.clone(),
+                    atto_alph_amount: output.alph_amount.to_string(),
+                    tokens: output.tokens.iter().map(|(id, amount)| TokenJson { id: *id, amount: amount.to_string() }).collect(),
+                    lock_time: (output.lock_time > 0).then_some(output.lock_time)
+                })
+                .collect()
+        };
+        let json: BuildJson = self.post("/transactions/build", &request)?;
+        Fee::new(json.gas_amount, amount(&json.gas_price)?).map_err(NodeError::Fee)
+    }
+
+    // hands back the id the node gave the transaction
+    pub fn submit(&self, transaction: &UnsignedTransaction, signature: &Signature) -> Result<Hash, NodeError> {
+        let request = SubmitRequest {
+            unsigned_tx: hex::encode(codec::serialize(transaction)),
+            signature: hex::encode(signature.as_bytes())
+        };
+        let json: SubmitJson = self.post("/transactions/submit", &request)?;
+        hash(&json.tx_id)
+    }
+
+    // replaces the account's balances with what the node has for its
+    // address. tokens the account has no metadata for are an error rather
+    // than dropped, add_token them first
+    pub fn refresh_account(&self, account: &mut Account) -> Result<(), NodeError> {
+        let address = account.address().ok_or(NodeError::NoAddress)?.clone();
+        let balance = self.balance(&address)?;
+
+        let mut refreshed = Account::new(balance.alph_amount, account.tokens.clone()).with_address(address);
+        for (id, amount) in &balance.tokens {
+            refreshed.deposit(id, *amount).map_err(NodeError::Account)?;
+        }
+        *account = refreshed;
+        Ok(())
+    }
+
+    // reads the reserves, collected fees and supply of a pool deployed from
+    // crate::ralph. the tokens and fee rate are constants compiled into the
+    // contract, not fields, so `swap` keeps its own; the contract's assets
+    // have to cover both reserves in those tokens or nothing is changed
+    pub fn refresh_swap(&self, swap: &mut Swap, contract: &Address) -> Result<(), NodeError> {
+        let state = self.contract_state(contract)?;
+        let field = |index: usize| {
+            state.mutable_fields.get(index).and_then(Val::as_u128).ok_or(NodeError::UnexpectedField(index))
+        };
+
+        let reserve_one = field(RESERVE_ONE_FIELD)?;
+        let reserve_two = field(RESERVE_TWO_FIELD)?;
+        let fee_one = field(FEE_ONE_FIELD)?;
+        let fee_two = field(FEE_TWO_FIELD)?;
+        let total_supply = field(TOTAL_SUPPLY_FIELD)?;
+
+        let held = |id: TokenId| if id.is_alph() {
+            state.alph_amount
+        } else {
+            state.tokens.iter().find(|(token, _)| *token == id).map_or(U256::ZERO, |(_, amount)| *amount)
+        };
+        for (id, reserve) in [(swap.token_one, reserve_one), (swap.token_two, reserve_two)] {
+            if held(id) < U256::from(reserve) {
+                return Err(NodeError::WrongPair(id));
+            }
+        }
+
+        swap.reserve_one = reserve_one;
+        swap.reserve_two = reserve_two;
+        swap.fee_one = fee_one;
+        swap.fee_two = fee_two;
+        swap.total_supply = total_supply;
+        Ok(())
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.set(API_KEY_HEADER, key),
+            None => request
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, NodeError> {
+        read(self.request("GET", path).call())
+    }
+
+    fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, NodeError> {
+        read(self.request("POST", path).send_json(body))
+    }
+}
+
+fn read<T: DeserializeOwned>(response: Result<ureq::Response, ureq::Error>) -> Result<T, NodeError> {
+    match response {
+        Ok(response) => {
+            let body = response.into_string().map_err(NodeError::Io)?;
+            serde_json::from_str(&body).map_err(NodeError::Json)
+        }
+        // the node explains itself in `detail`, fall back to the raw body
+        Err(ureq::Error::Status(code, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            let detail = serde_json::from_str::<ErrorJson>(&body).map(|error| error.detail).unwrap_or(body);
+            Err(NodeError::Status { code, detail })
+        }
+        Err(ureq::Error::Transport(transport)) => Err(NodeError::Transport(Box::new(transport)))
+    }
+}
+
+fn amount(s: &str) -> Result<u128, NodeError> {
+    s.parse().map_err(|_| NodeError::InvalidResponse(format!("amount `{}`", s)))
+}
+
+fn u256(s: &str) -> Result<U256, NodeError> {
+    U256::from_dec_str(s).ok_or_else(|| NodeError::InvalidResponse(format!("amount `{}`", s)))
+}
+
+fn tokens(tokens: &[TokenJson]) -> Result<Vec<(TokenId, u128)>, NodeError> {
+    tokens.iter().map(|token| Ok((token.id, amount(&token.amount)?))).collect()
+}
+
+fn bytes(s: &str) -> Result<Vec<u8>, NodeError> {
+    hex::decode(s).map_err(|_| NodeError::InvalidResponse(format!("hex `{}`", s)))
+}
+
+fn hash(s: &str) -> Result<Hash, NodeError> {
+    bytes(s)?.try_into().map_err(|_| NodeError::InvalidResponse(format!("hash `{}`", s)))
+}
+
+fn field_json(val: &Val) -> FieldJson {
+    let (kind, value) = match val {
+        Val::Bool(b) => ("Bool", serde_json::Value::Bool(*b)),
+        Val::U256(n) => ("U256", serde_json::Value::String(n.to_string())),
+        Val::ByteVec(bytes) => ("ByteVec", serde_json::Value::String(hex::encode(bytes))),
+        Val::Address(address) => ("Address", serde_json::Value::String(address.to_string()))
+    };
+    FieldJson { kind: kind.to_string(), value }
+}
+
+// I256 fields have no abi value yet
+fn val(field: &FieldJson) -> Result<Val, NodeError> {
+    let invalid = || NodeError::InvalidResponse(format!("{} field `{}`", field.kind, field.value));
+    match (field.kind.as_str(), &field.value) {
+        ("Bool", serde_json::Value::Bool(b)) => Ok(Val::Bool(*b)),
+        ("U256", serde_json::Value::String(s)) => U256::from_dec_str(s).map(Val::U256).ok_or_else(invalid),
+        ("ByteVec", serde_json::Value::String(s)) => hex::decode(s).map(Val::ByteVec).map_err(|_| invalid()),
+        ("Address", serde_json::Value::String(s)) => s.parse().map(Val::Address).map_err(|_| invalid()),
+        _ => Err(invalid())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{self, MockNode, Route};
+    use super::*;
+    use crate::abi;
+    use crate::account::Token;
+    use crate::codec::U256;
+    use crate::contract::ContractId;
+    use crate::keys::KeyPair;
+    use crate::token_list::Network;
+    use crate::transaction::TransactionBuilder;
+
+    const USDT: &str = "625096cf33295c0ec60aa39350cbaf54b3a3fc0ee8966059724b99d2ec00c1db";
+    const WETH: &str = "2b2e4a3d647c061f5526b8931c497d551ac32595d095836507d5872c12c910f8";
+    const POOL: &str = "29Ji2robDmtxjxWbX3GXB25Tp1tHDZE5W3m6k6cF9GQMh";
+    const SENDER: &str = "16tQcRTHM9FHpEXg2xiPCGTRXmDDG4yRmWcMjTR7km2EX";
+
+    fn create_test_key() -> KeyPair {
+        KeyPair::from_secret_hex(&"a1".repeat(32)).unwrap()
+    }
+
+    fn create_test_token(id: &str, symbol: &str) -> Token {
+        Token::new(id.parse().unwrap(), symbol.to_string(), symbol.to_string(), 18, 0)
+    }
+
+    fn create_test_swap() -> Swap {
+        Swap {
+            token_one: TokenId::ALPH,
+            token_two: USDT.parse().unwrap(),
+            reserve_one: 0,
+            reserve_two: 0,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 0
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        let address = create_test_key().address();
+        let node = MockNode::start(vec![Route::get(&format!("/addresses/{}/balance", address), mock::BALANCE)]);
+
+        let balance = NodeClient::new(node.url()).balance(&address).unwrap();
+        // past what a u64 holds
+        assert_eq!(balance.alph_amount, 25_000_500_000_000_000_000_000);
+        assert_eq!(balance.locked_alph_amount, 500_000_000_000_000_000);
+        assert_eq!(balance.tokens, vec![(USDT.parse().unwrap(), 2_500_000), (WETH.parse().unwrap(), 20_000_000_000_000_000_000)]);
+        assert!(balance.locked_tokens.is_empty());
+        assert_eq!(balance.utxo_count, 2);
+    }
+
+    #[test]
+    fn test_refresh_account() {
+        let key = create_test_key();
+        let node = MockNode::start(vec![Route::get(&format!("/addresses/{}/balance", key.address()), mock::BALANCE)]);
+        let client = NodeClient::new(node.url());
+
+        let (usdt, weth) = (create_test_token(USDT, "USDT"), create_test_token(WETH, "WETH"));
+        let mut account = key.account(1, vec![usdt.clone()]);
+        // no metadata for WETH yet, and the account is left alone
+        assert!(matches!(
+            client.refresh_account(&mut account),
+            Err(NodeError::Account(AccountError::UnknownToken(id))) if id == weth.id()
+        ));
+        assert_eq!(account.alph_balance(), 1);
+
+        account.add_token(weth.clone());
+        client.refresh_account(&mut account).unwrap();
+        assert_eq!(account.alph_balance(), 25_000_500_000_000_000_000_000);
+        assert_eq!(account.balance(&usdt.id()), 2_500_000);
+        assert_eq!(account.balance(&weth.id()), 20_000_000_000_000_000_000);
+
+        let mut anonymous = Account::new(0, vec![]);
+        assert!(matches!(client.refresh_account(&mut anonymous), Err(NodeError::NoAddress)));
+    }
+
+    #[test]
+    fn test_utxos() {
+        let key = create_test_key();
+        let address = key.address();
+        let node = MockNode::start(vec![Route::get(&format!("/addresses/{}/utxos", address), mock::UTXOS)]);
+
+        let utxos = NodeClient::new(node.url()).utxos(&address).unwrap();
+        let [first, second] = utxos.utxos() else { panic!("expected two utxos") };
+        assert_eq!(first.reference.hint, (-1_294_548_702i32) as u32);
+        assert_eq!(hex::encode(first.reference.key), "9f08bb4d4e4323cfa7fcd5f719329af1b28aa1be233f4c0fd4baab4b2e9f5d8d");
+        assert_eq!(first.output.lockup_script, address);
+        assert_eq!(first.output.tokens, vec![(USDT.parse().unwrap(), 2_500_000)]);
+        assert_eq!(second.output.lock_time, 1_767_225_600_000);
+        assert_eq!(second.output.additional_data, vec![0]);
+
+        // and they add up to the balance
+        assert_eq!(utxos.balance(&TokenId::ALPH).unwrap(), 25_000_500_000_000_000_000_000);
+        assert_eq!(utxos.spendable_balance(&TokenId::ALPH, 0).unwrap(), 25_000_000_000_000_000_000_000);
+    }
+
+    #[test]
+    fn test_contract_state() {
+        let pool: Address = POOL.parse().unwrap();
+        let node = MockNode::start(vec![Route::get(&format!("/contracts/{}/state", POOL), mock::CONTRACT_STATE)]);
+
+        let state = NodeClient::new(node.url()).contract_state(&pool).unwrap();
+        assert_eq!(state.address, pool);
+        assert!(state.immutable_fields.is_empty());
+        assert_eq!(state.mutable_fields.len(), 5);
+        assert_eq!(state.mutable_fields[0], Val::U256(U256::from(4_000_000_000_000_000_000u128)));
+        assert_eq!(state.alph_amount, U256::from(4_000_000_000_000_000_000));
+        assert_eq!(state.tokens, vec![(USDT.parse().unwrap(), U256::from(100_000_000))]);
+
+        // the same body, saved
+        assert_eq!(ContractState::from_json(mock::CONTRACT_STATE).unwrap(), state);
+        assert!(matches!(ContractState::from_json("{}"), Err(NodeError::Json(_))));
+    }
+
+    #[test]
+    fn test_refresh_swap() {
+        let pool: Address = POOL.parse().unwrap();
+        let node = MockNode::start(vec![Route::get(&format!("/contracts/{}/state", POOL), mock::CONTRACT_STATE)]);
+        let client = NodeClient::new(node.url());
+
+        let mut swap = create_test_swap();
+        client.refresh_swap(&mut swap, &pool).unwrap();
+        assert_eq!((swap.reserve_one, swap.reserve_two), (4_000_000_000_000_000_000, 100_000_000));
+        assert_eq!((swap.fee_one, swap.fee_two), (12_000_000_000_000_000, 30_000));
+        assert_eq!(swap.total_supply, 20_000_000_000_000);
+        // configuration is the caller's
+        assert_eq!((swap.token_two, swap.fee), (USDT.parse().unwrap(), 30));
+
+        // a contract with a different layout
+        let other = Address::contract(ContractId::new([7; 32]));
+        let state = mock::CONTRACT_STATE.replace(r#""type": "U256", "value": "30000""#, r#""type": "Bool", "value": true"#);
+        let node = MockNode::start(vec![Route::get(&format!("/contracts/{}/state", other), &state)]);
+        let result = NodeClient::new(node.url()).refresh_swap(&mut swap, &other);
+        assert!(matches!(result, Err(NodeError::UnexpectedField(FEE_TWO_FIELD))));
+        assert_eq!(swap.fee_two, 30_000);
+
+        // a pool on another pair
+        let mut weth_swap = Swap { token_two: WETH.parse().unwrap(), ..create_test_swap() };
+        let result = client.refresh_swap(&mut weth_swap, &pool);
+        assert!(matches!(result, Err(NodeError::WrongPair(id)) if id == WETH.parse().unwrap()));
+        assert_eq!(weth_swap.reserve_one, 0);
+    }
+
+    #[test]
+    fn test_contract_events() {
+        let pool: Address = POOL.parse().unwrap();
+        let path = format!("/events/contract/{}?start=0&limit=2", POOL);
+        let node = MockNode::start(vec![Route::get(&path, mock::EVENTS)]);
+
+        let page = NodeClient::new(node.url()).contract_events(&pool, 0, 2).unwrap();
+        assert_eq!(page.next_start, 2);
+        let [swap, add] = &page.events[..] else { panic!("expected two events") };
+        assert_eq!(hex::encode(swap.tx_id), "66e9f787106bf68431827fc3cde3db92705e9ca984d404516a2c8014b30c8142");
+        assert_eq!(swap.event_index, 0);
+        assert_eq!(swap.fields[0], Val::Address(SENDER.parse().unwrap()));
+        assert_eq!(swap.fields[1], Val::Bool(true));
+        assert_eq!(swap.fields[3].as_u64(), Some(2_483_908));
+        assert_eq!(add.event_index, 1);
+        assert_eq!(add.fields.len(), 4);
+    }
+
+    #[test]
+    fn test_call_contract() {
+        let pool: Address = POOL.parse().unwrap();
+        let Address::P2C(pool_id) = pool else { panic!("expected a contract address") };
+        let node = MockNode::start(vec![Route::post("/contracts/call-contract", mock::CALL_CONTRACT)]);
+        let client = NodeClient::new(node.url());
+
+        let call = ContractCall::new(pool_id, abi::GET_RESERVES_METHOD, SENDER.parse().unwrap()).with_return_length(2);
+        let returns = client.call_contract(&call, 0).unwrap();
+        assert_eq!(returns, vec![Val::from(4_000_000_000_000_000_000), Val::from(100_000_000)]);
+
+        let request: serde_json::Value = serde_json::from_str(&node.requests()[0].body).unwrap();
+        assert_eq!(
+            request,
+            serde_json::json!({ "group": 0, "address": POOL, "callerAddress": SENDER, "methodIndex": 0, "args": [] })
+        );
+
+        // arguments go as typed json, approvals as the caller's assets
+        let swap = ContractCall::swap(pool_id, (TokenId::ALPH, USDT.parse().unwrap()), SENDER.parse().unwrap(), 2_500_000, false, 99_000_000_000_000_000);
+        client.call_contract(&swap, 0).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&node.requests()[1].body).unwrap();
+        assert_eq!(
+            request["args"],
+            serde_json::json!([
+                { "type": "Address", "value": SENDER },
+                { "type": "U256", "value": "2500000" },
+                { "type": "Bool", "value": false },
+                { "type": "U256", "value": "99000000000000000" }
+            ])
+        );
+        assert_eq!(
+            request["inputAssets"],
+            serde_json::json!([{ "address": SENDER, "asset": { "attoAlphAmount": "0", "tokens": [{ "id": USDT, "amount": "2500000" }] } }])
+        );
+
+        let failed = r#"{ "type": "CallContractFailed", "error": "AssertionFailedWithErrorCode" }"#;
+        let node = MockNode::start(vec![Route::post("/contracts/call-contract", failed)]);
+        let result = NodeClient::new(node.url()).call_contract(&call, 0);
+        assert!(matches!(result, Err(NodeError::CallFailed(error)) if error == "AssertionFailedWithErrorCode"));
+    }
+
+    #[test]
+    fn test_estimate_gas() {
+        let key = create_test_key();
+        let node = MockNode::start(vec![Route::post("/transactions/build", mock::BUILD)]);
+
+        let usdt: TokenId = USDT.parse().unwrap();
+        let destination = AssetOutput::new(1_000_000_000_000_000_000, SENDER.parse().unwrap()).with_tokens(vec![(usdt, 5)]);
+        let fee = NodeClient::new(node.url()).estimate_gas(&key.public_key(), &[destination]).unwrap();
+        assert_eq!((fee.gas_amount(), fee.gas_price()), (20_000, 100_000_000_000));
+
+        let request: serde_json::Value = serde_json::from_str(&node.requests()[0].body).unwrap();
+        assert_eq!(request["fromPublicKey"], hex::encode(key.public_key().as_bytes()));
+        assert_eq!(
+            request["destinations"],
+            serde_json::json!([{
+                "address": SENDER,
+                "attoAlphAmount": "1000000000000000000",
+                "tokens": [{ "id": USDT, "amount": "5" }]
+            }])
+        );
+    }
+
+    #[test]
+    fn test_submit() {
+        let key = create_test_key();
+        let node = MockNode::start(vec![Route::post("/transactions/submit", mock::SUBMIT)]);
+        let client = NodeClient::new(node.url()).with_api_key("secret");
+
+        let utxo = Utxo {
+            reference: OutputRef::new(&[1; 32], 0, &key.address()).unwrap(),
+            output: AssetOutput::new(10_000_000_000_000_000_000, key.address())
+        };
+        let utxos = UtxoSet::new(vec![utxo]);
+        let transaction = TransactionBuilder::new(&utxos, key.public_key(), Network::Devnet)
+            .pay(AssetOutput::new(1_000_000_000_000_000_000, SENDER.parse().unwrap()))
+            .build()
+            .unwrap();
+        let signature = key.sign(&transaction.id());
+
+        let tx_id = client.submit(&transaction, &signature).unwrap();
+        assert_eq!(hex::encode(tx_id), "bfca6c2610526915b21b4aa77e10a0d2ef67e897aa1a02b279dd1fb79d0718c4");
+
+        let recorded = &node.requests()[0];
+        assert_eq!(recorded.api_key.as_deref(), Some("secret"));
+        let request: serde_json::Value = serde_json::from_str(&recorded.body).unwrap();
+        assert_eq!(request["unsignedTx"], hex::encode(codec::serialize(&transaction)));
+        assert_eq!(request["signature"], hex::encode(signature.as_bytes()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let pool: Address = POOL.parse().unwrap();
+        let node = MockNode::start(vec![
+            Route::get(&format!("/contracts/{}/state", POOL), mock::NOT_FOUND).with_status(404),
+            Route::get(&format!("/contracts/{}/state", Address::contract(ContractId::new([7; 32]))), "{}"),
+            Route::post("/transactions/build", &mock::BUILD.replace("\"100000000000\"", "\"1e11\""))
+        ]);
+        let client = NodeClient::new(node.url());
+
+        match client.contract_state(&pool) {
+            Err(NodeError::Status { code, detail }) => {
+                assert_eq!(code, 404);
+                assert_eq!(detail, format!("Contract {} not found", POOL));
+            }
+            other => panic!("expected a 404, got {:?}", other)
+        }
+        assert!(matches!(client.contract_state(&Address::contract(ContractId::new([7; 32]))), Err(NodeError::Json(_))));
+        assert!(matches!(client.estimate_gas(&create_test_key().public_key(), &[]), Err(NodeError::InvalidResponse(_))));
+
+        // nothing listening
+        let url = node.url().to_string();
+        drop(node);
+        assert!(matches!(NodeClient::new(&url).balance(&pool), Err(NodeError::Transport(_))));
+    }
+}
@@ -0,0 +1,342 @@
+// ralph code generation
+
+// the pools are prototyped here and deployed as ralph contracts, so the
+// contracts are generated from the same configuration instead of written
+// by hand. the generated code follows the rust math step for step:
+//
+//   fee        amountIn * fee / 10000, rounded down, stays in the reserves
+//              and is tallied in feeOne / feeTwo
+//   swap out   rounded down; zero, or the whole reserve, is refused
+//   liquidity  sqrt(amountOne * amountTwo) for the first deposit, then the
+//              smaller pro rata share, all rounded down
+//
+// ralph has no floats, so the stable pool runs the same number of newton
+// steps in integers (and the square root is the integer one). results can
+// differ from the f64 prototype in the last unit.
+//
+// x^3 y + y^3 x is at most 2 max(x, y)^4, and a U256 holds that only while
+// both are below about 1.5e19. the stable pool solves on reserves divided
+// by the smallest power of ten that brings them to STABLE_MAX_RESERVE or
+// under, and rounds the new reserve up in the pool's favour; a pool that
+// shallow isn't scaled at all.
+//
+// swap takes the least the caller will accept, amountOutMin, as its last
+// argument and aborts below it.
+//
+// the golden files are written for the ralph compiler of RALPH_VERSION.
+
+use crate::contract::TokenId;
+use crate::swap::stable_swap::NEWTON_ITERATIONS;
+use crate::swap::{Pool, StableSwap, Swap};
+
+pub const RALPH_VERSION: &str = "alephium 3.0";
+
+// 2 * STABLE_MAX_RESERVE^4 must fit in a U256
+const STABLE_MAX_RESERVE: u128 = 10_000_000_000_000_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RalphError {
+    // contract names are identifiers starting with a capital letter
+    InvalidName(String),
+    // basis points, so at most 10000
+    InvalidFee(u64)
+}
+
+const HEADER: &str = "\
+// generated by alephium::ralph from a $KIND pool, do not edit
+// for the ralph compiler of $RALPH_VERSION
+
+Contract $NAME(
+    mut reserveOne: U256,
+    mut reserveTwo: U256,
+    mut feeOne: U256,
+    mut feeTwo: U256,
+    mut totalSupply: U256
+) {
+    const TokenOne = $TOKEN_ONE
+    const TokenTwo = $TOKEN_TWO
+    // basis points
+    const Fee = $FEE
+    const FeeBase = 10000
+$CONSTANTS
+    enum ErrorCodes {
+        AmountIsZero = 0
+        InsufficientLiquidity = 1
+        InsufficientOutput = 2
+    }
+
+    event Swap(sender: Address, tokenInIsOne: Bool, amountIn: U256, amountOut: U256)
+    event AddLiquidity(sender: Address, amountOne: U256, amountTwo: U256, liquidity: U256)
+    event RemoveLiquidity(sender: Address, amountOne: U256, amountTwo: U256, liquidity: U256)
+
+    pub fn getReserves() -> (U256, U256) {
+        return reserveOne, reserveTwo
+    }
+
+    pub fn getTotalSupply() -> U256 {
+        return totalSupply
+    }
+";
+
+const SWAP: &str = "
+    @using(preapprovedAssets = true, assetsInContract = true, updateFields = true)
+    pub fn swap(sender: Address, amountIn: U256, tokenInIsOne: Bool, amountOutMin: U256) -> U256 {
+        assert!(amountIn > 0, ErrorCodes.AmountIsZero)
+
+        let reserveIn = if (tokenInIsOne) reserveOne else reserveTwo
+        let reserveOut = if (tokenInIsOne) reserveTwo else reserveOne
+        let feeAmount = amountIn * Fee / FeeBase
+        let amountInAfterFee = amountIn - feeAmount
+$AMOUNT_OUT
+        assert!(amountOut > 0 && amountOut < reserveOut, ErrorCodes.InsufficientLiquidity)
+        assert!(amountOut >= amountOutMin, ErrorCodes.InsufficientOutput)
+
+        if (tokenInIsOne) {
+            transferTokenToSelf!(sender, TokenOne, amountIn)
+            transferTokenFromSelf!(sender, TokenTwo, amountOut)
+            reserveOne = reserveOne + amountIn
+            reserveTwo = reserveTwo - amountOut
+            feeOne = feeOne + feeAmount
+        } else {
+            transferTokenToSelf!(sender, TokenTwo, amountIn)
+            transferTokenFromSelf!(sender, TokenOne, amountOut)
+            reserveTwo = reserveTwo + amountIn
+            reserveOne = reserveOne - amountOut
+            feeTwo = feeTwo + feeAmount
+        }
+
+        emit Swap(sender, tokenInIsOne, amountIn, amountOut)
+        return amountOut
+    }
+";
+
+const CONSTANT_PRODUCT_OUT: &str = "        let amountOut = reserveOut * amountInAfterFee / (reserveIn + amountInAfterFee)";
+
+// a new reserve above the old one underflows and aborts, one of zero
+// fails the check on amountOut
+const STABLE_OUT: &str = "        let newReserveIn = reserveIn + amountInAfterFee
+        let scale = stableScale(if (newReserveIn > reserveOut) newReserveIn else reserveOut)
+        let k = invariant(reserveIn / scale, reserveOut / scale)
+        let mut newReserveOut = solveY(newReserveIn / scale, k, reserveOut / scale)
+        if (scale > 1) {
+            newReserveOut = (newReserveOut + 1) * scale
+        }
+        let amountOut = reserveOut - newReserveOut";
+
+const LIQUIDITY: &str = "
+    @using(preapprovedAssets = true, assetsInContract = true, updateFields = true)
+    pub fn addLiquidity(sender: Address, amountOne: U256, amountTwo: U256) -> U256 {
+        assert!(amountOne > 0 && amountTwo > 0, ErrorCodes.AmountIsZero)
+
+        let mut liquidity = 0
+        if (reserveOne == 0 && reserveTwo == 0) {
+            liquidity = sqrt(amountOne * amountTwo)
+        } else {
+            let liquidityOne = amountOne * totalSupply / reserveOne
+            let liquidityTwo = amountTwo * totalSupply / reserveTwo
+            liquidity = if (liquidityOne < liquidityTwo) liquidityOne else liquidityTwo
+        }
+
+        transferTokenToSelf!(sender, TokenOne, amountOne)
+        transferTokenToSelf!(sender, TokenTwo, amountTwo)
+        transferTokenFromSelf!(sender, selfTokenId!(), liquidity)
+        reserveOne = reserveOne + amountOne
+        reserveTwo = reserveTwo + amountTwo
+        totalSupply = totalSupply + liquidity
+
+        emit AddLiquidity(sender, amountOne, amountTwo, liquidity)
+        return liquidity
+    }
+
+    @using(preapprovedAssets = true, assetsInContract = true, updateFields = true)
+    pub fn removeLiquidity(sender: Address, liquidity: U256) -> (U256, U256) {
+        assert!(liquidity > 0, ErrorCodes.AmountIsZero)
+        assert!(liquidity <= totalSupply, ErrorCodes.InsufficientLiquidity)
+
+        let amountOne = reserveOne * liquidity / totalSupply
+        let amountTwo = reserveTwo * liquidity / totalSupply
+
+        transferTokenToSelf!(sender, selfTokenId!(), liquidity)
+        transferTokenFromSelf!(sender, TokenOne, amountOne)
+        transferTokenFromSelf!(sender, TokenTwo, amountTwo)
+        reserveOne = reserveOne - amountOne
+        reserveTwo = reserveTwo - amountTwo
+        totalSupply = totalSupply - liquidity
+
+        emit RemoveLiquidity(sender, amountOne, amountTwo, liquidity)
+        return amountOne, amountTwo
+    }
+
+    fn sqrt(y: U256) -> U256 {
+        if (y > 3) {
+            let mut z = y
+            let mut x = y / 2 + 1
+            while (x < z) {
+                z = x
+                x = (y / x + x) / 2
+            }
+            return z
+        }
+        if (y != 0) {
+            return 1
+        }
+        return 0
+    }
+";
+
+const STABLE_MATH: &str = "
+    // the power of ten that brings `reserve` to MaxReserve or under
+    fn stableScale(reserve: U256) -> U256 {
+        let mut scale = 1
+        while (reserve / scale > MaxReserve) {
+            scale = scale * 10
+        }
+        return scale
+    }
+
+    // k = x^3 y + y^3 x, both at most MaxReserve
+    fn invariant(x: U256, y: U256) -> U256 {
+        return x * x * x * y + y * y * y * x
+    }
+
+    // newton's method on x y^3 + x^3 y - k = 0
+    fn solveY(x: U256, k: U256, initialGuess: U256) -> U256 {
+        let mut y = initialGuess
+        let mut i = 0
+        while (i < NewtonIterations) {
+            let slope = 3 * x * y * y + x * x * x
+            if (slope == 0) {
+                return y
+            }
+            let value = invariant(x, y)
+            let mut next = y
+            if (value > k) {
+                next = y - (value - k) / slope
+            } else {
+                next = y + (k - value) / slope
+            }
+            if (next == y) {
+                return y
+            }
+            y = next
+            i = i + 1
+        }
+        return y
+    }
+";
+
+const FOOTER: &str = "}\n";
+
+pub fn constant_product(swap: &Swap, name: &str) -> Result<String, RalphError> {
+    let header = header(name, "constant product", swap.token_one, swap.token_two, swap.fee, "")?;
+    Ok([&header, &SWAP.replace("$AMOUNT_OUT", CONSTANT_PRODUCT_OUT), LIQUIDITY, FOOTER].concat())
+}
+
+pub fn stable_swap(swap: &StableSwap, name: &str) -> Result<String, RalphError> {
+    let constants = format!("    const NewtonIterations = {}\n    const MaxReserve = {}\n", NEWTON_ITERATIONS, STABLE_MAX_RESERVE);
+    let header = header(name, "stable swap", swap.token_one, swap.token_two, swap.fee, &constants)?;
+    Ok([&header, &SWAP.replace("$AMOUNT_OUT", STABLE_OUT), LIQUIDITY, STABLE_MATH, FOOTER].concat())
+}
+
+pub fn generate(pool: &Pool, name: &str) -> Result<String, RalphError> {
+    match pool {
+        Pool::Swap(swap) => constant_product(swap, name),
+        Pool::StableSwap(swap) => stable_swap(swap, name)
+    }
+}
+
+fn header(name: &str, kind: &str, token_one: TokenId, token_two: TokenId, fee: u64, constants: &str) -> Result<String, RalphError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_uppercase()) && name.chars().all(|c| c.is_ascii_alphanumeric());
+    if !valid {
+        return Err(RalphError::InvalidName(name.to_string()));
+    }
+    if fee > 10_000 {
+        return Err(RalphError::InvalidFee(fee));
+    }
+
+    Ok(HEADER
+        .replace("$KIND", kind)
+        .replace("$NAME", name)
+        .replace("$RALPH_VERSION", RALPH_VERSION)
+        .replace("$TOKEN_ONE", &token_literal(token_one))
+        .replace("$TOKEN_TWO", &token_literal(token_two))
+        .replace("$FEE", &fee.to_string())
+        .replace("$CONSTANTS", constants))
+}
+
+fn token_literal(id: TokenId) -> String {
+    if id.is_alph() {
+        "ALPH".to_string()
+    } else {
+        format!("#{}", id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const USDC: TokenId = TokenId::new([0x18; 32]);
+
+    fn create_test_swap() -> Swap {
+        Swap { token_one: TokenId::ALPH, token_two: USDT, reserve_one: 0, reserve_two: 0, fee: 30, fee_one: 0, fee_two: 0, total_supply: 0 }
+    }
+
+    fn create_test_stable_swap() -> StableSwap {
+        StableSwap { token_one: USDT, token_two: USDC, reserve_one: 0, reserve_two: 0, fee: 4, fee_one: 0, fee_two: 0, total_supply: 0 }
+    }
+
+    // UPDATE_GOLDEN=1 cargo test rewrites the files instead
+    fn assert_golden(generated: &str, file: &str, golden: &str) {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let path = format!("{}/src/ralph/golden/{}", env!("CARGO_MANIFEST_DIR"), file);
+            std::fs::write(path, generated).unwrap();
+            return;
+        }
+        assert_eq!(generated, golden, "{} is out of date", file);
+    }
+
+    #[test]
+    fn test_constant_product_golden() {
+        let generated = constant_product(&create_test_swap(), "AlphUsdtPool").unwrap();
+        assert_golden(&generated, "constant_product.ral", include_str!("golden/constant_product.ral"));
+    }
+
+    #[test]
+    fn test_stable_swap_golden() {
+        let generated = stable_swap(&create_test_stable_swap(), "UsdtUsdcPool").unwrap();
+        assert_golden(&generated, "stable_swap.ral", include_str!("golden/stable_swap.ral"));
+    }
+
+    #[test]
+    fn test_follows_the_configuration() {
+        let swap = Swap { fee: 100, ..create_test_swap() };
+        let generated = generate(&Pool::Swap(swap), "Pool").unwrap();
+        assert!(generated.contains("const Fee = 100\n"));
+        assert!(generated.contains("const TokenOne = ALPH\n"));
+        assert!(generated.contains(&format!("const TokenTwo = #{}\n", USDT)));
+        assert!(!generated.contains('$'));
+
+        let stable = generate(&Pool::StableSwap(create_test_stable_swap()), "Pool").unwrap();
+        assert!(stable.contains(&format!("const NewtonIterations = {}\n", NEWTON_ITERATIONS)));
+        assert!(stable.contains(&format!("const MaxReserve = {}\n", STABLE_MAX_RESERVE)));
+        assert!(stable.contains(&format!("// for the ralph compiler of {}\n", RALPH_VERSION)));
+    }
+
+    #[test]
+    fn test_stable_bound() {
+        // x^3 y + y^3 x <= 2 m^4 <= 2^256 - 1 needs m^2 <= 2^127.5
+        assert!(STABLE_MAX_RESERVE.pow(2) <= 1 << 127);
+        // and the bound is no looser than a power of ten has to be
+        assert!((STABLE_MAX_RESERVE * 10).checked_pow(2).is_none_or(|square| square > 1 << 127));
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        assert_eq!(constant_product(&create_test_swap(), "pool"), Err(RalphError::InvalidName("pool".to_string())));
+        assert_eq!(constant_product(&create_test_swap(), "My Pool"), Err(RalphError::InvalidName("My Pool".to_string())));
+        let swap = Swap { fee: 10_001, ..create_test_swap() };
+        assert_eq!(constant_product(&swap, "Pool"), Err(RalphError::InvalidFee(10_001)));
+    }
+}
@@ -0,0 +1,343 @@
+// trade replay simulator
+
+// replaces the notebook: take a pool, feed it a log of swaps and liquidity
+// events, and record what the pool looked like after every step.
+//
+// trade logs are either csv or a json array with the same columns:
+//
+//   action,token_in,amount,amount_one,amount_two,liquidity
+//   swap,one,100,,,
+//   add_liquidity,,,1000,2000,
+//   remove_liquidity,,,,,50
+//
+// `token_in` is "one"/"two" or the hex id of one of the pool's tokens.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::contract::TokenId;
+use crate::swap::{Pool, PoolError};
+
+#[derive(Debug)]
+pub enum SimulatorError {
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    // line (1-based record number) and what was wrong with it
+    InvalidRecord(usize, String)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Swap { amount_in: u128, token_in_is_one: bool },
+    AddLiquidity { amount_one: u128, amount_two: u128 },
+    RemoveLiquidity { liquidity_tokens: u128 }
+}
+
+impl Action {
+
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Swap { .. } => "swap",
+            Action::AddLiquidity { .. } => "add_liquidity",
+            Action::RemoveLiquidity { .. } => "remove_liquidity"
+        }
+    }
+}
+
+// one row of a trade log, before validation
+#[derive(Debug, Deserialize)]
+struct TradeRecord {
+    action: String,
+    token_in: Option<String>,
+    amount: Option<u128>,
+    amount_one: Option<u128>,
+    amount_two: Option<u128>,
+    liquidity: Option<u128>
+}
+
+impl TradeRecord {
+
+    fn into_action(self, line: usize, pool: &Pool) -> Result<Action, SimulatorError> {
+        let missing = |field: &str| SimulatorError::InvalidRecord(line, format!("{} needs `{}`", self.action, field));
+
+        match self.action.as_str() {
+            "swap" => {
+                let token_in = self.token_in.as_deref().ok_or_else(|| missing("token_in"))?;
+                let (token_one, token_two) = pool.tokens();
+                let token_in_is_one = match (token_in, token_in.parse::<TokenId>()) {
+                    ("one", _) => true,
+                    ("two", _) => false,
+                    (_, Ok(id)) if id == token_one => true,
+                    (_, Ok(id)) if id == token_two => false,
+                    (other, _) => return Err(SimulatorError::InvalidRecord(line, format!("unknown token `{}`", other)))
+                };
+                let amount_in = self.amount.ok_or_else(|| missing("amount"))?;
+                Ok(Action::Swap { amount_in, token_in_is_one })
+            }
+            "add_liquidity" => Ok(Action::AddLiquidity {
+                amount_one: self.amount_one.ok_or_else(|| missing("amount_one"))?,
+                amount_two: self.amount_two.ok_or_else(|| missing("amount_two"))?
+            }),
+            "remove_liquidity" => Ok(Action::RemoveLiquidity {
+                liquidity_tokens: self.liquidity.ok_or_else(|| missing("liquidity"))?
+            }),
+            other => Err(SimulatorError::InvalidRecord(line, format!("unknown action `{}`", other)))
+        }
+    }
+}
+
+pub fn read_trades_csv<R: Read>(reader: R, pool: &Pool) -> Result<Vec<Action>, SimulatorError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    reader.deserialize::<TradeRecord>()
+        .enumerate()
+        .map(|(i, record)| record.map_err(SimulatorError::Csv)?.into_action(i + 1, pool))
+        .collect()
+}
+
+pub fn read_trades_json<R: Read>(reader: R, pool: &Pool) -> Result<Vec<Action>, SimulatorError> {
+    let records: Vec<TradeRecord> = serde_json::from_reader(reader).map_err(SimulatorError::Json)?;
+    records.into_iter()
+        .enumerate()
+        .map(|(i, record)| record.into_action(i + 1, pool))
+        .collect()
+}
+
+// pool state after one step, written out as one csv row
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepRecord {
+    pub step: usize,
+    pub action: &'static str,
+    pub status: &'static str,
+    pub amount_in_one: u128,
+    pub amount_in_two: u128,
+    pub amount_out_one: u128,
+    pub amount_out_two: u128,
+    pub liquidity: u128,
+    pub reserve_one: u128,
+    pub reserve_two: u128,
+    pub price: f64,
+    pub fee_one: u128,
+    pub fee_two: u128,
+    pub total_supply: u128,
+    // value of one lp token, in units of token two
+    pub lp_value: f64
+}
+
+fn status(e: PoolError) -> &'static str {
+    match e {
+        PoolError::AmountIsZero => "amount_is_zero",
+        PoolError::InsufficientLiquidity => "insufficient_liquidity",
+        PoolError::Overflow => "overflow"
+    }
+}
+
+pub struct Simulator {
+    pool: Pool,
+    steps: usize
+}
+
+impl Simulator {
+
+    pub fn new(pool: Pool) -> Self {
+        Simulator { pool, steps: 0 }
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    // apply one action; a failed action leaves the pool alone and is
+    // recorded with its error as the status
+    pub fn step(&mut self, action: Action) -> StepRecord {
+        self.steps += 1;
+
+        let (mut in_one, mut in_two, mut out_one, mut out_two, mut liquidity) = (0, 0, 0, 0, 0);
+        let result = match action {
+            Action::Swap { amount_in, token_in_is_one } => {
+                self.pool.swap(amount_in, token_in_is_one).map(|amount_out| {
+                    if token_in_is_one {
+                        (in_one, out_two) = (amount_in, amount_out);
+                    } else {
+                        (in_two, out_one) = (amount_in, amount_out);
+                    }
+                })
+            }
+            Action::AddLiquidity { amount_one, amount_two } => {
+                self.pool.add_liquidity(amount_one, amount_two).map(|minted| {
+                    (in_one, in_two, liquidity) = (amount_one, amount_two, minted);
+                })
+            }
+            Action::RemoveLiquidity { liquidity_tokens } => {
+                self.pool.remove_liquidity(liquidity_tokens).map(|(amount_one, amount_two)| {
+                    (out_one, out_two, liquidity) = (amount_one, amount_two, liquidity_tokens);
+                })
+            }
+        };
+
+        let (reserve_one, reserve_two) = self.pool.reserves();
+        let (fee_one, fee_two) = self.pool.fees();
+        let price = self.pool.spot_price();
+        let total_supply = self.pool.total_supply();
+        let lp_value = if total_supply == 0 {
+            0.0
+        } else {
+            (reserve_one as f64 * price + reserve_two as f64) / total_supply as f64
+        };
+
+        StepRecord {
+            step: self.steps,
+            action: action.name(),
+            status: result.map_or_else(status, |_| "ok"),
+            amount_in_one: in_one,
+            amount_in_two: in_two,
+            amount_out_one: out_one,
+            amount_out_two: out_two,
+            liquidity,
+            reserve_one,
+            reserve_two,
+            price,
+            fee_one,
+            fee_two,
+            total_supply,
+            lp_value
+        }
+    }
+
+    pub fn replay(&mut self, actions: &[Action]) -> Vec<StepRecord> {
+        actions.iter().map(|action| self.step(*action)).collect()
+    }
+}
+
+pub fn write_csv<W: Write>(records: &[StepRecord], writer: W) -> Result<(), SimulatorError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for record in records {
+        writer.serialize(record).map_err(SimulatorError::Csv)?;
+    }
+    writer.flush().map_err(|e| SimulatorError::Csv(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::{StableSwap, Swap};
+
+    const ALPH: TokenId = TokenId::ALPH;
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const USDC: TokenId = TokenId::new([0x1c; 32]);
+
+    fn create_test_pool() -> Pool {
+        Pool::Swap(Swap {
+            token_one: ALPH,
+            token_two: USDT,
+            reserve_one: 0,
+            reserve_two: 0,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 0,
+        })
+    }
+
+    #[test]
+    fn test_read_trades_csv() {
+        let pool = create_test_pool();
+        let log = format!("action,token_in,amount,amount_one,amount_two,liquidity\n\
+                           add_liquidity,,,1000,2000,\n\
+                           swap,one,100,,,\n\
+                           swap, {} ,50,,,\n\
+                           remove_liquidity,,,,,10\n", USDT);
+        let actions = read_trades_csv(log.as_bytes(), &pool).unwrap();
+        assert_eq!(actions, vec![
+            Action::AddLiquidity { amount_one: 1000, amount_two: 2000 },
+            Action::Swap { amount_in: 100, token_in_is_one: true },
+            Action::Swap { amount_in: 50, token_in_is_one: false },
+            Action::RemoveLiquidity { liquidity_tokens: 10 },
+        ]);
+    }
+
+    #[test]
+    fn test_read_trades_json_matches_csv() {
+        let pool = create_test_pool();
+        let json = format!(r#"[
+            {{ "action": "add_liquidity", "amount_one": 1000, "amount_two": 2000 }},
+            {{ "action": "swap", "token_in": "{}", "amount": 100 }}
+        ]"#, ALPH);
+        let csv = "action,token_in,amount,amount_one,amount_two,liquidity\n\
+                   add_liquidity,,,1000,2000,\n\
+                   swap,one,100,,,\n";
+        assert_eq!(read_trades_json(json.as_bytes(), &pool).unwrap(), read_trades_csv(csv.as_bytes(), &pool).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        let pool = create_test_pool();
+        let json = r#"[{ "action": "swap", "amount": 100 }]"#;
+        assert!(matches!(read_trades_json(json.as_bytes(), &pool), Err(SimulatorError::InvalidRecord(1, _))));
+
+        let json = r#"[{ "action": "swap", "token_in": "one", "amount": 1 }, { "action": "flash_loan" }]"#;
+        assert!(matches!(read_trades_json(json.as_bytes(), &pool), Err(SimulatorError::InvalidRecord(2, _))));
+
+        let json = format!(r#"[{{ "action": "swap", "token_in": "{}", "amount": 1 }}]"#, USDC);
+        assert!(matches!(read_trades_json(json.as_bytes(), &pool), Err(SimulatorError::InvalidRecord(1, _))));
+    }
+
+    #[test]
+    fn test_replay_tracks_pool() {
+        let mut simulator = Simulator::new(create_test_pool());
+        let records = simulator.replay(&[
+            Action::AddLiquidity { amount_one: 1000, amount_two: 2000 },
+            Action::Swap { amount_in: 1000, token_in_is_one: true },
+            Action::RemoveLiquidity { liquidity_tokens: 100 },
+        ]);
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].liquidity, 1414);
+        assert_eq!(records[0].price, 2.0);
+
+        let swap = &records[1];
+        assert_eq!(swap.status, "ok");
+        assert_eq!(swap.amount_in_one, 1000);
+        assert_eq!(swap.reserve_one, 2000);
+        assert_eq!(swap.reserve_two, 2000 - swap.amount_out_two);
+        assert_eq!(swap.fee_one, 3);
+        // fees stay in the pool, so k grows
+        assert!(swap.reserve_one * swap.reserve_two > 1000 * 2000);
+
+        let removal = &records[2];
+        assert_eq!(removal.total_supply, 1314);
+        assert_eq!(removal.reserve_one, 2000 - removal.amount_out_one);
+        assert_eq!(&simulator.pool().reserves(), &(removal.reserve_one, removal.reserve_two));
+    }
+
+    #[test]
+    fn test_failed_step_is_recorded() {
+        let mut simulator = Simulator::new(Pool::StableSwap(StableSwap {
+            token_one: USDT,
+            token_two: USDC,
+            reserve_one: 1000,
+            reserve_two: 1000,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 1000,
+        }));
+
+        let record = simulator.step(Action::Swap { amount_in: 0, token_in_is_one: true });
+        assert_eq!(record.status, "amount_is_zero");
+        assert_eq!((record.reserve_one, record.reserve_two), (1000, 1000));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut simulator = Simulator::new(create_test_pool());
+        let records = simulator.replay(&[Action::AddLiquidity { amount_one: 1000, amount_two: 2000 }]);
+
+        let mut out = Vec::new();
+        write_csv(&records, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("step,action,status,amount_in_one,amount_in_two,amount_out_one,amount_out_two,liquidity,reserve_one,reserve_two,price,fee_one,fee_two,total_supply,lp_value"));
+        assert!(lines.next().unwrap().starts_with("1,add_liquidity,ok,1000,2000,0,0,1414,1000,2000,2.0,0,0,1414,"));
+    }
+}
@@ -0,0 +1,813 @@
+// pool and account snapshots
+
+// snapshots are plain mirrors of the live structs so the saved format only
+// changes when we say so. any change to the shapes below means bumping
+// SNAPSHOT_VERSION and teaching `Versioned` how to upgrade the old one.
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::account::{Account, AccountError, Token};
+use crate::address::{blake2b, Address, AddressError};
+use crate::contract::TokenId;
+use crate::swap::{Pool, StableSwap, Swap};
+
+// 1: tokens named by symbol, account tokens carry their address (free-form
+//    text at first, base58 contract addresses later)
+// 2: tokens named by hex id everywhere
+// 3: accounts may carry their own address
+// 4: account token balances, separate from token metadata
+// 5: account and pool amounts are u128
+pub const SNAPSHOT_VERSION: u16 = 5;
+
+// binary layout: magic (4 bytes) | version (u16 little endian) | postcard body
+const BINARY_MAGIC: [u8; 4] = *b"ALPS";
+const BINARY_HEADER_LEN: usize = BINARY_MAGIC.len() + 2;
+
+// pool fees are in basis points, so anything above is a broken file
+const MAX_FEE: u64 = 10_000;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Json(serde_json::Error),
+    Binary(postcard::Error),
+    BadMagic,
+    MissingVersion,
+    UnsupportedVersion(u16),
+    WrongKind,
+    Address(AddressError),
+    Account(AccountError),
+    InvalidFee(u64)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    pub token_one: TokenId,
+    pub token_two: TokenId,
+    pub reserve_one: u128,
+    pub reserve_two: u128,
+    pub fee: u64,
+    pub fee_one: u128,
+    pub fee_two: u128,
+    pub total_supply: u128
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenSnapshot {
+    pub id: TokenId,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u64,
+    pub supply: u128
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub address: Option<Address>,
+    pub alph_amount: u128,
+    pub tokens: Vec<TokenSnapshot>,
+    pub balances: BTreeMap<TokenId, u128>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Snapshot {
+    Swap(PoolSnapshot),
+    StableSwap(PoolSnapshot),
+    Account(AccountSnapshot)
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u16,
+    snapshot: &'a Snapshot
+}
+
+// the two halves of an envelope, read separately since the version decides
+// the body's shape. the body goes from the text straight into its struct:
+// through a serde_json::Value, amounts past u64 would come out as floats
+#[derive(Deserialize)]
+struct Header {
+    version: Option<serde_json::Value>
+}
+
+#[derive(Deserialize)]
+struct Body<T> {
+    snapshot: Option<T>
+}
+
+fn json_body<T: DeserializeOwned>(json: &str) -> Result<T, SnapshotError> {
+    let body: Body<T> = serde_json::from_str(json).map_err(SnapshotError::Json)?;
+    body.snapshot.ok_or(SnapshotError::WrongKind)
+}
+
+mod v1 {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct TokenSnapshot {
+        // base58 P2C address of the token's contract, or any label in the
+        // oldest snapshots
+        pub address: String,
+        pub name: String,
+        pub symbol: String,
+        pub decimals: u64,
+        pub supply: u64
+    }
+
+    #[derive(Deserialize)]
+    pub struct AccountSnapshot {
+        pub alph_amount: u64,
+        pub tokens: Vec<TokenSnapshot>
+    }
+
+    // token_one/token_two held symbols
+    #[derive(Deserialize)]
+    pub struct PoolSnapshot {
+        pub token_one: String,
+        pub token_two: String,
+        pub reserve_one: u64,
+        pub reserve_two: u64,
+        pub fee: u64,
+        pub fee_one: u64,
+        pub fee_two: u64,
+        pub total_supply: u64
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Snapshot {
+        Swap(PoolSnapshot),
+        StableSwap(PoolSnapshot),
+        Account(AccountSnapshot)
+    }
+}
+
+mod v2 {
+    use serde::Deserialize;
+
+    use super::v4::{PoolSnapshot, TokenSnapshot};
+
+    #[derive(Deserialize)]
+    pub struct AccountSnapshot {
+        pub alph_amount: u64,
+        pub tokens: Vec<TokenSnapshot>
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Snapshot {
+        Swap(PoolSnapshot),
+        StableSwap(PoolSnapshot),
+        Account(AccountSnapshot)
+    }
+}
+
+mod v3 {
+    use serde::Deserialize;
+
+    use super::v4::{PoolSnapshot, TokenSnapshot};
+    use crate::address::Address;
+
+    #[derive(Deserialize)]
+    pub struct AccountSnapshot {
+        pub address: Option<Address>,
+        pub alph_amount: u64,
+        pub tokens: Vec<TokenSnapshot>
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Snapshot {
+        Swap(PoolSnapshot),
+        StableSwap(PoolSnapshot),
+        Account(AccountSnapshot)
+    }
+}
+
+// amounts still u64
+mod v4 {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use crate::address::Address;
+    use crate::contract::TokenId;
+
+    #[derive(Deserialize)]
+    pub struct PoolSnapshot {
+        pub token_one: TokenId,
+        pub token_two: TokenId,
+        pub reserve_one: u64,
+        pub reserve_two: u64,
+        pub fee: u64,
+        pub fee_one: u64,
+        pub fee_two: u64,
+        pub total_supply: u64
+    }
+
+    #[derive(Deserialize)]
+    pub struct TokenSnapshot {
+        pub id: TokenId,
+        pub name: String,
+        pub symbol: String,
+        pub decimals: u64,
+        pub supply: u64
+    }
+
+    #[derive(Deserialize)]
+    pub struct AccountSnapshot {
+        pub address: Option<Address>,
+        pub alph_amount: u64,
+        pub tokens: Vec<TokenSnapshot>,
+        pub balances: BTreeMap<TokenId, u64>
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Snapshot {
+        Swap(PoolSnapshot),
+        StableSwap(PoolSnapshot),
+        Account(AccountSnapshot)
+    }
+}
+
+// every schema we have ever written, oldest first
+enum Versioned {
+    V1(v1::Snapshot),
+    V2(v2::Snapshot),
+    V3(v3::Snapshot),
+    V4(v4::Snapshot),
+    V5(Snapshot)
+}
+
+impl Versioned {
+
+    fn from_json(version: u16, json: &str) -> Result<Self, SnapshotError> {
+        match version {
+            1 => Ok(Versioned::V1(json_body(json)?)),
+            2 => Ok(Versioned::V2(json_body(json)?)),
+            3 => Ok(Versioned::V3(json_body(json)?)),
+            4 => Ok(Versioned::V4(json_body(json)?)),
+            5 => Ok(Versioned::V5(json_body(json)?)),
+            v => Err(SnapshotError::UnsupportedVersion(v))
+        }
+    }
+
+    fn from_binary(version: u16, body: &[u8]) -> Result<Self, SnapshotError> {
+        match version {
+            1 => Ok(Versioned::V1(postcard::from_bytes(body).map_err(SnapshotError::Binary)?)),
+            2 => Ok(Versioned::V2(postcard::from_bytes(body).map_err(SnapshotError::Binary)?)),
+            3 => Ok(Versioned::V3(postcard::from_bytes(body).map_err(SnapshotError::Binary)?)),
+            4 => Ok(Versioned::V4(postcard::from_bytes(body).map_err(SnapshotError::Binary)?)),
+            5 => Ok(Versioned::V5(postcard::from_bytes(body).map_err(SnapshotError::Binary)?)),
+            v => Err(SnapshotError::UnsupportedVersion(v))
+        }
+    }
+
+    // walk an old snapshot forward to the current schema
+    fn migrate(self) -> Result<Snapshot, SnapshotError> {
+        match self {
+            Versioned::V1(snapshot) => Versioned::V2(migrate_v1(snapshot)).migrate(),
+            Versioned::V2(snapshot) => Versioned::V3(migrate_v2(snapshot)).migrate(),
+            Versioned::V3(snapshot) => Versioned::V4(migrate_v3(snapshot)).migrate(),
+            Versioned::V4(snapshot) => Versioned::V5(migrate_v4(snapshot)).migrate(),
+            Versioned::V5(snapshot) => Ok(snapshot)
+        }
+    }
+}
+
+// v1 named a token however it liked: pools by symbol (or, later, hex id),
+// accounts by the token's P2C address (or, at first, any label). pools and
+// accounts go through the same rule so one v1 run stays consistent:
+//
+//   a hex token id        is that id
+//   a P2C address         is its contract's id
+//   anything else         gets a stand-in id, blake2b of the text
+//
+// stand-ins are the same on every load, so a pool and an account that named
+// a token by the same text still agree, but they match no real token
+fn v1_token_id(token: &str) -> TokenId {
+    if let Ok(id) = token.parse::<TokenId>() {
+        return id;
+    }
+    match token.parse::<Address>() {
+        Ok(Address::P2C(contract_id)) => TokenId::from(contract_id),
+        _ => TokenId::new(blake2b(token.as_bytes()))
+    }
+}
+
+fn migrate_v1(snapshot: v1::Snapshot) -> v2::Snapshot {
+    let migrate_pool = |pool: v1::PoolSnapshot| v4::PoolSnapshot {
+        token_one: v1_token_id(&pool.token_one),
+        token_two: v1_token_id(&pool.token_two),
+        reserve_one: pool.reserve_one,
+        reserve_two: pool.reserve_two,
+        fee: pool.fee,
+        fee_one: pool.fee_one,
+        fee_two: pool.fee_two,
+        total_supply: pool.total_supply
+    };
+
+    match snapshot {
+        v1::Snapshot::Swap(pool) => v2::Snapshot::Swap(migrate_pool(pool)),
+        v1::Snapshot::StableSwap(pool) => v2::Snapshot::StableSwap(migrate_pool(pool)),
+        v1::Snapshot::Account(account) => {
+            let tokens = account.tokens.into_iter()
+                .map(|t| v4::TokenSnapshot { id: v1_token_id(&t.address), name: t.name, symbol: t.symbol, decimals: t.decimals, supply: t.supply })
+                .collect();
+            v2::Snapshot::Account(v2::AccountSnapshot { alph_amount: account.alph_amount, tokens })
+        }
+    }
+}
+
+// accounts written before v3 never had an address
+fn migrate_v2(snapshot: v2::Snapshot) -> v3::Snapshot {
+    match snapshot {
+        v2::Snapshot::Swap(pool) => v3::Snapshot::Swap(pool),
+        v2::Snapshot::StableSwap(pool) => v3::Snapshot::StableSwap(pool),
+        v2::Snapshot::Account(account) => v3::Snapshot::Account(v3::AccountSnapshot {
+            address: None,
+            alph_amount: account.alph_amount,
+            tokens: account.tokens
+        })
+    }
+}
+
+// token balances weren't tracked before v4, so there are none to carry over
+fn migrate_v3(snapshot: v3::Snapshot) -> v4::Snapshot {
+    match snapshot {
+        v3::Snapshot::Swap(pool) => v4::Snapshot::Swap(pool),
+        v3::Snapshot::StableSwap(pool) => v4::Snapshot::StableSwap(pool),
+        v3::Snapshot::Account(account) => v4::Snapshot::Account(v4::AccountSnapshot {
+            address: account.address,
+            alph_amount: account.alph_amount,
+            tokens: account.tokens,
+            balances: BTreeMap::new()
+        })
+    }
+}
+
+// every u64 amount fits in a u128 as it is
+fn migrate_v4(snapshot: v4::Snapshot) -> Snapshot {
+    let migrate_pool = |pool: v4::PoolSnapshot| PoolSnapshot {
+        token_one: pool.token_one,
+        token_two: pool.token_two,
+        reserve_one: pool.reserve_one.into(),
+        reserve_two: pool.reserve_two.into(),
+        fee: pool.fee,
+        fee_one: pool.fee_one.into(),
+        fee_two: pool.fee_two.into(),
+        total_supply: pool.total_supply.into()
+    };
+
+    match snapshot {
+        v4::Snapshot::Swap(pool) => Snapshot::Swap(migrate_pool(pool)),
+        v4::Snapshot::StableSwap(pool) => Snapshot::StableSwap(migrate_pool(pool)),
+        v4::Snapshot::Account(account) => {
+            let tokens = account.tokens.into_iter()
+                .map(|t| TokenSnapshot { id: t.id, name: t.name, symbol: t.symbol, decimals: t.decimals, supply: t.supply.into() })
+                .collect();
+            Snapshot::Account(AccountSnapshot {
+                address: account.address,
+                alph_amount: account.alph_amount.into(),
+                tokens,
+                balances: account.balances.into_iter().map(|(id, amount)| (id, amount.into())).collect()
+            })
+        }
+    }
+}
+
+impl Snapshot {
+
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        let envelope = Envelope { version: SNAPSHOT_VERSION, snapshot: self };
+        serde_json::to_string_pretty(&envelope).map_err(SnapshotError::Json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        let header: Header = serde_json::from_str(json).map_err(SnapshotError::Json)?;
+        let version = header.version
+            .and_then(|v| v.as_u64())
+            .ok_or(SnapshotError::MissingVersion)?;
+        let version = u16::try_from(version).map_err(|_| SnapshotError::UnsupportedVersion(u16::MAX))?;
+
+        Versioned::from_json(version, json)?.migrate()?.validate()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&BINARY_MAGIC);
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        postcard::to_extend(self, bytes).map_err(SnapshotError::Binary)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < BINARY_HEADER_LEN || bytes[..4] != BINARY_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        Versioned::from_binary(version, &bytes[BINARY_HEADER_LEN..])?.migrate()?.validate()
+    }
+
+    // what the schema can't rule out on its own
+    fn validate(self) -> Result<Self, SnapshotError> {
+        match &self {
+            Snapshot::Swap(pool) | Snapshot::StableSwap(pool) if pool.fee > MAX_FEE => Err(SnapshotError::InvalidFee(pool.fee)),
+            _ => Ok(self)
+        }
+    }
+}
+
+impl From<&Swap> for Snapshot {
+    fn from(swap: &Swap) -> Self {
+        Snapshot::Swap(PoolSnapshot {
+            token_one: swap.token_one,
+            token_two: swap.token_two,
+            reserve_one: swap.reserve_one,
+            reserve_two: swap.reserve_two,
+            fee: swap.fee,
+            fee_one: swap.fee_one,
+            fee_two: swap.fee_two,
+            total_supply: swap.total_supply
+        })
+    }
+}
+
+impl From<&StableSwap> for Snapshot {
+    fn from(swap: &StableSwap) -> Self {
+        Snapshot::StableSwap(PoolSnapshot {
+            token_one: swap.token_one,
+            token_two: swap.token_two,
+            reserve_one: swap.reserve_one,
+            reserve_two: swap.reserve_two,
+            fee: swap.fee,
+            fee_one: swap.fee_one,
+            fee_two: swap.fee_two,
+            total_supply: swap.total_supply
+        })
+    }
+}
+
+impl From<&Pool> for Snapshot {
+    fn from(pool: &Pool) -> Self {
+        match pool {
+            Pool::Swap(swap) => Snapshot::from(swap),
+            Pool::StableSwap(swap) => Snapshot::from(swap)
+        }
+    }
+}
+
+impl From<&Account> for Snapshot {
+    fn from(account: &Account) -> Self {
+        let tokens = account.tokens.iter()
+            .map(|token| TokenSnapshot {
+                id: token.id,
+                name: token.name.clone(),
+                symbol: token.symbol.clone(),
+                decimals: token.decimals,
+                supply: token.supply
+            })
+            .collect();
+
+        Snapshot::Account(AccountSnapshot {
+            address: account.address.clone(),
+            alph_amount: account.alph_amount,
+            tokens,
+            balances: account.balances.clone()
+        })
+    }
+}
+
+impl TryFrom<Snapshot> for Swap {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot: Snapshot) -> Result<Self, Self::Error> {
+        match snapshot {
+            Snapshot::Swap(pool) => Ok(Swap {
+                token_one: pool.token_one,
+                token_two: pool.token_two,
+                reserve_one: pool.reserve_one,
+                reserve_two: pool.reserve_two,
+                fee: pool.fee,
+                fee_one: pool.fee_one,
+                fee_two: pool.fee_two,
+                total_supply: pool.total_supply
+            }),
+            _ => Err(SnapshotError::WrongKind)
+        }
+    }
+}
+
+impl TryFrom<Snapshot> for StableSwap {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot: Snapshot) -> Result<Self, Self::Error> {
+        match snapshot {
+            Snapshot::StableSwap(pool) => Ok(StableSwap {
+                token_one: pool.token_one,
+                token_two: pool.token_two,
+                reserve_one: pool.reserve_one,
+                reserve_two: pool.reserve_two,
+                fee: pool.fee,
+                fee_one: pool.fee_one,
+                fee_two: pool.fee_two,
+                total_supply: pool.total_supply
+            }),
+            _ => Err(SnapshotError::WrongKind)
+        }
+    }
+}
+
+impl TryFrom<Snapshot> for Pool {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot: Snapshot) -> Result<Self, Self::Error> {
+        match snapshot {
+            Snapshot::Swap(_) => Ok(Pool::Swap(Swap::try_from(snapshot)?)),
+            Snapshot::StableSwap(_) => Ok(Pool::StableSwap(StableSwap::try_from(snapshot)?)),
+            Snapshot::Account(_) => Err(SnapshotError::WrongKind)
+        }
+    }
+}
+
+impl TryFrom<Snapshot> for Account {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot: Snapshot) -> Result<Self, Self::Error> {
+        match snapshot {
+            Snapshot::Account(account) => {
+                let tokens = account.tokens.into_iter()
+                    .map(|t| Token::new(t.id, t.name, t.symbol, t.decimals, t.supply))
+                    .collect();
+                let mut restored = Account::new(account.alph_amount, tokens);
+                restored.address = account.address;
+                for (id, amount) in account.balances {
+                    restored.deposit(&id, amount).map_err(SnapshotError::Account)?;
+                }
+                Ok(restored)
+            }
+            _ => Err(SnapshotError::WrongKind)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::ContractId;
+
+    const TOKEN_A: TokenId = TokenId::new([0xa; 32]);
+    const TOKEN_B: TokenId = TokenId::new([0xb; 32]);
+    const STABLE_A: TokenId = TokenId::new([0x5a; 32]);
+    const STABLE_B: TokenId = TokenId::new([0x5b; 32]);
+
+    fn create_test_swap() -> Swap {
+        Swap {
+            token_one: TOKEN_A,
+            token_two: TOKEN_B,
+            reserve_one: 1000,
+            reserve_two: 2000,
+            fee: 30,
+            fee_one: 3,
+            fee_two: 6,
+            total_supply: 1414,
+        }
+    }
+
+    fn create_test_stable_swap() -> StableSwap {
+        StableSwap {
+            token_one: STABLE_A,
+            token_two: STABLE_B,
+            reserve_one: 1000,
+            reserve_two: 1000,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 1000,
+        }
+    }
+
+    fn create_test_account() -> Account {
+        let usdt = Token::from_contract_id(ContractId::new([1; 32]), "Tether USD".to_string(), "USDT".to_string(), 6, 1_000_000);
+        Account::new(5_000, vec![usdt])
+    }
+
+    fn create_test_account_with_balances() -> Account {
+        let mut account = create_test_account().with_address(Address::p2pkh(&[2; 33]));
+        account.deposit(&TokenId::from(ContractId::new([1; 32])), 250).unwrap();
+        account
+    }
+
+    #[test]
+    fn test_swap_json_round_trip() {
+        let swap = create_test_swap();
+        let json = Snapshot::from(&swap).to_json().unwrap();
+        let restored = Swap::try_from(Snapshot::from_json(&json).unwrap()).unwrap();
+        assert_eq!(restored, swap);
+    }
+
+    #[test]
+    fn test_stable_swap_binary_round_trip() {
+        let swap = create_test_stable_swap();
+        let bytes = Snapshot::from(&swap).to_bytes().unwrap();
+        let restored = StableSwap::try_from(Snapshot::from_bytes(&bytes).unwrap()).unwrap();
+        assert_eq!(restored, swap);
+    }
+
+    #[test]
+    fn test_account_round_trip_both_formats() {
+        let account = create_test_account_with_balances();
+        let snapshot = Snapshot::from(&account);
+
+        let from_json = Snapshot::from_json(&snapshot.to_json().unwrap()).unwrap();
+        let from_bytes = Snapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        assert_eq!(from_json, from_bytes);
+        assert_eq!(Account::try_from(from_json).unwrap(), account);
+    }
+
+    #[test]
+    fn test_amounts_past_u64() {
+        // a whale: well over 2^64 attoALPH and tokens
+        let mut account = create_test_account_with_balances();
+        account.deposit(&TokenId::ALPH, 1_000_000_000_000_000_000_000_000).unwrap();
+        account.deposit(&TokenId::from(ContractId::new([1; 32])), u64::MAX as u128 * 3).unwrap();
+        let snapshot = Snapshot::from(&account);
+
+        let json = snapshot.to_json().unwrap();
+        assert!(json.contains(r#""alph_amount": 1000000000000000000005000"#));
+        assert_eq!(Account::try_from(Snapshot::from_json(&json).unwrap()).unwrap(), account);
+        assert_eq!(Account::try_from(Snapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap()).unwrap(), account);
+    }
+
+    #[test]
+    fn test_binary_is_compact() {
+        let snapshot = Snapshot::from(&create_test_swap());
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], b"ALPS");
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), SNAPSHOT_VERSION);
+        assert!(bytes.len() < snapshot.to_json().unwrap().len() / 4);
+    }
+
+    #[test]
+    fn test_json_fixture_is_stable() {
+        // fixtures shared with other teams look exactly like this
+        let json = r#"{
+            "version": 5,
+            "snapshot": {
+                "swap": {
+                    "token_one": "0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a",
+                    "token_two": "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+                    "reserve_one": 1000,
+                    "reserve_two": 2000,
+                    "fee": 30,
+                    "fee_one": 3,
+                    "fee_two": 6,
+                    "total_supply": 1414
+                }
+            }
+        }"#;
+        let swap = Swap::try_from(Snapshot::from_json(json).unwrap()).unwrap();
+        assert_eq!(swap, create_test_swap());
+    }
+
+    #[test]
+    fn test_migrate_v1_account() {
+        let address = Address::contract(ContractId::new([1; 32]));
+        let json = format!(r#"{{ "version": 1, "snapshot": {{ "account": {{ "alph_amount": 5000, "tokens": [
+            {{ "address": "{}", "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1000000 }}
+        ] }} }} }}"#, address);
+        let account = Account::try_from(Snapshot::from_json(&json).unwrap()).unwrap();
+        assert_eq!(account, create_test_account());
+    }
+
+    #[test]
+    fn test_migrate_v2_account() {
+        let json = r#"{ "version": 2, "snapshot": { "account": { "alph_amount": 5000, "tokens": [
+            { "id": "0101010101010101010101010101010101010101010101010101010101010101",
+              "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1000000 }
+        ] } } }"#;
+        let account = Account::try_from(Snapshot::from_json(json).unwrap()).unwrap();
+        assert_eq!(account, create_test_account());
+        assert_eq!(account.address(), None);
+    }
+
+    #[test]
+    fn test_migrate_v3_account() {
+        let address = Address::p2pkh(&[2; 33]);
+        let json = format!(r#"{{ "version": 3, "snapshot": {{ "account": {{ "address": "{}", "alph_amount": 5000, "tokens": [
+            {{ "id": "0101010101010101010101010101010101010101010101010101010101010101",
+              "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1000000 }}
+        ] }} }} }}"#, address);
+        let account = Account::try_from(Snapshot::from_json(&json).unwrap()).unwrap();
+        assert_eq!(account, create_test_account().with_address(address));
+        assert_eq!(account.balances().count(), 0);
+    }
+
+    #[test]
+    fn test_migrate_v4() {
+        let json = r#"{ "version": 4, "snapshot": { "account": { "address": null, "alph_amount": 5000, "tokens": [
+            { "id": "0101010101010101010101010101010101010101010101010101010101010101",
+              "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1000000 }
+        ], "balances": { "0101010101010101010101010101010101010101010101010101010101010101": 250 } } } }"#;
+        let account = Account::try_from(Snapshot::from_json(json).unwrap()).unwrap();
+        let mut expected = create_test_account();
+        expected.deposit(&TokenId::from(ContractId::new([1; 32])), 250).unwrap();
+        assert_eq!(account, expected);
+
+        // a v4 reader only ever wrote u64s
+        let past_u64 = json.replace("5000", "18446744073709551616");
+        assert!(matches!(Snapshot::from_json(&past_u64), Err(SnapshotError::Json(_))));
+    }
+
+    #[test]
+    fn test_migrate_v1_pools() {
+        let pool = |one: &str, two: &str| format!(r#"{{ "version": 1, "snapshot": {{ "stable_swap": {{
+            "token_one": "{}", "token_two": "{}", "reserve_one": 1000, "reserve_two": 1000,
+            "fee": 30, "fee_one": 0, "fee_two": 0, "total_supply": 1000
+        }} }} }}"#, one, two);
+
+        let migrated = Snapshot::from_json(&pool(&STABLE_A.to_string(), &STABLE_B.to_string())).unwrap();
+        assert_eq!(StableSwap::try_from(migrated).unwrap(), create_test_stable_swap());
+
+        // symbols get the same stand-in ids as account labels
+        let migrated = StableSwap::try_from(Snapshot::from_json(&pool("StableA", "StableB")).unwrap()).unwrap();
+        assert_eq!(migrated.token_one, TokenId::new(blake2b(b"StableA")));
+        assert_eq!(migrated.token_two, TokenId::new(blake2b(b"StableB")));
+
+        // and a pool naming a contract by address agrees with the account
+        let address = Address::contract(ContractId::new([1; 32])).to_string();
+        let migrated = StableSwap::try_from(Snapshot::from_json(&pool(&address, "StableB")).unwrap()).unwrap();
+        assert_eq!(migrated.token_one, TokenId::from(ContractId::new([1; 32])));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let json = r#"{ "version": 99, "snapshot": { "account": { "alph_amount": 1, "tokens": [] } } }"#;
+        assert!(matches!(Snapshot::from_json(json), Err(SnapshotError::UnsupportedVersion(99))));
+
+        let mut bytes = Snapshot::from(&create_test_swap()).to_bytes().unwrap();
+        bytes[4..6].copy_from_slice(&99u16.to_le_bytes());
+        assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn test_missing_version_and_bad_magic() {
+        let json = r#"{ "snapshot": { "account": { "alph_amount": 1, "tokens": [] } } }"#;
+        assert!(matches!(Snapshot::from_json(json), Err(SnapshotError::MissingVersion)));
+        assert!(matches!(Snapshot::from_bytes(b"NOPE\x01\x00"), Err(SnapshotError::BadMagic)));
+        assert!(matches!(Snapshot::from_bytes(b"AL"), Err(SnapshotError::BadMagic)));
+    }
+
+    #[test]
+    fn test_migrate_v1_free_form_address() {
+        // as the first snapshots wrote them
+        let json = r#"{ "version": 1, "snapshot": { "account": { "alph_amount": 5000, "tokens": [
+            { "address": "usdt-address", "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1000000 }
+        ] } } }"#;
+        let account = Account::try_from(Snapshot::from_json(json).unwrap()).unwrap();
+        let id = TokenId::new(blake2b(b"usdt-address"));
+        assert_eq!(account.token(&id).unwrap().symbol(), "USDT");
+        assert_eq!(Account::try_from(Snapshot::from_json(json).unwrap()).unwrap(), account);
+
+        // a real address that isn't a contract is just another label
+        let json = json.replace("usdt-address", &Address::p2pkh(&[2; 33]).to_string());
+        assert!(Account::try_from(Snapshot::from_json(&json).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_token_ids() {
+        let json = r#"{ "version": 3, "snapshot": { "account": { "address": null, "alph_amount": 1, "tokens": [
+            { "id": "abcd", "name": "Tether USD", "symbol": "USDT", "decimals": 6, "supply": 1 }
+        ] } } }"#;
+        assert!(matches!(Snapshot::from_json(json), Err(SnapshotError::Json(_))));
+    }
+
+    #[test]
+    fn test_balance_without_metadata() {
+        let json = r#"{ "version": 5, "snapshot": { "account": { "address": null, "alph_amount": 1, "tokens": [],
+            "balances": { "1717171717171717171717171717171717171717171717171717171717171717": 5 } } } }"#;
+        let snapshot = Snapshot::from_json(json).unwrap();
+        assert!(matches!(Account::try_from(snapshot), Err(SnapshotError::Account(AccountError::UnknownToken(_)))));
+    }
+
+    #[test]
+    fn test_invalid_fee() {
+        let swap = Swap { fee: 20_000, ..create_test_swap() };
+        let snapshot = Snapshot::from(&swap);
+        assert!(matches!(Snapshot::from_json(&snapshot.to_json().unwrap()), Err(SnapshotError::InvalidFee(20_000))));
+        assert!(matches!(Snapshot::from_bytes(&snapshot.to_bytes().unwrap()), Err(SnapshotError::InvalidFee(20_000))));
+
+        let swap = Swap { fee: 10_000, ..create_test_swap() };
+        assert!(Snapshot::from_json(&Snapshot::from(&swap).to_json().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_wrong_kind() {
+        let snapshot = Snapshot::from(&create_test_swap());
+        assert!(matches!(StableSwap::try_from(snapshot), Err(SnapshotError::WrongKind)));
+    }
+}
@@ -6,33 +6,94 @@
 // .. perform math in notebook (newton's method for cubic iterations (newton iterations))
     // optimal number for imbalanced pools is 8 - 15 (more gas); and 3 - 6 for balanced
 
-mod swap {
+// a * b / c rounded down, through a 256 bit product: reserves and amounts
+// are u128, and a deep pool's reserve times a big trade is past that. None
+// when c is zero or the result doesn't fit
+pub(crate) fn mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / c);
+    }
+    let (high, low) = widening_mul(a, b);
+    if high >= c {
+        return None;
+    }
+
+    // shift and subtract, one bit of `low` at a time. the remainder stays
+    // below c, so after a shift it is below 2c and one subtraction will do
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+// (high, low) halves of a * b
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & mask);
+    let (b_high, b_low) = (b >> 64, b & mask);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let middle = (low_low >> 64) + (high_low & mask) + (low_high & mask);
+
+    let low = (low_low & mask) | (middle << 64);
+    let high = a_high * b_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+#[allow(clippy::module_inception)]
+pub mod swap {
+
+    use super::mul_div;
+    use crate::contract::TokenId;
 
     #[derive(Debug)]
     pub enum SwapError {
         AmountIsZero,
-        InsufficientLiquidity
+        InsufficientLiquidity,
+        // a reserve, fee tally or the supply would pass u128
+        Overflow
     }
 
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Swap {
-        pub token_one: String,
-        pub token_two: String,
-        pub reserve_one: u64,
-        pub reserve_two: u64,
+        pub token_one: TokenId,
+        pub token_two: TokenId,
+        pub reserve_one: u128,
+        pub reserve_two: u128,
         pub fee: u64,
-        pub fee_one: u64,
-        pub fee_two: u64,
-        pub total_supply: u64
+        pub fee_one: u128,
+        pub fee_two: u128,
+        pub total_supply: u128
     }
 
     impl Swap {
 
-        pub fn total_supply(&self) -> u64 {
+        pub fn total_supply(&self) -> u128 {
             self.total_supply
         }
 
+        // price of token one in units of token two: y / x
+        pub fn spot_price(&self) -> f64 {
+            if self.reserve_one == 0 {
+                return 0.0;
+            }
+            self.reserve_two as f64 / self.reserve_one as f64
+        }
+
         // swap tokens with x * y = k
-        pub fn swap(&mut self, amount_in: u64, token_in_is_one: bool) -> Result<u64, SwapError> {
+        pub fn swap(&mut self, amount_in: u128, token_in_is_one: bool) -> Result<u128, SwapError> {
             if amount_in == 0 {
                 return Err(SwapError::AmountIsZero);
             }
@@ -44,54 +105,64 @@ mod swap {
             };
 
             // Apply 0.03% fee: fee = 30 basis points = 30/10000
-            let fee_amount = amount_in * self.fee / 10000;
+            let fee_amount = mul_div(amount_in, self.fee as u128, 10000).ok_or(SwapError::Overflow)?;
             let amount_in_after_fee = amount_in - fee_amount;
             
             // Constant product formula: x * y = k
             // amount_out = (reserve_out * amount_in_after_fee) / (reserve_in + amount_in_after_fee)
-            let amount_out = (reserve_out * amount_in_after_fee) / (reserve_in + amount_in_after_fee);
+            let new_reserve_in = reserve_in.checked_add(amount_in_after_fee).ok_or(SwapError::Overflow)?;
+            let amount_out = mul_div(reserve_out, amount_in_after_fee, new_reserve_in).ok_or(SwapError::InsufficientLiquidity)?;
             
-            if amount_out >= reserve_out {
+            // an output that rounds down to nothing would just donate the input
+            if amount_out == 0 || amount_out >= reserve_out {
                 return Err(SwapError::InsufficientLiquidity);
             }
 
-            // Update reserves and accrue fees
+            // Update reserves and accrue fees, checking before changing anything
             if token_in_is_one {
-                self.reserve_one += amount_in;
+                let reserve_one = self.reserve_one.checked_add(amount_in).ok_or(SwapError::Overflow)?;
+                self.fee_one = self.fee_one.checked_add(fee_amount).ok_or(SwapError::Overflow)?;
+                self.reserve_one = reserve_one;
                 self.reserve_two -= amount_out;
-                self.fee_one += fee_amount;
             } else {
-                self.reserve_two += amount_in;
+                let reserve_two = self.reserve_two.checked_add(amount_in).ok_or(SwapError::Overflow)?;
+                self.fee_two = self.fee_two.checked_add(fee_amount).ok_or(SwapError::Overflow)?;
+                self.reserve_two = reserve_two;
                 self.reserve_one -= amount_out;
-                self.fee_two += fee_amount;
             }
             
             Ok(amount_out)
         }
 
-        pub fn add_liquidity(&mut self, amount_one: u64, amount_two: u64) -> Result<u64, SwapError>{
+        pub(crate) fn add_liquidity(&mut self, amount_one: u128, amount_two: u128) -> Result<u128, SwapError>{
             if amount_one == 0 || amount_two == 0 {
                 return Err(SwapError::AmountIsZero);
             }
             
             let liquidity_tokens = if self.reserve_one == 0 && self.reserve_two == 0 {
                 // Initial liquidity: geometric mean
-                ((amount_one as f64 * amount_two as f64).sqrt()) as u64
+                ((amount_one as f64 * amount_two as f64).sqrt()) as u128
+            } else if self.reserve_one == 0 || self.reserve_two == 0 {
+                // one side drained: there's no ratio to deposit at
+                return Err(SwapError::InsufficientLiquidity);
             } else {
                 // Calculate liquidity based on existing ratio
-                let liquidity_one = amount_one * self.total_supply() / self.reserve_one;
-                let liquidity_two = amount_two * self.total_supply() / self.reserve_two;
+                let liquidity_one = mul_div(amount_one, self.total_supply(), self.reserve_one).ok_or(SwapError::Overflow)?;
+                let liquidity_two = mul_div(amount_two, self.total_supply(), self.reserve_two).ok_or(SwapError::Overflow)?;
                 liquidity_one.min(liquidity_two)
             };
             
-            self.reserve_one += amount_one;
-            self.reserve_two += amount_two;
-            self.total_supply += liquidity_tokens;
+            let reserve_one = self.reserve_one.checked_add(amount_one).ok_or(SwapError::Overflow)?;
+            let reserve_two = self.reserve_two.checked_add(amount_two).ok_or(SwapError::Overflow)?;
+            let total_supply = self.total_supply.checked_add(liquidity_tokens).ok_or(SwapError::Overflow)?;
+            (self.reserve_one, self.reserve_two, self.total_supply) = (reserve_one, reserve_two, total_supply);
             
             Ok(liquidity_tokens)
         }
 
-        pub fn remove_liquidity(&mut self, liquidity_tokens: u64) -> Result<(u64, u64), SwapError> {
+        // burns without asking whose tokens they were; outside the crate lp
+        // tokens are redeemed through Pool::remove_liquidity_with_account
+        pub(crate) fn remove_liquidity(&mut self, liquidity_tokens: u128) -> Result<(u128, u128), SwapError> {
             if liquidity_tokens == 0 {
                 return Err(SwapError::AmountIsZero);
             }
@@ -101,8 +172,9 @@ mod swap {
                 return Err(SwapError::InsufficientLiquidity);
             }
             
-            let amount_one = self.reserve_one * liquidity_tokens / total_supply;
-            let amount_two = self.reserve_two * liquidity_tokens / total_supply;
+            // a share of a reserve always fits, so these can't fail
+            let amount_one = mul_div(self.reserve_one, liquidity_tokens, total_supply).ok_or(SwapError::Overflow)?;
+            let amount_two = mul_div(self.reserve_two, liquidity_tokens, total_supply).ok_or(SwapError::Overflow)?;
             
             self.reserve_one -= amount_one;
             self.reserve_two -= amount_two;
@@ -113,33 +185,89 @@ mod swap {
     }
 }
 
-mod stable_swap {
+pub mod stable_swap {
+
+    use super::mul_div;
+    use crate::contract::TokenId;
 
     #[derive(Debug)]
     pub enum StableSwapError {
         AmountIsZero,
-        InsufficientLiquidity
+        InsufficientLiquidity,
+        // see SwapError::Overflow
+        Overflow
+    }
+    
+    // newton iterations per swap; see the note at the top of this file
+    pub const NEWTON_ITERATIONS: usize = 10;
+
+    // k = x³y + y³x
+    pub fn invariant(x: f64, y: f64) -> f64 {
+        x.powi(3) * y + y.powi(3) * x
+    }
+
+    // Solve for y on the curve given x: x³ * y + y³ * x = k
+    // Factor out y: y * (x³ + y² * x) = k
+    // This is a cubic equation: x * y³ + x³ * y - k = 0
+    //
+    // Use Newton's method for cubic: f(y) = x * y³ + x³ * y - k
+    // f'(y) = 3 * x * y² + x³
+    pub fn solve_y(x: f64, k: f64, initial_guess: f64, iterations: usize) -> f64 {
+        let mut y = initial_guess;
+        
+        for _ in 0..iterations {
+            let f = x * y.powi(3) + x.powi(3) * y - k;
+            let f_prime = 3.0 * x * y.powi(2) + x.powi(3);
+            
+            if f_prime.abs() < 1e-12 {
+                break;
+            }
+            
+            let y_next = y - f / f_prime;
+            if (y_next - y).abs() < 1e-12 {
+                break;
+            }
+            y = y_next;
+        }
+        
+        y
+    }
+
+    // price of token one in token two at a point on the curve
+    // = (3x²y + y³) / (x³ + 3xy²)
+    pub fn price_at(x: f64, y: f64) -> f64 {
+        let denominator = x.powi(3) + 3.0 * x * y.powi(2);
+        if denominator == 0.0 {
+            return 0.0;
+        }
+        (3.0 * x.powi(2) * y + y.powi(3)) / denominator
     }
     
+    #[derive(Debug, Clone, PartialEq)]
     pub struct StableSwap {
-        pub token_one: String,
-        pub token_two: String,
-        pub reserve_one: u64,
-        pub reserve_two: u64,
+        pub token_one: TokenId,
+        pub token_two: TokenId,
+        pub reserve_one: u128,
+        pub reserve_two: u128,
         pub fee: u64,
-        pub fee_one: u64,
-        pub fee_two: u64,
-        pub total_supply: u64
+        pub fee_one: u128,
+        pub fee_two: u128,
+        pub total_supply: u128
     }
 
     impl StableSwap {
 
-        pub fn total_supply(&self) -> u64 {
+        pub fn total_supply(&self) -> u128 {
             self.total_supply
         }
 
+        // price of token one in units of token two: -dy/dx along x³y + y³x = k
+        pub fn spot_price(&self) -> f64 {
+            price_at(self.reserve_one as f64, self.reserve_two as f64)
+        }
+
         // swap with stable swap formula x³y + y³x ≥ k
-        pub fn swap(&mut self, amount_in: u64, token_in_is_one: bool) -> Result<u64, StableSwapError> {
+        pub fn swap(&mut self, amount_in: u128, token_in_is_one: bool) -> Result<u128, StableSwapError> {
             if amount_in == 0 {
                 return Err(StableSwapError::AmountIsZero);
             }
@@ -151,81 +279,69 @@ mod stable_swap {
             };
 
             // Apply 0.03% fee
-            let fee_amount = amount_in * self.fee / 10000;
+            let fee_amount = mul_div(amount_in, self.fee as u128, 10000).ok_or(StableSwapError::Overflow)?;
             let dx = (amount_in - fee_amount) as f64;
 
             // Current invariant k = x³y + y³x
-            let k = x.powi(3) * y + y.powi(3) * x;
+            let k = invariant(x, y);
             
             // New x after adding input
             let new_x = x + dx;
             
-            // Solve for new_y: new_x³ * new_y + new_y³ * new_x = k
-            // Factor out new_y: new_y * (new_x³ + new_y² * new_x) = k
-            // This is a cubic equation: new_x * new_y³ + new_x³ * new_y - k = 0
-            
-            // Use Newton's method for cubic: f(y) = new_x * y³ + new_x³ * y - k
-            // f'(y) = 3 * new_x * y² + new_x³
-            let mut new_y = y; // Initial guess
+            let new_y = solve_y(new_x, k, y, NEWTON_ITERATIONS);
             
-            for _ in 0..10 { // Newton iterations
-                let f = new_x * new_y.powi(3) + new_x.powi(3) * new_y - k;
-                let f_prime = 3.0 * new_x * new_y.powi(2) + new_x.powi(3);
-                
-                if f_prime.abs() < 1e-12 {
-                    break;
-                }
-                
-                let new_y_next = new_y - f / f_prime;
-                if (new_y_next - new_y).abs() < 1e-12 {
-                    break;
-                }
-                new_y = new_y_next;
-            }
-            
-            let amount_out = (y - new_y) as u64;
+            let amount_out = (y - new_y) as u128;
             
-            if amount_out == 0 || new_y <= 0.0 {
+            // a tiny new_y can vanish in the subtraction above, so check the
+            // output against the reserve itself too
+            if amount_out == 0 || new_y <= 0.0 || amount_out as f64 >= y {
                 return Err(StableSwapError::InsufficientLiquidity);
             }
 
-            // Update reserves and accrue fees
+            // Update reserves and accrue fees, checking before changing anything
             if token_in_is_one {
-                self.reserve_one += amount_in;
+                let reserve_one = self.reserve_one.checked_add(amount_in).ok_or(StableSwapError::Overflow)?;
+                self.fee_one = self.fee_one.checked_add(fee_amount).ok_or(StableSwapError::Overflow)?;
+                self.reserve_one = reserve_one;
                 self.reserve_two -= amount_out;
-                self.fee_one += fee_amount;
             } else {
-                self.reserve_two += amount_in;
+                let reserve_two = self.reserve_two.checked_add(amount_in).ok_or(StableSwapError::Overflow)?;
+                self.fee_two = self.fee_two.checked_add(fee_amount).ok_or(StableSwapError::Overflow)?;
+                self.reserve_two = reserve_two;
                 self.reserve_one -= amount_out;
-                self.fee_two += fee_amount;
             }
             
             Ok(amount_out)
         }
 
-        pub fn add_liquidity(&mut self, amount_one: u64, amount_two: u64) -> Result<u64, StableSwapError>{
+        pub(crate) fn add_liquidity(&mut self, amount_one: u128, amount_two: u128) -> Result<u128, StableSwapError>{
             if amount_one == 0 || amount_two == 0 {
                 return Err(StableSwapError::AmountIsZero);
             }
             
             let liquidity_tokens = if self.reserve_one == 0 && self.reserve_two == 0 {
                 // Initial liquidity: geometric mean
-                ((amount_one as f64 * amount_two as f64).sqrt()) as u64
+                ((amount_one as f64 * amount_two as f64).sqrt()) as u128
+            } else if self.reserve_one == 0 || self.reserve_two == 0 {
+                // one side drained: there's no ratio to deposit at
+                return Err(StableSwapError::InsufficientLiquidity);
             } else {
                 // Calculate liquidity based on existing ratio
-                let liquidity_one = amount_one * self.total_supply() / self.reserve_one;
-                let liquidity_two = amount_two * self.total_supply() / self.reserve_two;
+                let liquidity_one = mul_div(amount_one, self.total_supply(), self.reserve_one).ok_or(StableSwapError::Overflow)?;
+                let liquidity_two = mul_div(amount_two, self.total_supply(), self.reserve_two).ok_or(StableSwapError::Overflow)?;
                 liquidity_one.min(liquidity_two)
             };
             
-            self.reserve_one += amount_one;
-            self.reserve_two += amount_two;
-            self.total_supply += liquidity_tokens;
+            let reserve_one = self.reserve_one.checked_add(amount_one).ok_or(StableSwapError::Overflow)?;
+            let reserve_two = self.reserve_two.checked_add(amount_two).ok_or(StableSwapError::Overflow)?;
+            let total_supply = self.total_supply.checked_add(liquidity_tokens).ok_or(StableSwapError::Overflow)?;
+            (self.reserve_one, self.reserve_two, self.total_supply) = (reserve_one, reserve_two, total_supply);
             
             Ok(liquidity_tokens)
         }
 
-        pub fn remove_liquidity(&mut self, liquidity_tokens: u64) -> Result<(u64, u64), StableSwapError> {
+        // see Swap::remove_liquidity
+        pub(crate) fn remove_liquidity(&mut self, liquidity_tokens: u128) -> Result<(u128, u128), StableSwapError> {
             if liquidity_tokens == 0 {
                 return Err(StableSwapError::AmountIsZero);
             }
@@ -235,8 +351,9 @@ mod stable_swap {
                 return Err(StableSwapError::InsufficientLiquidity);
             }
             
-            let amount_one = self.reserve_one * liquidity_tokens / total_supply;
-            let amount_two = self.reserve_two * liquidity_tokens / total_supply;
+            // a share of a reserve always fits, so these can't fail
+            let amount_one = mul_div(self.reserve_one, liquidity_tokens, total_supply).ok_or(StableSwapError::Overflow)?;
+            let amount_two = mul_div(self.reserve_two, liquidity_tokens, total_supply).ok_or(StableSwapError::Overflow)?;
             
             self.reserve_one -= amount_one;
             self.reserve_two -= amount_two;
@@ -247,14 +364,262 @@ mod stable_swap {
     }
 }
 
+// either kind of pool behind one interface, for code that replays or
+// analyses trades without caring about the curve
+pub mod pool {
+    use super::stable_swap::{StableSwap, StableSwapError};
+    use super::swap::{Swap, SwapError};
+    use crate::account::{Account, AccountError, Token};
+    use crate::address::{blake2b, HASH_LENGTH};
+    use crate::contract::TokenId;
+
+    // same as the lp tokens of the on-chain DEX
+    pub const LP_DECIMALS: u64 = 18;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum PoolError {
+        AmountIsZero,
+        InsufficientLiquidity,
+        Overflow
+    }
+
+    // an account-aware call fails on either side
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TradeError {
+        Pool(PoolError),
+        Account(AccountError)
+    }
+
+    impl From<PoolError> for TradeError {
+        fn from(e: PoolError) -> Self {
+            TradeError::Pool(e)
+        }
+    }
+
+    impl From<AccountError> for TradeError {
+        fn from(e: AccountError) -> Self {
+            TradeError::Account(e)
+        }
+    }
+
+    impl From<SwapError> for PoolError {
+        fn from(e: SwapError) -> Self {
+            match e {
+                SwapError::AmountIsZero => PoolError::AmountIsZero,
+                SwapError::InsufficientLiquidity => PoolError::InsufficientLiquidity,
+                SwapError::Overflow => PoolError::Overflow
+            }
+        }
+    }
+
+    impl From<StableSwapError> for PoolError {
+        fn from(e: StableSwapError) -> Self {
+            match e {
+                StableSwapError::AmountIsZero => PoolError::AmountIsZero,
+                StableSwapError::InsufficientLiquidity => PoolError::InsufficientLiquidity,
+                StableSwapError::Overflow => PoolError::Overflow
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Pool {
+        Swap(Swap),
+        StableSwap(StableSwap)
+    }
+
+    impl Pool {
+
+        pub fn tokens(&self) -> (TokenId, TokenId) {
+            match self {
+                Pool::Swap(p) => (p.token_one, p.token_two),
+                Pool::StableSwap(p) => (p.token_one, p.token_two)
+            }
+        }
+
+        pub fn reserves(&self) -> (u128, u128) {
+            match self {
+                Pool::Swap(p) => (p.reserve_one, p.reserve_two),
+                Pool::StableSwap(p) => (p.reserve_one, p.reserve_two)
+            }
+        }
+
+        // accrued fees (fee_one, fee_two)
+        pub fn fees(&self) -> (u128, u128) {
+            match self {
+                Pool::Swap(p) => (p.fee_one, p.fee_two),
+                Pool::StableSwap(p) => (p.fee_one, p.fee_two)
+            }
+        }
+
+        // fee in basis points
+        pub fn fee(&self) -> u64 {
+            match self {
+                Pool::Swap(p) => p.fee,
+                Pool::StableSwap(p) => p.fee
+            }
+        }
+
+        pub fn total_supply(&self) -> u128 {
+            match self {
+                Pool::Swap(p) => p.total_supply(),
+                Pool::StableSwap(p) => p.total_supply()
+            }
+        }
+
+        pub fn spot_price(&self) -> f64 {
+            match self {
+                Pool::Swap(p) => p.spot_price(),
+                Pool::StableSwap(p) => p.spot_price()
+            }
+        }
+
+        pub fn swap(&mut self, amount_in: u128, token_in_is_one: bool) -> Result<u128, PoolError> {
+            match self {
+                Pool::Swap(p) => Ok(p.swap(amount_in, token_in_is_one)?),
+                Pool::StableSwap(p) => Ok(p.swap(amount_in, token_in_is_one)?)
+            }
+        }
+
+        // these two are for replaying history where nobody's balance is
+        // tracked; anyone else goes through the account-aware calls below
+
+        pub(crate) fn add_liquidity(&mut self, amount_one: u128, amount_two: u128) -> Result<u128, PoolError> {
+            match self {
+                Pool::Swap(p) => Ok(p.add_liquidity(amount_one, amount_two)?),
+                Pool::StableSwap(p) => Ok(p.add_liquidity(amount_one, amount_two)?)
+            }
+        }
+
+        pub(crate) fn remove_liquidity(&mut self, liquidity_tokens: u128) -> Result<(u128, u128), PoolError> {
+            match self {
+                Pool::Swap(p) => Ok(p.remove_liquidity(liquidity_tokens)?),
+                Pool::StableSwap(p) => Ok(p.remove_liquidity(liquidity_tokens)?)
+            }
+        }
+
+        // on chain the pair contract issues the lp token, so its id would be
+        // the pair's contract id. we have no contract, so hash what tells one
+        // pool from another instead:
+        //
+        //   kind (0 constant product, 1 stable) ‖ token_one ‖ token_two ‖ fee
+        //
+        // so pools on the same pair with a different curve or fee don't share
+        // an lp token
+        pub fn lp_token_id(&self) -> TokenId {
+            let (token_one, token_two) = self.tokens();
+            let kind = match self {
+                Pool::Swap(_) => 0u8,
+                Pool::StableSwap(_) => 1u8
+            };
+            let mut preimage = Vec::with_capacity(1 + 2 * HASH_LENGTH + 8);
+            preimage.push(kind);
+            preimage.extend_from_slice(token_one.as_bytes());
+            preimage.extend_from_slice(token_two.as_bytes());
+            preimage.extend_from_slice(&self.fee().to_be_bytes());
+            TokenId::new(blake2b(&preimage))
+        }
+
+        // the pool's lp token, named after its pair, e.g. "ALPH-USDT LP"
+        pub fn lp_token(&self, symbol_one: &str, symbol_two: &str) -> Token {
+            let name = format!("{}-{} LP", symbol_one, symbol_two);
+            Token::new(self.lp_token_id(), name.clone(), name, LP_DECIMALS, self.total_supply())
+        }
+
+        // give `account` the lp token's metadata the first time it holds some.
+        // accounts can only hold tokens they have metadata for, so both
+        // symbols are known by the time it deposits into the pool.
+        //
+        // the supply moves with every add and remove, and a copy per holder
+        // would go stale, so holders record 0 and the pool's total_supply is
+        // the only figure
+        fn register_lp_token(&self, account: &mut Account) -> Result<(), AccountError> {
+            if account.token(&self.lp_token_id()).is_some() {
+                return Ok(());
+            }
+            let symbol = |id: TokenId| if id.is_alph() {
+                Ok("ALPH")
+            } else {
+                account.token(&id).map(Token::symbol).ok_or(AccountError::UnknownToken(id))
+            };
+            let (token_one, token_two) = self.tokens();
+            let token = Token { supply: 0, ..self.lp_token(symbol(token_one)?, symbol(token_two)?) };
+            account.add_token(token);
+            Ok(())
+        }
+
+        // the account-aware calls below run against copies of the pool and the
+        // account and only write both back once every step has succeeded
+
+        // debit `amount_in` from the account, credit what comes out
+        pub fn swap_with_account(&mut self, account: &mut Account, amount_in: u128, token_in_is_one: bool) -> Result<u128, TradeError> {
+            let (token_one, token_two) = self.tokens();
+            let (token_in, token_out) = if token_in_is_one { (token_one, token_two) } else { (token_two, token_one) };
+
+            let (mut pool, mut updated) = (self.clone(), account.clone());
+            updated.withdraw(&token_in, amount_in)?;
+            let amount_out = pool.swap(amount_in, token_in_is_one)?;
+            updated.deposit(&token_out, amount_out)?;
+
+            (*self, *account) = (pool, updated);
+            Ok(amount_out)
+        }
+
+        // debit both amounts and credit the minted lp tokens, registering the
+        // lp token with the account the first time it sees one
+        pub fn add_liquidity_with_account(&mut self, account: &mut Account, amount_one: u128, amount_two: u128) -> Result<u128, TradeError> {
+            let (token_one, token_two) = self.tokens();
+
+            let (mut pool, mut updated) = (self.clone(), account.clone());
+            updated.withdraw(&token_one, amount_one)?;
+            updated.withdraw(&token_two, amount_two)?;
+            let liquidity = pool.add_liquidity(amount_one, amount_two)?;
+            pool.register_lp_token(&mut updated)?;
+            updated.deposit(&pool.lp_token_id(), liquidity)?;
+
+            (*self, *account) = (pool, updated);
+            Ok(liquidity)
+        }
+
+        // burn the account's lp tokens and credit what they redeem for. only
+        // holders can redeem, and never more than they hold
+        pub fn remove_liquidity_with_account(&mut self, account: &mut Account, liquidity_tokens: u128) -> Result<(u128, u128), TradeError> {
+            let (token_one, token_two) = self.tokens();
+
+            let (mut pool, mut updated) = (self.clone(), account.clone());
+            updated.withdraw(&pool.lp_token_id(), liquidity_tokens)?;
+            let (amount_one, amount_two) = pool.remove_liquidity(liquidity_tokens)?;
+            updated.deposit(&token_one, amount_one)?;
+            updated.deposit(&token_two, amount_two)?;
+
+            (*self, *account) = (pool, updated);
+            Ok((amount_one, amount_two))
+        }
+    }
+}
+
+pub use self::swap::{Swap, SwapError};
+pub use self::stable_swap::{StableSwap, StableSwapError};
+pub use self::pool::{Pool, PoolError, TradeError, LP_DECIMALS};
+
+#[cfg(test)]
+mod proptests;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::account::{Account, AccountError, Token};
+    use crate::contract::TokenId;
+
+    const TOKEN_A: TokenId = TokenId::new([0xa; 32]);
+    const TOKEN_B: TokenId = TokenId::new([0xb; 32]);
+    const STABLE_A: TokenId = TokenId::new([0x5a; 32]);
+    const STABLE_B: TokenId = TokenId::new([0x5b; 32]);
 
     fn create_test_swap() -> swap::Swap {
         swap::Swap {
-            token_one: "TokenA".to_string(),
-            token_two: "TokenB".to_string(),
+            token_one: TOKEN_A,
+            token_two: TOKEN_B,
             reserve_one: 1000,
             reserve_two: 2000,
             fee: 30, // 0.3%
@@ -266,8 +631,8 @@ mod tests {
 
     fn create_test_stable_swap() -> stable_swap::StableSwap {
         stable_swap::StableSwap {
-            token_one: "StableA".to_string(),
-            token_two: "StableB".to_string(),
+            token_one: STABLE_A,
+            token_two: STABLE_B,
             reserve_one: 1000,
             reserve_two: 1000,
             fee: 30, // 0.3%
@@ -280,8 +645,8 @@ mod tests {
     #[test]
     fn test_swap_creation() {
         let swap = create_test_swap();
-        assert_eq!(swap.token_one, "TokenA");
-        assert_eq!(swap.token_two, "TokenB");
+        assert_eq!(swap.token_one, TOKEN_A);
+        assert_eq!(swap.token_two, TOKEN_B);
         assert_eq!(swap.reserve_one, 1000);
         assert_eq!(swap.reserve_two, 2000);
         assert_eq!(swap.fee, 30);
@@ -333,6 +698,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_swap_insufficient_liquidity_rejected() {
+        let mut swap = create_test_swap();
+        // with a tiny output reserve the output rounds down to zero
+        swap.reserve_two = 1;
+        let result = swap.swap(1000000, true);
+        assert!(matches!(result, Err(swap::SwapError::InsufficientLiquidity)));
+
+        // and the pool is left alone
+        assert_eq!(swap.reserve_one, 1000);
+        assert_eq!(swap.reserve_two, 1);
+        assert_eq!(swap.fee_one, 0);
+    }
+
     #[test]
     fn test_swap_updates_reserves() {
         let mut swap = create_test_swap();
@@ -377,7 +756,7 @@ mod tests {
         assert_eq!(swap.reserve_one, 1000);
         assert_eq!(swap.reserve_two, 2000);
         assert_eq!(swap.total_supply, liquidity);
-        assert_eq!(liquidity, ((1000_f64 * 2000_f64).sqrt()) as u64);
+        assert_eq!(liquidity, ((1000_f64 * 2000_f64).sqrt()) as u128);
     }
 
     #[test]
@@ -439,8 +818,8 @@ mod tests {
     #[test]
     fn test_stable_swap_creation() {
         let swap = create_test_stable_swap();
-        assert_eq!(swap.token_one, "StableA");
-        assert_eq!(swap.token_two, "StableB");
+        assert_eq!(swap.token_one, STABLE_A);
+        assert_eq!(swap.token_two, STABLE_B);
         assert_eq!(swap.reserve_one, 1000);
         assert_eq!(swap.reserve_two, 1000);
     }
@@ -463,6 +842,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_unwrap)]
     fn test_stable_swap_large_amount() {
         let mut swap = create_test_stable_swap();
         let result = swap.swap(800, true); // Large enough to trigger error
@@ -476,6 +856,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_stable_swap_large_amount_rate() {
+        let mut swap = create_test_stable_swap();
+        // The flat stable curve still quotes a large trade, just at a worse rate
+        let amount_out = swap.swap(800, true).unwrap();
+        assert_eq!(amount_out, 667);
+        assert_eq!(swap.reserve_one, 1800);
+        assert_eq!(swap.reserve_two, 333);
+
+        // draining the rest is another matter
+        let amount_out = swap.swap(1_000_000, true).unwrap();
+        assert!(amount_out < 333);
+    }
+
+    #[test]
+    fn test_stable_swap_cannot_drain_reserve() {
+        // shrunk by the property tests: drain a pool to a few lp tokens, then
+        // swap so much in that y - new_y rounds back to y
+        let mut swap = create_test_stable_swap();
+        swap.reserve_one = 6441076;
+        swap.reserve_two = 68494440;
+        swap.fee = 0;
+        swap.total_supply = 21004235;
+
+        swap.swap(2517582, true).unwrap();
+        for percent in [92, 98, 99, 97] {
+            swap.remove_liquidity(swap.total_supply * percent / 100).unwrap();
+        }
+        let reserve_one = swap.reserve_one;
+
+        let result = swap.swap(7452427, false);
+        assert!(matches!(result, Err(stable_swap::StableSwapError::InsufficientLiquidity)));
+        assert_eq!(swap.reserve_one, reserve_one);
+    }
+
     #[test]
     fn test_stable_swap_updates_reserves() {
         let mut swap = create_test_stable_swap();
@@ -523,12 +938,13 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables)]
     fn test_swap_constant_product_invariant() {
         let mut swap = create_test_swap();
         let initial_k = swap.reserve_one * swap.reserve_two;
         
         let amount_in = 100;
-        let fee_amount = amount_in * swap.fee / 10000;
+        let fee_amount = amount_in * swap.fee as u128 / 10000;
         let amount_in_after_fee = amount_in - fee_amount;
         
         let result = swap.swap(amount_in, true);
@@ -539,6 +955,228 @@ mod tests {
         assert!(new_k > initial_k); // Should increase due to fees
     }
 
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(6, 7, 4), Some(10));
+        // products past u128 go the long way round
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 90), Some(1 << 110));
+        assert_eq!(mul_div(u128::MAX, 3, 4), Some(255_211_775_190_703_847_597_530_955_573_826_158_591));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+
+        assert_eq!(mul_div(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div(1, 1, 0), None);
+    }
+
+    #[test]
+    fn test_deep_pool() {
+        // reserve times input is past u128 here
+        let mut swap = swap::Swap {
+            reserve_one: 1_000_000_000_000_000_000_000_000_000_000,
+            reserve_two: 1_000_000_000_000_000_000_000_000_000_000,
+            total_supply: 1_000_000_000_000_000_000_000_000_000_000,
+            ..create_test_swap()
+        };
+        let out = swap.swap(100_000_000_000_000_000_000, true).unwrap();
+        assert_eq!(out, 99_699_999_990_059_910_000);
+        assert_eq!(swap.fee_one, 300_000_000_000_000_000);
+
+        // doubling the pool doubles the supply
+        let supply = swap.total_supply;
+        let liquidity = swap.add_liquidity(swap.reserve_one, swap.reserve_two).unwrap();
+        assert_eq!(liquidity, supply);
+        assert_eq!(swap.remove_liquidity(liquidity).unwrap(), (swap.reserve_one, swap.reserve_two));
+    }
+
+    #[test]
+    fn test_overflow_changes_nothing() {
+        let mut swap = swap::Swap { reserve_one: u128::MAX - 10, ..create_test_swap() };
+        let before = swap.clone();
+        assert!(matches!(swap.swap(100, true), Err(swap::SwapError::Overflow)));
+        assert!(matches!(swap.add_liquidity(100, 100), Err(swap::SwapError::Overflow)));
+        assert_eq!(swap, before);
+
+        let mut stable = stable_swap::StableSwap { fee_two: u128::MAX, ..create_test_stable_swap() };
+        let before = stable.clone();
+        assert!(matches!(stable.swap(10_000, false), Err(stable_swap::StableSwapError::Overflow)));
+        assert_eq!(stable, before);
+
+        let mut pool = Pool::Swap(swap);
+        assert_eq!(pool.swap(100, true), Err(PoolError::Overflow));
+    }
+
+    #[test]
+    fn test_spot_prices() {
+        let swap = create_test_swap();
+        assert_eq!(swap.spot_price(), 2.0);
+
+        // a balanced stable pool trades at par
+        let stable = create_test_stable_swap();
+        assert!((stable.spot_price() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pool_dispatch() {
+        let mut pool = Pool::StableSwap(create_test_stable_swap());
+        assert_eq!(pool.tokens(), (STABLE_A, STABLE_B));
+
+        let amount_out = pool.swap(100, true).unwrap();
+        assert_eq!(pool.reserves(), (1100, 1000 - amount_out));
+        assert!(matches!(pool.swap(0, true), Err(PoolError::AmountIsZero)));
+    }
+
+    fn create_test_pool() -> Pool {
+        let mut pool = Pool::Swap(swap::Swap { reserve_one: 0, reserve_two: 0, ..create_test_swap() });
+        pool.add_liquidity(1_000_000, 2_000_000).unwrap();
+        pool
+    }
+
+    fn create_test_account() -> Account {
+        let token = |id: TokenId, symbol: &str| Token::new(id, symbol.to_string(), symbol.to_string(), 18, 1_000_000_000);
+        let mut account = Account::new(0, vec![token(TOKEN_A, "A"), token(TOKEN_B, "B")]);
+        account.deposit(&TOKEN_A, 10_000).unwrap();
+        account.deposit(&TOKEN_B, 10_000).unwrap();
+        account
+    }
+
+    #[test]
+    fn test_swap_with_account() {
+        let mut pool = create_test_pool();
+        let mut account = create_test_account();
+
+        let amount_out = pool.swap_with_account(&mut account, 1_000, true).unwrap();
+        assert_eq!(account.balance(&TOKEN_A), 9_000);
+        assert_eq!(account.balance(&TOKEN_B), 10_000 + amount_out);
+        assert_eq!(pool.reserves(), (1_001_000, 2_000_000 - amount_out));
+    }
+
+    #[test]
+    fn test_liquidity_with_account() {
+        let mut pool = create_test_pool();
+        let mut account = create_test_account();
+        let supply = pool.total_supply();
+
+        let liquidity = pool.add_liquidity_with_account(&mut account, 5_000, 10_000).unwrap();
+        assert_eq!(account.balance(&pool.lp_token_id()), liquidity);
+        assert_eq!(pool.total_supply(), supply + liquidity);
+        assert_eq!((account.balance(&TOKEN_A), account.balance(&TOKEN_B)), (5_000, 0));
+
+        let (amount_one, amount_two) = pool.remove_liquidity_with_account(&mut account, liquidity).unwrap();
+        assert_eq!(account.balance(&pool.lp_token_id()), 0);
+        assert_eq!(account.balance(&TOKEN_A), 5_000 + amount_one);
+        assert_eq!(account.balance(&TOKEN_B), amount_two);
+    }
+
+    #[test]
+    fn test_lp_token_metadata() {
+        let mut pool = create_test_pool();
+        let mut account = create_test_account();
+        pool.add_liquidity_with_account(&mut account, 1_000, 2_000).unwrap();
+
+        let lp = account.token(&pool.lp_token_id()).unwrap();
+        assert_eq!(lp.symbol(), "A-B LP");
+        assert_eq!(lp.decimals(), LP_DECIMALS);
+        assert_eq!(pool.lp_token("ALPH", "USDT").symbol(), "ALPH-USDT LP");
+        assert_eq!(pool.lp_token("ALPH", "USDT").supply(), pool.total_supply());
+
+        // the id depends on the pool, not on who holds it or how much is out
+        assert_eq!(create_test_pool().lp_token_id(), pool.lp_token_id());
+    }
+
+    #[test]
+    fn test_lp_supply_is_not_copied() {
+        let mut pool = create_test_pool();
+        let (mut alice, mut bob) = (create_test_account(), create_test_account());
+        pool.add_liquidity_with_account(&mut alice, 1_000, 2_000).unwrap();
+        pool.add_liquidity_with_account(&mut bob, 1_000, 2_000).unwrap();
+
+        // bob's deposit doesn't leave alice holding an old supply
+        for holder in [&alice, &bob] {
+            assert_eq!(holder.token(&pool.lp_token_id()).unwrap().supply(), 0);
+        }
+    }
+
+    #[test]
+    fn test_pools_on_the_same_pair() {
+        let constant_product = Pool::Swap(create_test_swap());
+        let cheaper = Pool::Swap(swap::Swap { fee: 5, ..create_test_swap() });
+        let stable = Pool::StableSwap(stable_swap::StableSwap { token_one: TOKEN_A, token_two: TOKEN_B, ..create_test_stable_swap() });
+        assert_eq!(constant_product.tokens(), stable.tokens());
+        assert_eq!(constant_product.fee(), stable.fee());
+
+        // same pair and fee, different curve; same curve, different fee
+        let ids = [constant_product.lp_token_id(), cheaper.lp_token_id(), stable.lp_token_id()];
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        assert_ne!(ids[1], ids[2]);
+
+        // and lp tokens of one can't redeem the other
+        let (mut pool, mut other) = (create_test_pool(), Pool::Swap(swap::Swap { fee: 5, ..create_test_swap() }));
+        let mut account = create_test_account();
+        let liquidity = pool.add_liquidity_with_account(&mut account, 1_000, 2_000).unwrap();
+        assert!(matches!(
+            other.remove_liquidity_with_account(&mut account, liquidity),
+            Err(TradeError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_lp_tokens_are_transferable() {
+        let mut pool = create_test_pool();
+        let mut alice = create_test_account();
+        let mut bob = Account::new(0, vec![]);
+        let liquidity = pool.add_liquidity_with_account(&mut alice, 1_000, 2_000).unwrap();
+
+        // only holders can redeem
+        assert!(matches!(
+            pool.remove_liquidity_with_account(&mut bob, 1),
+            Err(TradeError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+
+        alice.transfer(&mut bob, &pool.lp_token_id(), liquidity).unwrap();
+        // the payout is in A and B, so bob needs to know them too
+        bob.add_token(alice.token(&TOKEN_A).unwrap().clone());
+        bob.add_token(alice.token(&TOKEN_B).unwrap().clone());
+
+        // alice has nothing left to burn
+        assert!(matches!(
+            pool.remove_liquidity_with_account(&mut alice, 1),
+            Err(TradeError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+
+        let supply = pool.total_supply();
+        let (amount_one, amount_two) = pool.remove_liquidity_with_account(&mut bob, liquidity).unwrap();
+        assert_eq!((bob.balance(&TOKEN_A), bob.balance(&TOKEN_B)), (amount_one, amount_two));
+        assert_eq!(pool.total_supply(), supply - liquidity);
+    }
+
+    #[test]
+    fn test_failed_account_calls_change_nothing() {
+        let mut pool = create_test_pool();
+        let mut account = create_test_account();
+        let (pool_before, account_before) = (pool.clone(), account.clone());
+
+        // not enough of the input
+        assert!(matches!(
+            pool.swap_with_account(&mut account, 20_000, true),
+            Err(TradeError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+        // the account can pay but the pool can't
+        assert!(matches!(pool.swap_with_account(&mut account, 0, true), Err(TradeError::Pool(PoolError::AmountIsZero))));
+        // the output can't be credited after the pool has already moved
+        account.withdraw(&TOKEN_B, 10_000).unwrap();
+        account.deposit(&TOKEN_B, u128::MAX).unwrap();
+        let full = account.clone();
+        assert!(matches!(pool.swap_with_account(&mut account, 1_000, true), Err(TradeError::Account(AccountError::Overflow(_)))));
+        assert_eq!(account, full);
+        assert!(matches!(
+            pool.remove_liquidity_with_account(&mut account, 1),
+            Err(TradeError::Account(AccountError::InsufficientBalance { .. }))
+        ));
+
+        assert_eq!(pool, pool_before);
+        assert_ne!(account, account_before);
+    }
+
     #[test]
     fn test_fee_accumulation() {
         let mut swap = create_test_swap();
@@ -0,0 +1,164 @@
+// randomized invariants for both pool types
+//
+// every case starts from a random pool and runs a random sequence of swaps
+// and liquidity operations against it. amounts are kept small enough that the
+// stable curve, which solves in f64, stays exact to well under one unit.
+
+use proptest::prelude::*;
+
+use super::stable_swap::{self, StableSwap};
+use super::swap::Swap;
+use super::Pool;
+use crate::contract::TokenId;
+
+const TOKEN_A: TokenId = TokenId::new([0xa; 32]);
+const TOKEN_B: TokenId = TokenId::new([0xb; 32]);
+const STABLE_A: TokenId = TokenId::new([0x5a; 32]);
+const STABLE_B: TokenId = TokenId::new([0x5b; 32]);
+
+const MAX_RESERVE: u128 = 100_000_000;
+const MAX_AMOUNT: u128 = 10_000_000;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Swap { amount_in: u128, token_in_is_one: bool },
+    AddLiquidity { amount_one: u128, amount_two: u128 },
+    // share of the total supply to burn, in percent
+    RemoveLiquidity { percent: u128 }
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (1..MAX_AMOUNT, any::<bool>()).prop_map(|(amount_in, token_in_is_one)| Op::Swap { amount_in, token_in_is_one }),
+        1 => (1..MAX_AMOUNT, 1..MAX_AMOUNT).prop_map(|(amount_one, amount_two)| Op::AddLiquidity { amount_one, amount_two }),
+        1 => (1..100u128).prop_map(|percent| Op::RemoveLiquidity { percent }),
+    ]
+}
+
+// pools seeded through add_liquidity so total_supply means something
+fn pool(stable: bool) -> impl Strategy<Value = Pool> {
+    (1_000..MAX_RESERVE, 1_000..MAX_RESERVE, 0..100u64).prop_map(move |(amount_one, amount_two, fee)| {
+        let mut pool = if stable {
+            Pool::StableSwap(StableSwap {
+                token_one: STABLE_A,
+                token_two: STABLE_B,
+                reserve_one: 0,
+                reserve_two: 0,
+                fee,
+                fee_one: 0,
+                fee_two: 0,
+                total_supply: 0,
+            })
+        } else {
+            Pool::Swap(Swap {
+                token_one: TOKEN_A,
+                token_two: TOKEN_B,
+                reserve_one: 0,
+                reserve_two: 0,
+                fee,
+                fee_one: 0,
+                fee_two: 0,
+                total_supply: 0,
+            })
+        };
+        pool.add_liquidity(amount_one, amount_two).unwrap();
+        pool
+    })
+}
+
+fn any_pool() -> impl Strategy<Value = Pool> {
+    prop_oneof![pool(false), pool(true)]
+}
+
+// the curve's invariant, scaled to be linear in the reserves so it can be
+// compared per lp token: √(xy) and (x³y + y³x)^¼
+fn depth(pool: &Pool) -> f64 {
+    let (x, y) = pool.reserves();
+    let (x, y) = (x as f64, y as f64);
+    match pool {
+        Pool::Swap(_) => (x * y).sqrt(),
+        Pool::StableSwap(_) => stable_swap::invariant(x, y).powf(0.25)
+    }
+}
+
+// float slack for measuring depth; the stable curve also solves in f64
+fn tolerance(pool: &Pool) -> f64 {
+    match pool {
+        Pool::Swap(_) => 1e-12,
+        Pool::StableSwap(_) => 1e-9
+    }
+}
+
+fn apply(pool: &mut Pool, op: &Op) {
+    let before = pool.clone();
+    let result = match *op {
+        Op::Swap { amount_in, token_in_is_one } => pool.swap(amount_in, token_in_is_one).map(|_| ()),
+        Op::AddLiquidity { amount_one, amount_two } => pool.add_liquidity(amount_one, amount_two).map(|_| ()),
+        Op::RemoveLiquidity { percent } => {
+            let amount = pool.total_supply() * percent / 100;
+            pool.remove_liquidity(amount).map(|_| ())
+        }
+    };
+
+    // failures leave the pool exactly as it was
+    if result.is_err() {
+        assert_eq!(*pool, before);
+    }
+}
+
+proptest! {
+
+    #[test]
+    fn prop_swaps_never_decrease_k(mut pool in any_pool(), ops in prop::collection::vec(op(), 1..40)) {
+        for op in ops.iter().filter(|op| matches!(op, Op::Swap { .. })) {
+            let before = depth(&pool);
+            apply(&mut pool, op);
+            prop_assert!(depth(&pool) >= before * (1.0 - tolerance(&pool)), "{:?} shrank k: {:?}", op, pool);
+        }
+    }
+
+    #[test]
+    fn prop_reserves_never_underflow(mut pool in any_pool(), ops in prop::collection::vec(op(), 1..40)) {
+        // an underflow panics in debug builds, so surviving is most of the test
+        for op in &ops {
+            apply(&mut pool, op);
+            let (reserve_one, reserve_two) = pool.reserves();
+            if pool.total_supply() > 0 {
+                prop_assert!(reserve_one > 0 && reserve_two > 0, "{:?} drained {:?}", op, pool);
+            }
+        }
+    }
+
+    #[test]
+    fn prop_lp_share_value_never_decreases(mut pool in any_pool(), ops in prop::collection::vec(op(), 1..40)) {
+        for op in &ops {
+            let supply = pool.total_supply();
+            let before = depth(&pool) / supply as f64;
+            apply(&mut pool, op);
+
+            // burning everything leaves nothing to measure
+            if pool.total_supply() == 0 {
+                break;
+            }
+            let after = depth(&pool) / pool.total_supply() as f64;
+            prop_assert!(after >= before * (1.0 - tolerance(&pool)), "{:?} diluted lps: {} -> {}", op, before, after);
+        }
+    }
+
+    #[test]
+    fn prop_round_trips_never_profit(
+        mut pool in any_pool(),
+        ops in prop::collection::vec(op(), 0..20),
+        amount_in in 1..MAX_AMOUNT,
+        token_in_is_one in any::<bool>()
+    ) {
+        for op in &ops {
+            apply(&mut pool, op);
+        }
+
+        if let Ok(amount_out) = pool.swap(amount_in, token_in_is_one)
+            && let Ok(amount_back) = pool.swap(amount_out, !token_in_is_one) {
+            prop_assert!(amount_back <= amount_in, "{} in, {} back", amount_in, amount_back);
+        }
+    }
+}
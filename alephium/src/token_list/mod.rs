@@ -0,0 +1,311 @@
+// token lists
+
+// token metadata lives in one json list per network, in the same shape as
+// the alephium token-list repo:
+//
+//   {
+//     "networkId": 0,
+//     "tokens": [
+//       { "id": "<hex>", "name": "Tether USD", "symbol": "USDT", "decimals": 6,
+//         "logoURI": "https://...", "address": "<base58 P2C, optional>" }
+//     ]
+//   }
+//
+// a list is checked as a whole before anything uses it, so one bad entry
+// rejects the file instead of quietly dropping a token.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use serde::Deserialize;
+
+use crate::account::Token;
+use crate::address::Address;
+use crate::amount::MAX_DECIMALS;
+use crate::contract::TokenId;
+use crate::swap::Pool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Devnet
+}
+
+impl Network {
+
+    pub fn id(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0,
+            Network::Testnet => 1,
+            Network::Devnet => 4
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Network::Mainnet),
+            1 => Some(Network::Testnet),
+            4 => Some(Network::Devnet),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenListError {
+    Json(serde_json::Error),
+    UnknownNetwork(u8),
+    WrongNetwork { expected: Network, found: Network },
+    // position in the list, and what is wrong with it
+    InvalidToken(usize, String),
+    DuplicateId(TokenId),
+    DuplicateSymbol(String)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListFile {
+    network_id: u8,
+    tokens: Vec<TokenEntry>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenEntry {
+    id: String,
+    name: String,
+    symbol: String,
+    decimals: u64,
+    #[serde(rename = "logoURI")]
+    logo_uri: Option<String>,
+    address: Option<String>
+}
+
+impl TokenEntry {
+
+    // lists don't carry supply, so registry tokens report 0
+    fn into_token(self, index: usize) -> Result<(Token, Option<String>), TokenListError> {
+        let invalid = |reason: String| TokenListError::InvalidToken(index, reason);
+
+        let id = self.id.parse::<TokenId>().map_err(|e| invalid(format!("id `{}`: {:?}", self.id, e)))?;
+        if self.name.trim().is_empty() || self.symbol.trim().is_empty() {
+            return Err(invalid("name and symbol can't be empty".to_string()));
+        }
+        if self.symbol.chars().any(char::is_whitespace) {
+            return Err(invalid(format!("symbol `{}` has whitespace", self.symbol)));
+        }
+        if self.decimals > MAX_DECIMALS {
+            return Err(invalid(format!("{} decimals, at most {}", self.decimals, MAX_DECIMALS)));
+        }
+
+        let token = Token::new(id, self.name, self.symbol, self.decimals, 0);
+        if let Some(address) = &self.address {
+            let parsed = address.parse::<Address>().map_err(|e| invalid(format!("address `{}`: {:?}", address, e)))?;
+            // ALPH isn't issued by any contract, so it has no address to check
+            if id.is_alph() || parsed != *token.address() {
+                return Err(invalid(format!("address `{}` is not the contract of {}", address, id)));
+            }
+        }
+        Ok((token, self.logo_uri))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenRegistry {
+    network: Network,
+    tokens: Vec<Token>,
+    logos: BTreeMap<TokenId, String>
+}
+
+impl TokenRegistry {
+
+    pub fn read_json<R: Read>(reader: R, network: Network) -> Result<Self, TokenListError> {
+        let file: TokenListFile = serde_json::from_reader(reader).map_err(TokenListError::Json)?;
+        TokenRegistry::from_file(file, network)
+    }
+
+    pub fn from_json(json: &str, network: Network) -> Result<Self, TokenListError> {
+        TokenRegistry::read_json(json.as_bytes(), network)
+    }
+
+    fn from_file(file: TokenListFile, network: Network) -> Result<Self, TokenListError> {
+        let found = Network::from_id(file.network_id).ok_or(TokenListError::UnknownNetwork(file.network_id))?;
+        if found != network {
+            return Err(TokenListError::WrongNetwork { expected: network, found });
+        }
+
+        let mut registry = TokenRegistry { network, tokens: Vec::with_capacity(file.tokens.len()), logos: BTreeMap::new() };
+        for (index, entry) in file.tokens.into_iter().enumerate() {
+            let (token, logo) = entry.into_token(index)?;
+            if registry.get(&token.id()).is_some() {
+                return Err(TokenListError::DuplicateId(token.id()));
+            }
+            // symbols are looked up case-insensitively, so they must be unique that way too
+            if registry.by_symbol(token.symbol()).is_some() {
+                return Err(TokenListError::DuplicateSymbol(token.symbol().to_string()));
+            }
+            if let Some(logo) = logo {
+                registry.logos.insert(token.id(), logo);
+            }
+            registry.tokens.push(token);
+        }
+        Ok(registry)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn get(&self, id: &TokenId) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.id() == *id)
+    }
+
+    pub fn by_symbol(&self, symbol: &str) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.symbol().eq_ignore_ascii_case(symbol))
+    }
+
+    // a hex id or a symbol
+    pub fn resolve(&self, symbol_or_id: &str) -> Option<&Token> {
+        match symbol_or_id.parse::<TokenId>() {
+            Ok(id) => self.get(&id),
+            Err(_) => self.by_symbol(symbol_or_id)
+        }
+    }
+
+    pub fn logo(&self, id: &TokenId) -> Option<&str> {
+        self.logos.get(id).map(String::as_str)
+    }
+
+    // metadata for both sides of a pool, if the list knows them
+    pub fn pool_tokens(&self, pool: &Pool) -> Option<(&Token, &Token)> {
+        let (token_one, token_two) = pool.tokens();
+        Some((self.get(&token_one)?, self.get(&token_two)?))
+    }
+
+    // the pool's lp token, named from the list's symbols
+    pub fn lp_token(&self, pool: &Pool) -> Option<Token> {
+        let (token_one, token_two) = self.pool_tokens(pool)?;
+        Some(pool.lp_token(token_one.symbol(), token_two.symbol()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::swap::Swap;
+
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const LIST: &str = r#"{
+        "networkId": 1,
+        "tokens": [
+            { "id": "0000000000000000000000000000000000000000000000000000000000000000",
+              "name": "Alephium", "symbol": "ALPH", "decimals": 18, "logoURI": "https://example.com/alph.png" },
+            { "id": "1717171717171717171717171717171717171717171717171717171717171717",
+              "name": "Tether USD", "symbol": "USDT", "decimals": 6 }
+        ]
+    }"#;
+
+    fn create_test_list(tokens: &str) -> String {
+        format!(r#"{{ "networkId": 0, "tokens": [{}] }}"#, tokens)
+    }
+
+    fn entry(id: &str, symbol: &str, decimals: u64) -> String {
+        format!(r#"{{ "id": "{}", "name": "{}", "symbol": "{}", "decimals": {} }}"#, id, symbol, symbol, decimals)
+    }
+
+    fn invalid_reason(json: &str) -> String {
+        match TokenRegistry::from_json(json, Network::Mainnet) {
+            Err(TokenListError::InvalidToken(_, reason)) => reason,
+            other => panic!("expected an invalid token, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_load_and_resolve() {
+        let registry = TokenRegistry::from_json(LIST, Network::Testnet).unwrap();
+        assert_eq!(registry.network(), Network::Testnet);
+        assert_eq!(registry.tokens().len(), 2);
+
+        let usdt = registry.resolve("usdt").unwrap();
+        assert_eq!(usdt.id(), USDT);
+        assert_eq!(usdt.decimals(), 6);
+        assert_eq!(registry.resolve(&USDT.to_string()), Some(usdt));
+        assert_eq!(registry.resolve("DAI"), None);
+
+        assert_eq!(registry.logo(&TokenId::ALPH), Some("https://example.com/alph.png"));
+        assert_eq!(registry.logo(&USDT), None);
+    }
+
+    #[test]
+    fn test_pools_and_accounts() {
+        let registry = TokenRegistry::from_json(LIST, Network::Testnet).unwrap();
+        let pool = Pool::Swap(Swap {
+            token_one: TokenId::ALPH,
+            token_two: USDT,
+            reserve_one: 0,
+            reserve_two: 0,
+            fee: 30,
+            fee_one: 0,
+            fee_two: 0,
+            total_supply: 0,
+        });
+
+        let (one, two) = registry.pool_tokens(&pool).unwrap();
+        assert_eq!((one.symbol(), two.symbol()), ("ALPH", "USDT"));
+        assert_eq!(registry.lp_token(&pool).unwrap().symbol(), "ALPH-USDT LP");
+
+        let mut account = Account::new(0, registry.tokens().to_vec());
+        assert!(account.deposit(&USDT, 100).is_ok());
+    }
+
+    #[test]
+    fn test_networks() {
+        assert!(matches!(
+            TokenRegistry::from_json(LIST, Network::Mainnet),
+            Err(TokenListError::WrongNetwork { expected: Network::Mainnet, found: Network::Testnet })
+        ));
+        let devnet = r#"{ "networkId": 4, "tokens": [] }"#;
+        assert!(TokenRegistry::from_json(devnet, Network::Devnet).is_ok());
+        let unknown = r#"{ "networkId": 9, "tokens": [] }"#;
+        assert!(matches!(TokenRegistry::from_json(unknown, Network::Devnet), Err(TokenListError::UnknownNetwork(9))));
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let usdt = USDT.to_string();
+        assert!(invalid_reason(&create_test_list(&entry("abcd", "USDT", 6))).contains("id"));
+        assert!(invalid_reason(&create_test_list(&entry(&usdt, "USDT", 39))).contains("decimals"));
+        assert!(invalid_reason(&create_test_list(&entry(&usdt, "", 6))).contains("empty"));
+        assert!(invalid_reason(&create_test_list(&entry(&usdt, "US DT", 6))).contains("whitespace"));
+    }
+
+    #[test]
+    fn test_addresses() {
+        let usdt = Token::new(USDT, "Tether USD".to_string(), "USDT".to_string(), 6, 0);
+        let with_address = |address: &str| create_test_list(&format!(
+            r#"{{ "id": "{}", "name": "Tether USD", "symbol": "USDT", "decimals": 6, "address": "{}" }}"#, USDT, address
+        ));
+
+        assert!(TokenRegistry::from_json(&with_address(&usdt.address().to_string()), Network::Mainnet).is_ok());
+        assert!(invalid_reason(&with_address(&Address::p2pkh(&[2; 33]).to_string())).contains("not the contract"));
+        assert!(invalid_reason(&with_address("0OIl")).contains("InvalidBase58"));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let usdt = USDT.to_string();
+        let other = TokenId::new([0x18; 32]).to_string();
+
+        let json = create_test_list(&[entry(&usdt, "USDT", 6), entry(&usdt, "USDT2", 6)].join(","));
+        assert!(matches!(TokenRegistry::from_json(&json, Network::Mainnet), Err(TokenListError::DuplicateId(id)) if id == USDT));
+
+        let json = create_test_list(&[entry(&usdt, "USDT", 6), entry(&other, "usdt", 6)].join(","));
+        assert!(matches!(TokenRegistry::from_json(&json, Network::Mainnet), Err(TokenListError::DuplicateSymbol(s)) if s == "usdt"));
+    }
+}
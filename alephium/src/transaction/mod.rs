@@ -0,0 +1,566 @@
+// unsigned transactions
+
+// a transfer spends some of the sender's outputs and creates new ones:
+//
+//   inputs         output refs, each with the script that unlocks it
+//   fixed outputs  one per destination, then the change back to the sender
+//   gas            gas amount * gas price is the fee, paid in ALPH
+//
+// inputs have to add up to exactly the outputs plus the fee, for ALPH and
+// every token, so there is nowhere for a leftover to go except a change
+// output. every output needs at least DUST_AMOUNT of ALPH and can carry at
+// most MAX_TOKENS_PER_OUTPUT tokens, which means token change may be split
+// over several outputs, each pulling in its own dust.
+
+use std::collections::BTreeSet;
+
+use crate::address::{blake2b, Address, Hash};
+use crate::codec;
+use crate::contract::TokenId;
+use crate::fee::{FeeEstimator, Operation};
+use crate::keys::PublicKey;
+use crate::token_list::Network;
+use crate::utxo::{AssetOutput, OutputRef, SelectionStrategy, Target, UtxoError, UtxoSet, DUST_AMOUNT};
+
+pub const TRANSACTION_VERSION: u8 = 0;
+
+pub const MINIMAL_GAS: u64 = 20_000;
+pub const MAXIMAL_GAS_PER_TX: u64 = 5_000_000;
+// 100 nanoALPH, in attoALPH
+pub const MIN_GAS_PRICE: u128 = 100_000_000_000;
+
+pub const MAX_TOKENS_PER_OUTPUT: usize = 4;
+pub const MAX_INPUTS: usize = 256;
+pub const MAX_OUTPUTS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    NoDestinations,
+    // destination `index` is invalid
+    Dust { index: usize, alph_amount: u128 },
+    TooManyTokens { index: usize, count: usize },
+    DuplicateToken { index: usize, token: TokenId },
+    ZeroTokenAmount { index: usize, token: TokenId },
+    // ALPH goes in alph_amount, not the token list
+    NativeToken { index: usize },
+    // contracts can't own asset outputs
+    ContractDestination { index: usize },
+    GasOutOfRange(u64),
+    GasPriceTooLow(u128),
+    TooManyInputs(usize),
+    TooManyOutputs(usize),
+    Overflow,
+    Utxo(UtxoError)
+}
+
+impl From<UtxoError> for TransactionError {
+    fn from(e: UtxoError) -> Self {
+        TransactionError::Utxo(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnlockScript {
+    // the owner's public key, the signature goes in the signed transaction
+    P2PKH(PublicKey),
+    // m of the multisig's keys, each with its position in the lockup script
+    P2MPKH(Vec<(PublicKey, usize)>),
+    // whatever unlocked the input before this one
+    SameAsPrevious
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxInput {
+    pub output_ref: OutputRef,
+    pub unlock_script: UnlockScript
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsignedTransaction {
+    pub version: u8,
+    pub network_id: u8,
+    pub gas_amount: u64,
+    pub gas_price: u128,
+    pub inputs: Vec<TxInput>,
+    pub fixed_outputs: Vec<AssetOutput>
+}
+
+impl UnsignedTransaction {
+
+    // blake2b of the wire format, and what gets signed
+    pub fn id(&self) -> Hash {
+        blake2b(&codec::serialize(self))
+    }
+
+    // a built transaction always fits; a decoded one may not
+    pub fn fee(&self) -> Result<u128, TransactionError> {
+        (self.gas_amount as u128).checked_mul(self.gas_price).ok_or(TransactionError::Overflow)
+    }
+}
+
+// pays `destinations` out of the outputs `public_key` owns
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder<'a> {
+    utxos: &'a UtxoSet,
+    public_key: PublicKey,
+    network: Network,
+    destinations: Vec<AssetOutput>,
+    gas_amount: u64,
+    gas_price: u128,
+    estimator: Option<FeeEstimator>,
+    strategy: SelectionStrategy,
+    now: u64
+}
+
+impl<'a> TransactionBuilder<'a> {
+
+    pub fn new(utxos: &'a UtxoSet, public_key: PublicKey, network: Network) -> Self {
+        TransactionBuilder {
+            utxos,
+            public_key,
+            network,
+            destinations: vec![],
+            gas_amount: MINIMAL_GAS,
+            gas_price: MIN_GAS_PRICE,
+            estimator: None,
+            strategy: SelectionStrategy::LargestFirst,
+            now: 0
+        }
+    }
+
+    // lock times and tokens ride along on the output
+    pub fn pay(mut self, destination: AssetOutput) -> Self {
+        self.destinations.push(destination);
+        self
+    }
+
+    pub fn with_gas(mut self, gas_amount: u64, gas_price: u128) -> Self {
+        self.gas_amount = gas_amount;
+        self.gas_price = gas_price;
+        self.estimator = None;
+        self
+    }
+
+    // size the gas to the inputs and outputs the transaction ends up with
+    pub fn with_estimated_gas(mut self, estimator: FeeEstimator) -> Self {
+        self.estimator = Some(estimator);
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // unix milliseconds, outputs still locked at `now` aren't spent
+    pub fn at(mut self, now: u64) -> Self {
+        self.now = now;
+        self
+    }
+
+    pub fn build(&self) -> Result<UnsignedTransaction, TransactionError> {
+        let Some(estimator) = self.estimator else {
+            return self.build_with_gas(self.gas_amount, self.gas_price);
+        };
+
+        let operation = if self.destinations.iter().any(|destination| !destination.tokens.is_empty()) {
+            Operation::TokenTransfer
+        } else {
+            Operation::Transfer
+        };
+        // a bigger fee can pull in more inputs, which need more gas again,
+        // so go round until the gas covers the transaction it paid for
+        let mut gas_amount = estimator.gas(operation, 1, self.destinations.len() + 1);
+        loop {
+            let tx = self.build_with_gas(gas_amount, estimator.gas_price())?;
+            let needed = estimator.gas(operation, tx.inputs.len(), tx.fixed_outputs.len());
+            if needed <= gas_amount {
+                return Ok(tx);
+            }
+            gas_amount = needed;
+        }
+    }
+
+    fn build_with_gas(&self, gas_amount: u64, gas_price: u128) -> Result<UnsignedTransaction, TransactionError> {
+        let target = self.validate(gas_amount, gas_price)?;
+        let sender = self.public_key.address();
+        // only outputs this key can unlock
+        let owned = UtxoSet::new(self.utxos.utxos().iter().filter(|utxo| utxo.output.lockup_script == sender).cloned().collect());
+
+        // select leaves dust for one change output, but token change split
+        // over several needs dust for each, so select again holding back
+        // enough for all of them
+        let mut reserve = 0u128;
+        let (selection, change) = loop {
+            let alph_amount = target.alph_amount.checked_add(reserve).ok_or(TransactionError::Overflow)?;
+            let selection = owned.select(&Target { alph_amount, ..target.clone() }, &sender, self.now, self.strategy, DUST_AMOUNT)?;
+
+            let (leftover, tokens) = match &selection.change {
+                Some(change) => (change.alph_amount, change.tokens.clone()),
+                None => (0, vec![])
+            };
+            let leftover = leftover.checked_add(reserve).ok_or(TransactionError::Overflow)?;
+            let outputs = if tokens.is_empty() { usize::from(leftover > 0) } else { tokens.len().div_ceil(MAX_TOKENS_PER_OUTPUT) };
+            let needed = DUST_AMOUNT * outputs as u128;
+
+            if leftover >= needed {
+                break (selection, split_change(&sender, leftover, tokens));
+            }
+            reserve = needed;
+        };
+
+        if selection.inputs.len() > MAX_INPUTS {
+            return Err(TransactionError::TooManyInputs(selection.inputs.len()));
+        }
+        let fixed_outputs: Vec<AssetOutput> = self.destinations.iter().cloned().chain(change).collect();
+        if fixed_outputs.len() > MAX_OUTPUTS {
+            return Err(TransactionError::TooManyOutputs(fixed_outputs.len()));
+        }
+
+        // the node takes one signature per P2PKH unlock, so only the first
+        // input carries the key and the rest reuse it
+        let inputs = selection.inputs.into_iter()
+            .enumerate()
+            .map(|(index, utxo)| {
+                let unlock_script = if index == 0 { UnlockScript::P2PKH(self.public_key) } else { UnlockScript::SameAsPrevious };
+                TxInput { output_ref: utxo.reference, unlock_script }
+            })
+            .collect();
+        Ok(UnsignedTransaction {
+            version: TRANSACTION_VERSION,
+            network_id: self.network.id(),
+            gas_amount,
+            gas_price,
+            inputs,
+            fixed_outputs
+        })
+    }
+
+    // everything the inputs have to cover, fee included
+    fn validate(&self, gas_amount: u64, gas_price: u128) -> Result<Target, TransactionError> {
+        if self.destinations.is_empty() {
+            return Err(TransactionError::NoDestinations);
+        }
+        if !(MINIMAL_GAS..=MAXIMAL_GAS_PER_TX).contains(&gas_amount) {
+            return Err(TransactionError::GasOutOfRange(gas_amount));
+        }
+        if gas_price < MIN_GAS_PRICE {
+            return Err(TransactionError::GasPriceTooLow(gas_price));
+        }
+
+        let mut target = Target {
+            alph_amount: (gas_amount as u128).checked_mul(gas_price).ok_or(TransactionError::Overflow)?,
+            tokens: vec![]
+        };
+        for (index, destination) in self.destinations.iter().enumerate() {
+            if matches!(destination.lockup_script, Address::P2C(_)) {
+                return Err(TransactionError::ContractDestination { index });
+            }
+            if destination.alph_amount < DUST_AMOUNT {
+                return Err(TransactionError::Dust { index, alph_amount: destination.alph_amount });
+            }
+            if destination.tokens.len() > MAX_TOKENS_PER_OUTPUT {
+                return Err(TransactionError::TooManyTokens { index, count: destination.tokens.len() });
+            }
+
+            let mut seen = BTreeSet::new();
+            for (token, amount) in &destination.tokens {
+                if token.is_alph() {
+                    return Err(TransactionError::NativeToken { index });
+                }
+                if !seen.insert(*token) {
+                    return Err(TransactionError::DuplicateToken { index, token: *token });
+                }
+                if *amount == 0 {
+                    return Err(TransactionError::ZeroTokenAmount { index, token: *token });
+                }
+            }
+
+            target.alph_amount = target.alph_amount.checked_add(destination.alph_amount).ok_or(TransactionError::Overflow)?;
+            target.tokens.extend_from_slice(&destination.tokens);
+        }
+        Ok(target)
+    }
+}
+
+// `alph_amount` covers a dust amount for every output this makes
+fn split_change(sender: &Address, alph_amount: u128, tokens: Vec<(TokenId, u128)>) -> Vec<AssetOutput> {
+    if tokens.is_empty() {
+        return if alph_amount > 0 { vec![AssetOutput::new(alph_amount, sender.clone())] } else { vec![] };
+    }
+
+    let chunks: Vec<_> = tokens.chunks(MAX_TOKENS_PER_OUTPUT).map(<[_]>::to_vec).collect();
+    let extra = DUST_AMOUNT * (chunks.len() as u128 - 1);
+    chunks.into_iter().enumerate()
+        .map(|(i, chunk)| {
+            let alph = if i == 0 { alph_amount - extra } else { DUST_AMOUNT };
+            AssetOutput::new(alph, sender.clone()).with_tokens(chunk)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyPair;
+    use crate::utxo::Utxo;
+
+    const ALPH: u128 = 1_000_000_000_000_000_000;
+    const FEE: u128 = MINIMAL_GAS as u128 * MIN_GAS_PRICE;
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+
+    fn create_test_key() -> KeyPair {
+        KeyPair::from_secret_bytes(&[7; 32]).unwrap()
+    }
+
+    fn create_test_utxo(key: &KeyPair, index: u32, alph_amount: u128, tokens: Vec<(TokenId, u128)>) -> Utxo {
+        Utxo {
+            reference: OutputRef::new(&[0x77; 32], index, &key.address()).unwrap(),
+            output: AssetOutput::new(alph_amount, key.address()).with_tokens(tokens)
+        }
+    }
+
+    fn create_test_set(key: &KeyPair) -> UtxoSet {
+        UtxoSet::new(vec![
+            create_test_utxo(key, 0, 5 * ALPH, vec![]),
+            create_test_utxo(key, 1, 2 * ALPH, vec![(USDT, 1_000)]),
+        ])
+    }
+
+    fn recipient() -> Address {
+        Address::p2pkh(&[3; 33])
+    }
+
+    // inputs pay for outputs and fee exactly
+    fn assert_balanced(set: &UtxoSet, tx: &UnsignedTransaction, token: &TokenId) {
+        let spent: u128 = tx.inputs.iter()
+            .map(|input| set.utxos().iter().find(|utxo| utxo.reference == input.output_ref).unwrap().output.token_amount(token))
+            .sum();
+        let paid: u128 = tx.fixed_outputs.iter().map(|output| output.token_amount(token)).sum();
+        let fee = if token.is_alph() { tx.fee().unwrap() } else { 0 };
+        assert_eq!(spent, paid + fee);
+    }
+
+    #[test]
+    fn test_transfer_with_change() {
+        let key = create_test_key();
+        let set = create_test_set(&key);
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Testnet)
+            .pay(AssetOutput::new(ALPH, recipient()))
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.network_id, 1);
+        assert_eq!(tx.fee(), Ok(FEE));
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].unlock_script, UnlockScript::P2PKH(key.public_key()));
+        assert_eq!(tx.fixed_outputs, vec![
+            AssetOutput::new(ALPH, recipient()),
+            AssetOutput::new(4 * ALPH - FEE, key.address()),
+        ]);
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+    }
+
+    #[test]
+    fn test_amounts_past_u64() {
+        // 50,000 ALPH is well past the ~18.4 a u64 of attoALPH holds
+        let key = create_test_key();
+        let set = UtxoSet::new(vec![
+            create_test_utxo(&key, 0, 20_000 * ALPH, vec![]),
+            create_test_utxo(&key, 1, 30_000 * ALPH, vec![]),
+        ]);
+        assert_eq!(set.balance(&TokenId::ALPH), Ok(50_000 * ALPH));
+
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(45_000 * ALPH, recipient()))
+            .build()
+            .unwrap();
+        assert_eq!(tx.fixed_outputs[1].alph_amount, 5_000 * ALPH - FEE);
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+        assert_eq!(codec::deserialize::<UnsignedTransaction>(&codec::serialize(&tx)), Ok(tx));
+    }
+
+    #[test]
+    fn test_tokens_and_lock_times() {
+        let key = create_test_key();
+        let set = create_test_set(&key);
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(ALPH, recipient()).with_tokens(vec![(USDT, 400)]).with_lock_time(1_700_000_000_000))
+            .pay(AssetOutput::new(ALPH, Address::p2pkh(&[4; 33])))
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.fixed_outputs.len(), 3);
+        assert_eq!(tx.fixed_outputs[0].lock_time, 1_700_000_000_000);
+        assert_eq!(tx.fixed_outputs[2].tokens, vec![(USDT, 600)]);
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+        assert_balanced(&set, &tx, &USDT);
+    }
+
+    #[test]
+    fn test_small_leftover_is_topped_up() {
+        let key = create_test_key();
+        let set = UtxoSet::new(vec![
+            create_test_utxo(&key, 0, ALPH + FEE + 1, vec![]),
+            create_test_utxo(&key, 1, ALPH, vec![]),
+        ]);
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(ALPH, recipient()))
+            .build()
+            .unwrap();
+
+        // 1 attoALPH can't be its own output, so the second input comes in
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.inputs[0].unlock_script, UnlockScript::P2PKH(key.public_key()));
+        assert_eq!(tx.inputs[1].unlock_script, UnlockScript::SameAsPrevious);
+        assert_eq!(tx.fixed_outputs[1].alph_amount, ALPH + 1);
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+    }
+
+    #[test]
+    fn test_one_signature_for_many_inputs() {
+        let key = create_test_key();
+        let utxos: Vec<Utxo> = (0..4).map(|i| create_test_utxo(&key, i, ALPH, vec![])).collect();
+        let set = UtxoSet::new(utxos);
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(3 * ALPH, recipient()))
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 4);
+        let scripts: Vec<UnlockScript> = tx.inputs.iter().map(|input| input.unlock_script.clone()).collect();
+        assert_eq!(scripts, vec![
+            UnlockScript::P2PKH(key.public_key()),
+            UnlockScript::SameAsPrevious,
+            UnlockScript::SameAsPrevious,
+            UnlockScript::SameAsPrevious,
+        ]);
+        assert_eq!(codec::deserialize::<UnsignedTransaction>(&codec::serialize(&tx)), Ok(tx));
+    }
+
+    #[test]
+    fn test_token_change_is_split() {
+        let key = create_test_key();
+        let tokens: Vec<(TokenId, u128)> = (1..=6u8).map(|i| (TokenId::new([i; 32]), 10)).collect();
+        let set = UtxoSet::new(vec![
+            create_test_utxo(&key, 0, 3 * ALPH, tokens.clone()),
+            create_test_utxo(&key, 1, ALPH, vec![]),
+        ]);
+        let tx = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(ALPH, recipient()).with_tokens(vec![(tokens[0].0, 5)]))
+            .build()
+            .unwrap();
+
+        let change = &tx.fixed_outputs[1..];
+        assert_eq!(change.len(), 2);
+        assert_eq!(change[0].tokens.len(), MAX_TOKENS_PER_OUTPUT);
+        assert_eq!(change[1].tokens.len(), 2);
+        assert_eq!(change[1].alph_amount, DUST_AMOUNT);
+        for (id, _) in &tokens {
+            assert_balanced(&set, &tx, id);
+        }
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+    }
+
+    #[test]
+    fn test_id_commits_to_everything() {
+        let key = create_test_key();
+        let set = create_test_set(&key);
+        let builder = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet).pay(AssetOutput::new(ALPH, recipient()));
+        let tx = builder.build().unwrap();
+
+        assert_eq!(builder.build().unwrap().id(), tx.id());
+        let mut changed = tx.clone();
+        changed.fixed_outputs[0].lock_time = 1;
+        assert_ne!(changed.id(), tx.id());
+        let mut changed = tx.clone();
+        changed.network_id = 1;
+        assert_ne!(changed.id(), tx.id());
+
+        let signature = key.sign(&tx.id());
+        assert!(key.public_key().verify(&tx.id(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_destinations() {
+        let key = create_test_key();
+        let set = create_test_set(&key);
+        let build = |destination: AssetOutput| {
+            TransactionBuilder::new(&set, key.public_key(), Network::Mainnet).pay(destination).build()
+        };
+
+        assert_eq!(TransactionBuilder::new(&set, key.public_key(), Network::Mainnet).build(), Err(TransactionError::NoDestinations));
+        assert_eq!(
+            build(AssetOutput::new(DUST_AMOUNT - 1, recipient())),
+            Err(TransactionError::Dust { index: 0, alph_amount: DUST_AMOUNT - 1 })
+        );
+        let five: Vec<(TokenId, u128)> = (1..=5u8).map(|i| (TokenId::new([i; 32]), 1)).collect();
+        assert_eq!(build(AssetOutput::new(ALPH, recipient()).with_tokens(five)), Err(TransactionError::TooManyTokens { index: 0, count: 5 }));
+        assert_eq!(
+            build(AssetOutput::new(ALPH, recipient()).with_tokens(vec![(USDT, 1), (USDT, 2)])),
+            Err(TransactionError::DuplicateToken { index: 0, token: USDT })
+        );
+        assert_eq!(
+            build(AssetOutput::new(ALPH, recipient()).with_tokens(vec![(USDT, 0)])),
+            Err(TransactionError::ZeroTokenAmount { index: 0, token: USDT })
+        );
+        assert_eq!(
+            build(AssetOutput::new(ALPH, recipient()).with_tokens(vec![(TokenId::ALPH, 1)])),
+            Err(TransactionError::NativeToken { index: 0 })
+        );
+        let contract = Address::contract(crate::contract::ContractId::new([9; 32]));
+        assert_eq!(build(AssetOutput::new(ALPH, contract)), Err(TransactionError::ContractDestination { index: 0 }));
+    }
+
+    #[test]
+    fn test_gas_and_funds() {
+        let key = create_test_key();
+        let set = create_test_set(&key);
+        let builder = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet).pay(AssetOutput::new(ALPH, recipient()));
+
+        assert_eq!(builder.clone().with_gas(MINIMAL_GAS - 1, MIN_GAS_PRICE).build(), Err(TransactionError::GasOutOfRange(MINIMAL_GAS - 1)));
+        assert_eq!(builder.clone().with_gas(MINIMAL_GAS, 1).build(), Err(TransactionError::GasPriceTooLow(1)));
+
+        let tx = builder.clone().with_gas(50_000, 2 * MIN_GAS_PRICE).build().unwrap();
+        assert_eq!(tx.fee(), Ok(50_000 * 2 * MIN_GAS_PRICE));
+        assert_balanced(&set, &tx, &TokenId::ALPH);
+
+        // a gas price the builder would never have allowed, off the wire
+        let decoded = UnsignedTransaction { gas_price: u128::MAX, ..tx };
+        let decoded = codec::deserialize::<UnsignedTransaction>(&codec::serialize(&decoded)).unwrap();
+        assert_eq!(decoded.fee(), Err(TransactionError::Overflow));
+
+        let greedy = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet).pay(AssetOutput::new(7 * ALPH, recipient()));
+        assert!(matches!(greedy.build(), Err(TransactionError::Utxo(UtxoError::InsufficientFunds { .. }))));
+
+        // someone else's outputs can't be spent
+        let thief = KeyPair::from_secret_bytes(&[8; 32]).unwrap();
+        let stolen = TransactionBuilder::new(&set, thief.public_key(), Network::Mainnet).pay(AssetOutput::new(ALPH, recipient()));
+        assert!(matches!(stolen.build(), Err(TransactionError::Utxo(UtxoError::InsufficientFunds { available: 0, .. }))));
+    }
+
+    #[test]
+    fn test_estimated_gas() {
+        let key = create_test_key();
+        let utxos: Vec<Utxo> = (0..8).map(|i| create_test_utxo(&key, i, ALPH / 2, vec![])).collect();
+        let set = UtxoSet::new(utxos);
+        let estimator = FeeEstimator::default();
+
+        let small = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(ALPH / 4, recipient()))
+            .with_estimated_gas(estimator)
+            .build()
+            .unwrap();
+        assert_eq!(small.gas_amount, MINIMAL_GAS);
+
+        // seven inputs is more than the minimal gas covers
+        let large = TransactionBuilder::new(&set, key.public_key(), Network::Mainnet)
+            .pay(AssetOutput::new(3 * ALPH, recipient()))
+            .with_estimated_gas(estimator)
+            .build()
+            .unwrap();
+        assert_eq!(large.inputs.len(), 7);
+        assert_eq!(large.gas_amount, estimator.gas(Operation::Transfer, 7, 2));
+        assert_balanced(&set, &large, &TokenId::ALPH);
+    }
+}
@@ -0,0 +1,528 @@
+// utxo model
+
+// on chain nobody has "a balance", they have unspent outputs. each output
+// carries some ALPH, maybe some tokens, the lockup script that can spend it
+// and a lock time before which it can't be spent at all. `Account` is the
+// aggregated view of a set of these.
+//
+// spending picks inputs (coin selection), pays the target and sends what's
+// left back as a change output. a transaction has to balance exactly, so
+// there's nothing to leave to the miners: any leftover goes into the change,
+// and since every output has to carry at least the dust amount of ALPH, a
+// leftover smaller than that (or token change with too little ALPH to carry
+// it) pulls in more ALPH until the change covers the dust.
+
+use std::collections::BTreeMap;
+
+use crate::account::{Account, AccountError, Token};
+use crate::address::{blake2b, Address, Hash, HASH_LENGTH};
+use crate::contract::TokenId;
+use crate::group::{address_hint, GroupError};
+
+// 0.001 ALPH, in attoALPH
+pub const DUST_AMOUNT: u128 = 1_000_000_000_000_000;
+
+// how many subsets branch and bound looks at before giving up
+const MAX_BNB_TRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UtxoError {
+    InsufficientFunds { token: TokenId, available: u128, requested: u128 },
+    // branch and bound found no input set that avoids a change output
+    NoExactMatch,
+    Overflow
+}
+
+// how an input refers to the output it spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutputRef {
+    pub hint: u32,
+    pub key: Hash
+}
+
+impl OutputRef {
+
+    // output `index` of transaction `tx_id`, locked by `lockup_script`
+    pub fn new(tx_id: &Hash, index: u32, lockup_script: &Address) -> Result<Self, GroupError> {
+        let mut preimage = [0u8; HASH_LENGTH + 4];
+        preimage[..HASH_LENGTH].copy_from_slice(tx_id);
+        preimage[HASH_LENGTH..].copy_from_slice(&index.to_be_bytes());
+        Ok(OutputRef { hint: address_hint(lockup_script)?, key: blake2b(&preimage) })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetOutput {
+    pub alph_amount: u128,
+    pub lockup_script: Address,
+    // unix milliseconds, 0 for spendable right away
+    pub lock_time: u64,
+    pub tokens: Vec<(TokenId, u128)>,
+    pub additional_data: Vec<u8>
+}
+
+impl AssetOutput {
+
+    pub fn new(alph_amount: u128, lockup_script: Address) -> Self {
+        AssetOutput { alph_amount, lockup_script, lock_time: 0, tokens: vec![], additional_data: vec![] }
+    }
+
+    pub fn with_tokens(mut self, tokens: Vec<(TokenId, u128)>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    pub fn with_lock_time(mut self, lock_time: u64) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    pub fn token_amount(&self, id: &TokenId) -> u128 {
+        if id.is_alph() {
+            return self.alph_amount;
+        }
+        self.tokens.iter().filter(|(token, _)| token == id).map(|(_, amount)| amount).sum()
+    }
+
+    pub fn is_unlocked(&self, now: u64) -> bool {
+        self.lock_time <= now
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utxo {
+    pub reference: OutputRef,
+    pub output: AssetOutput
+}
+
+// what a spend has to pay out, not counting change
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Target {
+    pub alph_amount: u128,
+    pub tokens: Vec<(TokenId, u128)>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionStrategy {
+    // biggest outputs first: few inputs, almost always a change output
+    LargestFirst,
+    // search for inputs that cover the target exactly, so no change output
+    // is needed at all
+    BranchAndBound
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub inputs: Vec<Utxo>,
+    // None when the inputs add up to the target exactly
+    pub change: Option<AssetOutput>
+}
+
+impl Selection {
+
+    pub fn input_amount(&self, id: &TokenId) -> u128 {
+        self.inputs.iter().map(|utxo| utxo.output.token_amount(id)).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UtxoSet {
+    utxos: Vec<Utxo>
+}
+
+fn checked_sum<'a>(mut outputs: impl Iterator<Item = &'a AssetOutput>, id: &TokenId) -> Result<u128, UtxoError> {
+    outputs.try_fold(0u128, |total, output| total.checked_add(output.token_amount(id)).ok_or(UtxoError::Overflow))
+}
+
+fn target_tokens(target: &Target) -> Result<BTreeMap<TokenId, u128>, UtxoError> {
+    let mut tokens = BTreeMap::new();
+    for (id, amount) in &target.tokens {
+        let total: &mut u128 = tokens.entry(*id).or_default();
+        *total = total.checked_add(*amount).ok_or(UtxoError::Overflow)?;
+    }
+    Ok(tokens)
+}
+
+impl UtxoSet {
+
+    pub fn new(utxos: Vec<Utxo>) -> Self {
+        UtxoSet { utxos }
+    }
+
+    pub fn utxos(&self) -> &[Utxo] {
+        &self.utxos
+    }
+
+    pub fn insert(&mut self, utxo: Utxo) {
+        self.utxos.push(utxo);
+    }
+
+    // spend an output, handing it back if it was there
+    pub fn remove(&mut self, reference: &OutputRef) -> Option<Utxo> {
+        let index = self.utxos.iter().position(|utxo| utxo.reference == *reference)?;
+        Some(self.utxos.swap_remove(index))
+    }
+
+    // everything held, locked or not
+    pub fn balance(&self, id: &TokenId) -> Result<u128, UtxoError> {
+        checked_sum(self.utxos.iter().map(|utxo| &utxo.output), id)
+    }
+
+    pub fn spendable_balance(&self, id: &TokenId, now: u64) -> Result<u128, UtxoError> {
+        checked_sum(self.spendable(now).map(|utxo| &utxo.output), id)
+    }
+
+    fn spendable(&self, now: u64) -> impl Iterator<Item = &Utxo> {
+        self.utxos.iter().filter(move |utxo| utxo.output.is_unlocked(now))
+    }
+
+    // the aggregated view, locked outputs included. `tokens` supplies the
+    // metadata for every token the outputs carry
+    pub fn to_account(&self, tokens: Vec<Token>) -> Result<Account, AccountError> {
+        let mut account = Account::new(0, tokens);
+        for utxo in &self.utxos {
+            account.deposit(&TokenId::ALPH, utxo.output.alph_amount)?;
+            for (id, amount) in &utxo.output.tokens {
+                account.deposit(id, *amount)?;
+            }
+        }
+        Ok(account)
+    }
+
+    // pick unlocked inputs paying `target`, with change going back to
+    // `change_address`
+    pub fn select(
+        &self,
+        target: &Target,
+        change_address: &Address,
+        now: u64,
+        strategy: SelectionStrategy,
+        dust_limit: u128
+    ) -> Result<Selection, UtxoError> {
+        let wanted = target_tokens(target)?;
+        let mut available: Vec<&Utxo> = self.spendable(now).collect();
+        let mut inputs: Vec<&Utxo> = Vec::new();
+
+        // tokens first, since only some outputs carry them at all
+        for (id, amount) in &wanted {
+            available.sort_by_key(|utxo| std::cmp::Reverse(utxo.output.token_amount(id)));
+            let mut have = checked_sum(inputs.iter().map(|utxo| &utxo.output), id)?;
+            while have < *amount {
+                match available.first() {
+                    Some(utxo) if utxo.output.token_amount(id) > 0 => {
+                        have = have.checked_add(utxo.output.token_amount(id)).ok_or(UtxoError::Overflow)?;
+                        inputs.push(available.remove(0));
+                    }
+                    _ => return Err(UtxoError::InsufficientFunds { token: *id, available: have, requested: *amount })
+                }
+            }
+        }
+
+        let mut token_change = self.has_token_change(&inputs, &wanted)?;
+        let mut have = checked_sum(inputs.iter().map(|utxo| &utxo.output), &TokenId::ALPH)?;
+
+        match strategy {
+            SelectionStrategy::LargestFirst => {
+                // an output carrying tokens can bring token change along, so
+                // what's needed is worked out again after every pick
+                available.sort_by_key(|utxo| std::cmp::Reverse(utxo.output.alph_amount));
+                let total = have.saturating_add(checked_sum(available.iter().map(|utxo| &utxo.output), &TokenId::ALPH)?);
+                let mut rest = available.into_iter();
+                loop {
+                    let need = alph_needed(target.alph_amount, have, token_change, dust_limit)?;
+                    if have >= need {
+                        break;
+                    }
+                    let Some(utxo) = rest.next() else {
+                        return Err(UtxoError::InsufficientFunds { token: TokenId::ALPH, available: total, requested: need });
+                    };
+                    have = have.checked_add(utxo.output.alph_amount).ok_or(UtxoError::Overflow)?;
+                    inputs.push(utxo);
+                    token_change = token_change || self.has_token_change(&inputs, &wanted)?;
+                }
+            }
+            SelectionStrategy::BranchAndBound => {
+                let need = alph_needed(target.alph_amount, have, token_change, dust_limit)?;
+                if have < need {
+                    // only plain ALPH outputs, anything else would bring token change
+                    let mut plain: Vec<&Utxo> = available.into_iter().filter(|utxo| utxo.output.tokens.is_empty()).collect();
+                    plain.sort_by_key(|utxo| std::cmp::Reverse(utxo.output.alph_amount));
+                    inputs.extend(branch_and_bound(&plain, need - have).ok_or(UtxoError::NoExactMatch)?);
+                }
+            }
+        }
+
+        self.finish(inputs, target, &wanted, change_address)
+    }
+
+    fn has_token_change(&self, inputs: &[&Utxo], wanted: &BTreeMap<TokenId, u128>) -> Result<bool, UtxoError> {
+        for utxo in inputs {
+            for (id, _) in &utxo.output.tokens {
+                let have = checked_sum(inputs.iter().map(|utxo| &utxo.output), id)?;
+                if have > wanted.get(id).copied().unwrap_or(0) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn finish(
+        &self,
+        inputs: Vec<&Utxo>,
+        target: &Target,
+        wanted: &BTreeMap<TokenId, u128>,
+        change_address: &Address
+    ) -> Result<Selection, UtxoError> {
+        let outputs = || inputs.iter().map(|utxo| &utxo.output);
+
+        let mut change_tokens = BTreeMap::new();
+        for (id, _) in outputs().flat_map(|output| output.tokens.iter()) {
+            let extra = checked_sum(outputs(), id)? - wanted.get(id).copied().unwrap_or(0);
+            if extra > 0 {
+                change_tokens.insert(*id, extra);
+            }
+        }
+
+        let have = checked_sum(outputs(), &TokenId::ALPH)?;
+        if have < target.alph_amount {
+            return Err(UtxoError::InsufficientFunds { token: TokenId::ALPH, available: have, requested: target.alph_amount });
+        }
+        let change_alph = have - target.alph_amount;
+
+        let change = if change_tokens.is_empty() && change_alph == 0 {
+            None
+        } else {
+            Some(AssetOutput::new(change_alph, change_address.clone()).with_tokens(change_tokens.into_iter().collect()))
+        };
+
+        Ok(Selection { inputs: inputs.into_iter().cloned().collect(), change })
+    }
+}
+
+// the ALPH inputs holding `have` must reach: the target exactly, or the
+// target plus dust for a change output once anything is left over
+fn alph_needed(target: u128, have: u128, token_change: bool, dust_limit: u128) -> Result<u128, UtxoError> {
+    if !token_change && have <= target {
+        return Ok(target);
+    }
+    target.checked_add(dust_limit).ok_or(UtxoError::Overflow)
+}
+
+// depth first over include/exclude for each output, biggest first, looking
+// for a total of exactly `need`. `available` sorted biggest first
+fn branch_and_bound<'a>(available: &[&'a Utxo], need: u128) -> Option<Vec<&'a Utxo>> {
+    let amounts: Vec<u128> = available.iter().map(|utxo| utxo.output.alph_amount).collect();
+    // what is still left to add from position i on
+    let mut remaining = vec![0u128; amounts.len() + 1];
+    for i in (0..amounts.len()).rev() {
+        remaining[i] = remaining[i + 1].saturating_add(amounts[i]);
+    }
+    let mut picked = vec![false; amounts.len()];
+    let mut tries = 0;
+    if search(&amounts, &remaining, 0, 0, need, &mut picked, &mut tries) {
+        Some(available.iter().zip(picked).filter(|(_, p)| *p).map(|(utxo, _)| *utxo).collect())
+    } else {
+        None
+    }
+}
+
+fn search(amounts: &[u128], remaining: &[u128], i: usize, total: u128, need: u128, picked: &mut [bool], tries: &mut usize) -> bool {
+    *tries += 1;
+    if total >= need {
+        return total == need;
+    }
+    if i == amounts.len() || *tries > MAX_BNB_TRIES || total.saturating_add(remaining[i]) < need {
+        return false;
+    }
+
+    picked[i] = true;
+    if search(amounts, remaining, i + 1, total.saturating_add(amounts[i]), need, picked, tries) {
+        return true;
+    }
+    picked[i] = false;
+    search(amounts, remaining, i + 1, total, need, picked, tries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDT: TokenId = TokenId::new([0x17; 32]);
+    const DUST: u128 = 10;
+
+    fn owner() -> Address {
+        Address::p2pkh(&[2; 33])
+    }
+
+    fn create_test_utxo(index: u32, alph_amount: u128, tokens: Vec<(TokenId, u128)>) -> Utxo {
+        Utxo {
+            reference: OutputRef::new(&[0x77; 32], index, &owner()).unwrap(),
+            output: AssetOutput::new(alph_amount, owner()).with_tokens(tokens)
+        }
+    }
+
+    fn create_test_set() -> UtxoSet {
+        UtxoSet::new(vec![
+            create_test_utxo(0, 500, vec![]),
+            create_test_utxo(1, 300, vec![]),
+            create_test_utxo(2, 200, vec![]),
+            create_test_utxo(3, 50, vec![(USDT, 1_000)]),
+        ])
+    }
+
+    fn alph(amount: u128) -> Target {
+        Target { alph_amount: amount, tokens: vec![] }
+    }
+
+    #[test]
+    fn test_output_refs() {
+        let a = OutputRef::new(&[1; 32], 0, &owner()).unwrap();
+        assert_eq!(a.hint, address_hint(&owner()).unwrap());
+        assert_ne!(OutputRef::new(&[1; 32], 1, &owner()).unwrap().key, a.key);
+        assert_ne!(OutputRef::new(&[2; 32], 0, &owner()).unwrap().key, a.key);
+    }
+
+    #[test]
+    fn test_balances_and_locks() {
+        let mut set = create_test_set();
+        set.insert(Utxo {
+            reference: OutputRef::new(&[0x78; 32], 0, &owner()).unwrap(),
+            output: AssetOutput::new(1_000, owner()).with_lock_time(5_000)
+        });
+
+        assert_eq!(set.balance(&TokenId::ALPH), Ok(2_050));
+        assert_eq!(set.spendable_balance(&TokenId::ALPH, 4_999), Ok(1_050));
+        assert_eq!(set.spendable_balance(&TokenId::ALPH, 5_000), Ok(2_050));
+        assert_eq!(set.balance(&USDT), Ok(1_000));
+
+        // locked outputs are never picked
+        let selection = set.select(&alph(1_500), &owner(), 0, SelectionStrategy::LargestFirst, DUST);
+        assert!(matches!(selection, Err(UtxoError::InsufficientFunds { available: 1_050, .. })));
+    }
+
+    #[test]
+    fn test_balances_past_u64() {
+        const ALPH: u128 = 1_000_000_000_000_000_000;
+        let set = UtxoSet::new(vec![
+            create_test_utxo(0, 1_000 * ALPH, vec![]),
+            create_test_utxo(1, 2_000 * ALPH, vec![(USDT, u64::MAX as u128)]),
+            create_test_utxo(2, 3_000 * ALPH, vec![(USDT, u64::MAX as u128)]),
+        ]);
+        assert_eq!(set.balance(&TokenId::ALPH), Ok(6_000 * ALPH));
+        assert_eq!(set.balance(&USDT), Ok(2 * u64::MAX as u128));
+
+        let selection = set.select(&alph(4_500 * ALPH), &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+        assert_eq!(selection.input_amount(&TokenId::ALPH), 5_000 * ALPH);
+        assert_eq!(selection.change.unwrap().alph_amount, 500 * ALPH);
+
+        let usdt = Token::new(USDT, "Tether USD".to_string(), "USDT".to_string(), 6, u128::MAX);
+        assert_eq!(set.to_account(vec![usdt]).unwrap().alph_balance(), 6_000 * ALPH);
+    }
+
+    #[test]
+    fn test_to_account() {
+        let usdt = Token::new(USDT, "Tether USD".to_string(), "USDT".to_string(), 6, 1_000_000);
+        let account = create_test_set().to_account(vec![usdt]).unwrap();
+        assert_eq!(account.alph_balance(), 1_050);
+        assert_eq!(account.balance(&USDT), 1_000);
+
+        assert_eq!(create_test_set().to_account(vec![]), Err(AccountError::UnknownToken(USDT)));
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let set = create_test_set();
+        let selection = set.select(&alph(600), &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.input_amount(&TokenId::ALPH), 800);
+        assert_eq!(selection.change, Some(AssetOutput::new(200, owner())));
+
+        // exactly the target needs no change
+        let selection = set.select(&alph(500), &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+        assert_eq!((selection.inputs.len(), selection.change), (1, None));
+    }
+
+    #[test]
+    fn test_dust_change_pulls_in_more() {
+        let set = create_test_set();
+        // 500 alone would leave 5, too little for an output and nowhere else
+        // to go, so the next output comes in to make real change
+        let selection = set.select(&alph(495), &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.change, Some(AssetOutput::new(305, owner())));
+
+        // and when there's nothing more, the change's dust is what's missing
+        assert_eq!(
+            set.select(&alph(1_045), &owner(), 0, SelectionStrategy::LargestFirst, DUST),
+            Err(UtxoError::InsufficientFunds { token: TokenId::ALPH, available: 1_050, requested: 1_055 })
+        );
+    }
+
+    #[test]
+    fn test_tokens_picked_for_alph_carry_dust() {
+        // the only output holds tokens, so spending it for its ALPH makes
+        // token change that has no ALPH left to carry it
+        let set = UtxoSet::new(vec![create_test_utxo(0, 10 * DUST, vec![(USDT, 5)])]);
+        assert_eq!(
+            set.select(&alph(10 * DUST), &owner(), 0, SelectionStrategy::LargestFirst, DUST),
+            Err(UtxoError::InsufficientFunds { token: TokenId::ALPH, available: 10 * DUST, requested: 11 * DUST })
+        );
+
+        // with another output to draw on, the change gets its dust
+        let mut set = set;
+        set.insert(create_test_utxo(1, 3 * DUST, vec![]));
+        let selection = set.select(&alph(10 * DUST), &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+        let change = selection.change.unwrap();
+        assert_eq!(change.tokens, vec![(USDT, 5)]);
+        assert_eq!(change.alph_amount, 3 * DUST);
+    }
+
+    #[test]
+    fn test_branch_and_bound_avoids_change() {
+        let set = create_test_set();
+        // largest first would take 500 + 300 and make change
+        let selection = set.select(&alph(700), &owner(), 0, SelectionStrategy::BranchAndBound, DUST).unwrap();
+        assert_eq!(selection.input_amount(&TokenId::ALPH), 700);
+        assert_eq!(selection.change, None);
+
+        // nothing sums to exactly 650 without tokens tagging along
+        assert_eq!(
+            set.select(&alph(650), &owner(), 0, SelectionStrategy::BranchAndBound, DUST),
+            Err(UtxoError::NoExactMatch)
+        );
+    }
+
+    #[test]
+    fn test_token_change_carries_dust() {
+        let set = create_test_set();
+        let target = Target { alph_amount: 45, tokens: vec![(USDT, 400)] };
+        let selection = set.select(&target, &owner(), 0, SelectionStrategy::LargestFirst, DUST).unwrap();
+
+        // the token output alone leaves 5 ALPH for a change output that needs 10
+        assert_eq!(selection.inputs.len(), 2);
+        let change = selection.change.unwrap();
+        assert_eq!(change.tokens, vec![(USDT, 600)]);
+        assert_eq!(change.alph_amount, 550 - 45);
+    }
+
+    #[test]
+    fn test_insufficient_tokens() {
+        let set = create_test_set();
+        let target = Target { alph_amount: 0, tokens: vec![(USDT, 600), (USDT, 600)] };
+        assert_eq!(
+            set.select(&target, &owner(), 0, SelectionStrategy::LargestFirst, DUST),
+            Err(UtxoError::InsufficientFunds { token: USDT, available: 1_000, requested: 1_200 })
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut set = create_test_set();
+        let reference = set.utxos()[1].reference;
+        assert_eq!(set.remove(&reference).unwrap().output.alph_amount, 300);
+        assert_eq!(set.remove(&reference), None);
+        assert_eq!(set.balance(&TokenId::ALPH), Ok(750));
+    }
+}
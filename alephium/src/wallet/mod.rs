@@ -0,0 +1,292 @@
+// hd wallets
+
+// a BIP 39 mnemonic stretches into a 64 byte seed (with an optional
+// passphrase), and BIP 32 derives a tree of keys from that seed. alephium
+// wallets use the BIP 44 path
+//
+//   m/44'/1234'/0'/0/i
+//
+// where 1234 is alephium's coin type and i counts addresses. an address's
+// group falls out of its key, so wallets that want an address in a given
+// group walk i until one lands there.
+
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::PrimeField;
+use k256::Scalar;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha512;
+
+use crate::account::{Account, Token};
+use crate::group::{GroupConfig, GroupError};
+use crate::keys::{KeyPair, SECRET_KEY_LENGTH};
+
+pub const ALEPHIUM_COIN_TYPE: u32 = 1234;
+pub const HARDENED: u32 = 0x8000_0000;
+
+// a fair key source finds a group in ~groups tries
+const MAX_GROUP_SEARCH: u32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalletError {
+    Mnemonic(bip39::Error),
+    InvalidPath(String),
+    // the one in ~2^127 chance that a BIP 32 child key is invalid
+    InvalidChild(u32),
+    Group(GroupError)
+}
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = HmacSha512::new_from_slice(key).expect("hmac takes keys of any length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    let output = mac.finalize().into_bytes();
+    let (left, right) = output.split_at(32);
+    (left.try_into().unwrap(), right.try_into().unwrap())
+}
+
+// a node in the BIP 32 tree
+#[derive(Clone)]
+pub struct ExtendedKey {
+    key_pair: KeyPair,
+    chain_code: [u8; 32]
+}
+
+impl ExtendedKey {
+
+    pub fn master(seed: &[u8]) -> Result<Self, WalletError> {
+        let (secret, chain_code) = hmac_sha512(b"Bitcoin seed", &[seed]);
+        let key_pair = KeyPair::from_secret_bytes(&secret).map_err(|_| WalletError::InvalidChild(0))?;
+        Ok(ExtendedKey { key_pair, chain_code })
+    }
+
+    pub fn key_pair(&self) -> &KeyPair {
+        &self.key_pair
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    // indices from HARDENED up are hardened
+    pub fn child(&self, index: u32) -> Result<Self, WalletError> {
+        let secret = self.key_pair.secret_bytes();
+        let (tweak, chain_code) = if index >= HARDENED {
+            hmac_sha512(&self.chain_code, &[&[0], &secret, &index.to_be_bytes()])
+        } else {
+            hmac_sha512(&self.chain_code, &[self.key_pair.public_key().as_bytes(), &index.to_be_bytes()])
+        };
+
+        let tweak = Option::<Scalar>::from(Scalar::from_repr(tweak.into())).ok_or(WalletError::InvalidChild(index))?;
+        let parent = Option::<Scalar>::from(Scalar::from_repr(secret.into())).ok_or(WalletError::InvalidChild(index))?;
+        let child: [u8; SECRET_KEY_LENGTH] = (tweak + parent).to_repr().into();
+        // a zero key is rejected here
+        let key_pair = KeyPair::from_secret_bytes(&child).map_err(|_| WalletError::InvalidChild(index))?;
+        Ok(ExtendedKey { key_pair, chain_code })
+    }
+
+    // "m/44'/1234'/0'/0/0", with ' or h marking hardened steps
+    pub fn derive(&self, path: &str) -> Result<Self, WalletError> {
+        let invalid = || WalletError::InvalidPath(path.to_string());
+        let mut steps = path.split('/');
+        if steps.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        steps.try_fold(self.clone(), |key, step| {
+            let (number, hardened) = match step.strip_suffix('\'').or_else(|| step.strip_suffix('h')) {
+                Some(number) => (number, true),
+                None => (step, false)
+            };
+            let index: u32 = number.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid());
+            }
+            key.child(if hardened { index | HARDENED } else { index })
+        })
+    }
+}
+
+// shows where the key sits, never what it is
+impl std::fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtendedKey").field("public_key", &self.key_pair.public_key()).finish_non_exhaustive()
+    }
+}
+
+pub fn alephium_path(index: u32) -> String {
+    format!("m/44'/{}'/0'/0/{}", ALEPHIUM_COIN_TYPE, index)
+}
+
+#[derive(Clone)]
+pub struct Wallet {
+    mnemonic: bip39::Mnemonic,
+    // m/44'/1234'/0'/0, the parent of every address key
+    addresses: ExtendedKey
+}
+
+impl Wallet {
+
+    // 12, 15, 18, 21 or 24 words
+    pub fn generate(word_count: usize, passphrase: &str) -> Result<Self, WalletError> {
+        let mut entropy = vec![0u8; word_count * 4 / 3];
+        OsRng.fill_bytes(&mut entropy);
+        let mnemonic = bip39::Mnemonic::from_entropy(&entropy).map_err(WalletError::Mnemonic)?;
+        Wallet::from_mnemonic_words(mnemonic, passphrase)
+    }
+
+    // checks the words and the checksum
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self, WalletError> {
+        let mnemonic = bip39::Mnemonic::parse(phrase).map_err(WalletError::Mnemonic)?;
+        Wallet::from_mnemonic_words(mnemonic, passphrase)
+    }
+
+    fn from_mnemonic_words(mnemonic: bip39::Mnemonic, passphrase: &str) -> Result<Self, WalletError> {
+        let master = ExtendedKey::master(&mnemonic.to_seed(passphrase))?;
+        let path = format!("m/44'/{}'/0'/0", ALEPHIUM_COIN_TYPE);
+        let addresses = master.derive(&path)?;
+        Ok(Wallet { mnemonic, addresses })
+    }
+
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    // the key at m/44'/1234'/0'/0/index
+    pub fn key_pair(&self, index: u32) -> Result<KeyPair, WalletError> {
+        if index >= HARDENED {
+            return Err(WalletError::InvalidPath(alephium_path(index)));
+        }
+        Ok(self.addresses.child(index)?.key_pair)
+    }
+
+    pub fn account(&self, index: u32, alph_amount: u128, tokens: Vec<Token>) -> Result<Account, WalletError> {
+        Ok(self.key_pair(index)?.account(alph_amount, tokens))
+    }
+
+    // the first index from `start` on whose address is in `group`
+    pub fn first_in_group(&self, config: &GroupConfig, group: u32, start: u32) -> Result<(u32, KeyPair), WalletError> {
+        if group >= config.groups() {
+            return Err(WalletError::Group(GroupError::InvalidGroup(group)));
+        }
+
+        for index in start..start.saturating_add(MAX_GROUP_SEARCH).min(HARDENED) {
+            let key_pair = match self.key_pair(index) {
+                Ok(key_pair) => key_pair,
+                // BIP 32 says to skip invalid children
+                Err(WalletError::InvalidChild(_)) => continue,
+                Err(e) => return Err(e)
+            };
+            if config.group_of(&key_pair.address()).map_err(WalletError::Group)? == group {
+                return Ok((index, key_pair));
+            }
+        }
+        Err(WalletError::Group(GroupError::Exhausted))
+    }
+}
+
+impl std::fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wallet").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_bip32_vector_one() {
+        // test vector 1 from BIP 32
+        let master = ExtendedKey::master(&hex::decode("000102030405060708090a0b0c0d0e0f").unwrap()).unwrap();
+        assert_eq!(hex::encode(master.key_pair().secret_bytes()), "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35");
+        assert_eq!(hex::encode(master.chain_code()), "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508");
+
+        let child = master.derive("m/0'").unwrap();
+        assert_eq!(hex::encode(child.key_pair().secret_bytes()), "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea");
+        assert_eq!(hex::encode(child.chain_code()), "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141");
+
+        // a normal step, through the public key
+        let grandchild = master.derive("m/0h/1").unwrap();
+        assert_eq!(hex::encode(grandchild.key_pair().secret_bytes()), "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368");
+    }
+
+    #[test]
+    fn test_bip39_seed_vector() {
+        // from the BIP 39 reference vectors, passphrase "TREZOR"
+        let mnemonic = bip39::Mnemonic::parse(ABANDON).unwrap();
+        assert_eq!(
+            hex::encode(mnemonic.to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+    }
+
+    #[test]
+    fn test_restore_is_deterministic() {
+        let wallet = Wallet::from_mnemonic(ABANDON, "").unwrap();
+        let again = Wallet::from_mnemonic(ABANDON, "").unwrap();
+        assert_eq!(wallet.key_pair(0).unwrap().public_key(), again.key_pair(0).unwrap().public_key());
+        assert_ne!(wallet.key_pair(0).unwrap().public_key(), wallet.key_pair(1).unwrap().public_key());
+
+        // the passphrase is part of the seed
+        let other = Wallet::from_mnemonic(ABANDON, "secret").unwrap();
+        assert_ne!(other.key_pair(0).unwrap().public_key(), wallet.key_pair(0).unwrap().public_key());
+
+        // the same key the full path gives
+        let master = ExtendedKey::master(&bip39::Mnemonic::parse(ABANDON).unwrap().to_seed("")).unwrap();
+        let key = master.derive(&alephium_path(3)).unwrap();
+        assert_eq!(key.key_pair().public_key(), wallet.key_pair(3).unwrap().public_key());
+    }
+
+    #[test]
+    fn test_generate() {
+        let wallet = Wallet::generate(24, "").unwrap();
+        assert_eq!(wallet.mnemonic().split(' ').count(), 24);
+        let restored = Wallet::from_mnemonic(&wallet.mnemonic(), "").unwrap();
+        assert_eq!(restored.key_pair(0).unwrap().public_key(), wallet.key_pair(0).unwrap().public_key());
+
+        assert!(matches!(Wallet::generate(13, ""), Err(WalletError::Mnemonic(_))));
+    }
+
+    #[test]
+    fn test_invalid_mnemonics() {
+        // "about" is what makes the checksum work
+        let bad_checksum = ABANDON.replace("about", "abandon");
+        assert!(matches!(Wallet::from_mnemonic(&bad_checksum, ""), Err(WalletError::Mnemonic(_))));
+        assert!(matches!(Wallet::from_mnemonic("not a real mnemonic", ""), Err(WalletError::Mnemonic(_))));
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        let master = ExtendedKey::master(&[1; 64]).unwrap();
+        for path in ["", "44'/0", "m/x", "m/2147483648", "m/1''"] {
+            assert_eq!(master.derive(path).unwrap_err(), WalletError::InvalidPath(path.to_string()), "{}", path);
+        }
+        assert!(master.derive("m").is_ok());
+    }
+
+    #[test]
+    fn test_first_in_group() {
+        let wallet = Wallet::from_mnemonic(ABANDON, "").unwrap();
+        let config = GroupConfig::default();
+
+        for group in 0..4 {
+            let (index, key_pair) = wallet.first_in_group(&config, group, 0).unwrap();
+            assert_eq!(config.group_of(&key_pair.address()).unwrap(), group);
+            // nothing before it was in the group
+            for earlier in 0..index {
+                assert_ne!(config.group_of(&wallet.key_pair(earlier).unwrap().address()).unwrap(), group);
+            }
+
+            let account = wallet.account(index, 0, vec![]).unwrap();
+            assert_eq!(account.group(&config), Some(group));
+        }
+
+        assert_eq!(wallet.first_in_group(&config, 4, 0).unwrap_err(), WalletError::Group(GroupError::InvalidGroup(4)));
+    }
+}